@@ -1,78 +1,515 @@
-extern crate proc_macro;
-extern crate backside;
+//! `backside-cli`, the library from the command line
+
+use std::collections::HashMap;
+use std::fs;
+use std::io::{self, Read, Write};
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
 
 use backside::*;
+use backside::backside_parser::{self, pull::{Item, Parser}};
+use backside::resample::AspectRatio;
+use backside::timing::{self, Snap, Timecodes};
+
+const USAGE: &str = "\
+Usage: backside-cli <command> [options] [file]
+
+Reads the script from `file`, or stdin if it's missing or `-`.
+
+Commands:
+  check [file...]               Report every problem in the scripts
+  fmt                           Rewrite the script in canonical form
+  info                          Show what the script is about
+  shift <offset>                Move every event by `offset`, eg. `-1.5s`,
+                                `250ms` or `0:01:02.50`
+  convert                       Change resolution, frame rate or colours:
+      --res <w>x<h>               resample to this resolution
+      --ar <mode>                 on aspect ratio changes: `stretch`,
+                                  `add-borders` or `remove-borders`
+      --fps <from>:<to>           retime, eg. `23.976:25`
+      --matrix <matrix>           remap colours, eg. `TV.709`
+  snap                          Snap events to scene changes:
+      --keyframes <file>          keyframes or XviD / SCXviD log
+      --timecodes <file>          v1 / v2 timecodes of the video, or
+      --fps <fps>                 its constant frame rate
+      --link <time>               join lines this close, `300ms` by
+                                  default, `off` not to
+      --link-bias <bias>          where joined lines meet, from `0`
+                                  (end of the first) to `1`, `0.5`
+                                  by default
+      --starts-before <frames>    how far starts move to keyframes
+      --starts-after <frames>     after them, and ends, `5`, `4`, `5`
+      --ends-before <frames>      and `5` frames by default
+      --ends-after <frames>
+  extract-fonts                 Write the embedded fonts to the `-o`
+                                directory, `.` by default
+  render                        Draw a frame of the script as a PAM image:
+      --time <time>               when, eg. `1.5s` or `0:01:02.50`
+      --size <w>x<h>              frame size, `PlayResX` by `PlayResY`
+                                  by default
+      --font <file>               font to use besides the embedded ones
+      --fonts-dir <dir>           directory to look for fonts in
+      --shaping <mode>            `complex` OpenType shaping, the
+                                  default, or VSFilter's `simple` one
+
+Options:
+  -o, --output <path>           Write to `path` rather than stdout
+      --lenient                 Get over errors like libass does, and
+                                report them on stderr
+  -h, --help                    Show this
+
+Exit codes: 0 on success, 1 if the script is invalid, 2 on bad usage,
+3 if a file can't be read or written.";
+
+/// Why the command failed, which decides the exit code
+enum Fail {
+    /// The script has errors
+    Invalid,
+    Usage(String),
+    Io(String, io::Error),
+}
+
+impl Fail {
+    fn report(self) -> ExitCode {
+        match self {
+            Fail::Invalid      => ExitCode::from(1),
+            Fail::Usage(msg)   => {
+                eprintln!("backside-cli: {}\n\nSee `backside-cli --help`.", msg);
+                ExitCode::from(2)
+            },
+            Fail::Io(path, e) => {
+                eprintln!("backside-cli: {}: {}", path, e);
+                ExitCode::from(3)
+            },
+        }
+    }
+}
+
+type Res<T> = std::result::Result<T, Fail>;
+
+fn usage<T>(msg: impl Into<String>) -> Res<T> {
+    Err(Fail::Usage(msg.into()))
+}
+
+/// Options taking a value
+const VALUED: &[&str] = &[
+    "--output", "--res", "--ar", "--fps", "--matrix", "--keyframes", "--timecodes", "--link", "--link-bias",
+    "--starts-before", "--starts-after", "--ends-before", "--ends-after", "--time", "--size", "--font", "--fonts-dir", "--shaping",
+];
+
+/// Command line, minus the command
+struct Args {
+    files: Vec<String>,
+    opts: HashMap<&'static str, String>,
+    lenient: bool,
+}
+
+impl Args {
+    fn parse(mut args: impl Iterator<Item = String>) -> Res<Self> {
+        let mut a = Args {
+            files: Vec::new(),
+            opts: HashMap::new(),
+            lenient: false,
+        };
+        while let Some(arg) = args.next() {
+            // Negative offsets aren't options
+            let is_opt = arg.len() > 1 && arg.starts_with('-') && !arg[1..].starts_with(|c: char| c.is_ascii_digit());
+            if !is_opt {
+                a.files.push(arg);
+                continue;
+            }
+            let name = match arg.as_str() {
+                "--lenient" => {
+                    a.lenient = true;
+                    continue;
+                },
+                "-o" => "--output",
+                _ => match VALUED.iter().find(|&&o| o == arg) {
+                    Some(o) => o,
+                    None    => return usage(format!("unknown option `{}`", arg)),
+                },
+            };
+            a.opts.insert(name, value(&arg, args.next())?);
+        }
+        Ok(a)
+    }
+
+    fn opt(&self, name: &str) -> Option<&str> {
+        self.opts.get(name).map(String::as_str)
+    }
+
+    /// The only input file, `None` for stdin
+    fn input(&self) -> Res<Option<&str>> {
+        match self.files.as_slice() {
+            []    => Ok(None),
+            [f]   => Ok(Some(f.as_str()).filter(|&f| f != "-")),
+            [_, extra, ..] => usage(format!("unexpected argument `{}`", extra)),
+        }
+    }
+
+    /// Parse the input script, reporting the errors on stderr
+    fn script(&self) -> Res<Script> {
+        let path = self.input()?;
+        let text = read(path)?;
+        let name = path.unwrap_or("<stdin>");
+        if self.lenient {
+            let (script, warnings) = Script::parse_lenient(&text);
+            for w in warnings {
+                eprintln!("{}: warning: {}", name, w);
+            }
+            return Ok(script);
+        }
+        Script::parse(&text).map_err(|e| {
+            eprintln!("{}: {}", name, e);
+            Fail::Invalid
+        })
+    }
+
+    /// Write `data` out to `-o` or stdout
+    fn output(&self, data: impl AsRef<[u8]>) -> Res<()> {
+        match self.opt("--output").filter(|&o| o != "-") {
+            Some(path) => fs::write(path, data).map_err(|e| Fail::Io(path.into(), e)),
+            None       => io::stdout().write_all(data.as_ref()).map_err(|e| Fail::Io("<stdout>".into(), e)),
+        }
+    }
+}
+
+fn value(opt: &str, v: Option<String>) -> Res<String> {
+    v.ok_or_else(|| Fail::Usage(format!("`{}` needs a value", opt)))
+}
+
+/// Whole file, or stdin without one
+fn read(path: Option<&str>) -> Res<String> {
+    let mut s = String::new();
+    let res = match path {
+        Some(p) => fs::File::open(p).and_then(|mut f| f.read_to_string(&mut s)),
+        None    => io::stdin().read_to_string(&mut s),
+    };
+    res.map(|_| s).map_err(|e| Fail::Io(path.unwrap_or("<stdin>").into(), e))
+}
+
+/// `-1.5s`, `250ms`, `0:01:02.50`
+fn parse_offset(s: &str) -> Option<Timestamp> {
+    let (neg, t) = match s.strip_prefix('-') {
+        Some(t) => (true, t),
+        None    => (false, s.strip_prefix('+').unwrap_or(s)),
+    };
+    let t = match t {
+        _ if t.contains(':')   => Timestamp::parse(t)?,
+        _ if t.ends_with("ms") => Timestamp::from_ms(t[..t.len() - 2].parse().ok()?),
+        _ if t.ends_with('s')  => Timestamp::from_ms((t[..t.len() - 1].parse::<f64>().ok()? * 1000.0).round() as i64),
+        _ => return None,
+    };
+    Some(if neg { Timestamp(-t.0) } else { t })
+}
+
+/// `1920x1080`
+fn parse_res(s: &str) -> Res<(i32, i32)> {
+    s.split_once('x')
+        .and_then(|(w, h)| Some((w.parse().ok()?, h.parse().ok()?)))
+        .filter(|&(w, h): &(i32, i32)| w > 0 && h > 0)
+        .ok_or_else(|| Fail::Usage(format!("invalid resolution `{}`", s)))
+}
+
+/// `24000/1001`, `25`, or `23.976` and friends for their NTSC fractions
+fn parse_fps(s: &str) -> Option<Fps> {
+    match s.split_once('/') {
+        Some((n, d)) => Fps::new(n.parse().ok()?, d.parse().ok()?),
+        None => match s {
+            "23.976" | "23.98" => Fps::new(24000, 1001),
+            "29.97"            => Fps::new(30000, 1001),
+            "59.94"            => Fps::new(60000, 1001),
+            _ => Fps::new((s.parse::<f64>().ok()? * 1000.0).round() as u32, 1000),
+        },
+    }
+}
+
+fn check(args: &Args) -> Res<()> {
+    let files: Vec<Option<&str>> = match args.files.as_slice() {
+        []    => vec![None],
+        files => files.iter().map(|f| Some(f.as_str()).filter(|&f| f != "-")).collect(),
+    };
+    let mut ok = true;
+    for path in files {
+        let text = read(path)?;
+        let errors = problems(&text);
+        for e in &errors {
+            println!("{}: {}", path.unwrap_or("<stdin>"), e);
+        }
+        ok &= errors.is_empty();
+    }
+    if ok { Ok(()) } else { Err(Fail::Invalid) }
+}
+
+/// What `Script::parse_lenient` gets over in `text`, and what gets dropped
+/// from the text of its events, in file order
+fn problems(text: &str) -> Vec<Error> {
+    let lines: Vec<&str> = text.lines().collect();
+    let mut errors = Vec::new();
+    let mut section = None;
+    let mut p = Parser::lenient(text.as_bytes());
+    while let Some(item) = p.next() {
+        errors.append(&mut p.warnings);
+        let event = match item {
+            Ok(Item::SectionHeader(h)) => {
+                section = Some(h);
+                continue;
+            },
+            Ok(Item::Event(e)) => e,
+            Ok(_)  => continue,
+            Err(e) => {
+                errors.push(e);
+                continue;
+            },
+        };
+
+        let mut warnings = Vec::new();
+        backside_parser::parse_dialogue_lenient(&event.text, &mut warnings);
+        let line = lines.get(p.line - 1).copied().unwrap_or_default();
+        // Spans are within the text, which is the end of the line unless the
+        // `Format:` has it elsewhere
+        let at = line.strip_suffix(event.text.as_str()).map(str::len);
+        for w in warnings {
+            let span = w.span.clone().zip(at).map(|(s, at)| s.start + at..s.end + at);
+            errors.push(Error { span, ..w }.locate(p.line, section, line));
+        }
+    }
+    errors
+}
+
+fn fmt(args: &Args) -> Res<()> {
+    let mut script = args.script()?;
+    script.layout = Default::default();
+    args.output(script.to_string())
+}
+
+fn info(args: &Args) -> Res<()> {
+    let script = args.script()?;
+    let (x, y) = script.info.play_res();
+    let mut out = String::new();
+    let mut line = |k: &str, v: &dyn std::fmt::Display| out += &format!("{:<14}{}\n", k, v);
+
+    line("Title:", &script.info.title().unwrap_or("-"));
+    line("Script type:", &script.info.script_type().map_or("-", |t| t.as_str()));
+    line("Resolution:", &format!("{}x{}", x, y));
+    line("YCbCr matrix:", &script.info.ycbcr_matrix().map_or("-", |m| m.as_str()));
+    line("Styles:", &script.styles.len());
+
+    let dialogue = script.events.iter().filter(|e| e.kind == EventKind::Dialogue).count();
+    line("Events:", &format!("{} ({} dialogue)", script.events.len(), dialogue));
+    let start = script.events.iter().map(|e| e.start).min();
+    let end = script.events.iter().map(|e| e.end).max();
+    if let (Some(start), Some(end)) = (start, end) {
+        line("Time span:", &format!("{} - {}", start, end));
+    }
+
+    let list = |names: Vec<&str>| match names.is_empty() {
+        true  => String::from("0"),
+        false => format!("{} ({})", names.len(), names.join(", ")),
+    };
+    line("Fonts:", &list(script.font_names().collect()));
+    line("Graphics:", &list(script.graphic_names().collect()));
+    args.output(&out)
+}
+
+fn shift(mut args: Args) -> Res<()> {
+    if args.files.is_empty() {
+        return usage("`shift` needs an offset");
+    }
+    let offset = args.files.remove(0);
+    let by = parse_offset(&offset).ok_or_else(|| Fail::Usage(format!("invalid offset `{}`", offset)))?;
+    let mut script = args.script()?;
+    script.shift(by);
+    args.output(script.to_string())
+}
+
+fn convert(args: &Args) -> Res<()> {
+    let res = args.opt("--res").map(parse_res).transpose()?;
+    let ar = match args.opt("--ar") {
+        None | Some("stretch") => AspectRatio::Stretch,
+        Some("add-borders")    => AspectRatio::AddBorders,
+        Some("remove-borders") => AspectRatio::RemoveBorders,
+        Some(ar) => return usage(format!("invalid aspect ratio mode `{}`", ar)),
+    };
+    let fps = args.opt("--fps").map(|f| {
+        f.split_once(':')
+            .and_then(|(from, to)| Some((parse_fps(from)?, parse_fps(to)?)))
+            .ok_or_else(|| Fail::Usage(format!("invalid frame rates `{}`", f)))
+    }).transpose()?;
+    let matrix = args.opt("--matrix").map(|m| {
+        YCbCrMatrix::parse(m).ok_or_else(|| Fail::Usage(format!("invalid matrix `{}`", m)))
+    }).transpose()?;
+
+    let mut script = args.script()?;
+    if let Some(to) = res {
+        script.resample(to, ar);
+    }
+    if let Some((from, to)) = fps {
+        script.change_fps(from, to);
+    }
+    if let Some(m) = matrix {
+        script.convert_colours(m);
+    }
+    args.output(script.to_string())
+}
+
+fn snap(args: &Args) -> Res<()> {
+    let Some(kf) = args.opt("--keyframes") else {
+        return usage("`snap` needs `--keyframes`");
+    };
+    let keyframes = timing::parse_keyframes(&read(Some(kf))?).map_err(|e| {
+        eprintln!("{}: {}", kf, e);
+        Fail::Invalid
+    })?;
+    let tc = match (args.opt("--timecodes"), args.opt("--fps")) {
+        (Some(tc), _) => Timecodes::parse(&read(Some(tc))?).map_err(|e| {
+            eprintln!("{}: {}", tc, e);
+            Fail::Invalid
+        })?,
+        (None, Some(f)) => Timecodes::cfr(parse_fps(f).ok_or_else(|| Fail::Usage(format!("invalid frame rate `{}`", f)))?),
+        (None, None) => return usage("`snap` needs `--timecodes` or `--fps`"),
+    };
+
+    let mut opts = Snap::default();
+    if let Some(l) = args.opt("--link") {
+        opts.link = match l {
+            "off" => None,
+            _ => Some(parse_offset(l).filter(|t| t.0 >= 0).ok_or_else(|| Fail::Usage(format!("invalid link distance `{}`", l)))?),
+        };
+    }
+    if let Some(b) = args.opt("--link-bias") {
+        opts.link_bias = b.parse().ok()
+            .filter(|b| (0.0..=1.0).contains(b))
+            .ok_or_else(|| Fail::Usage(format!("invalid link bias `{}`", b)))?;
+    }
+    for (name, frames) in [
+        ("--starts-before", &mut opts.starts_before),
+        ("--starts-after", &mut opts.starts_after),
+        ("--ends-before", &mut opts.ends_before),
+        ("--ends-after", &mut opts.ends_after),
+    ] {
+        if let Some(n) = args.opt(name) {
+            *frames = n.parse().ok()
+                .filter(|&n: &i64| n >= 0)
+                .ok_or_else(|| Fail::Usage(format!("invalid frame count `{}`", n)))?;
+        }
+    }
+
+    let mut script = args.script()?;
+    script.snap(&opts, &keyframes, &tc);
+    args.output(script.to_string())
+}
+
+fn extract_fonts(args: &Args) -> Res<()> {
+    let script = args.script()?;
+    let dir = Path::new(args.opt("--output").unwrap_or("."));
+    for name in script.font_names() {
+        let Some(Ok(data)) = script.extract_font(name) else {
+            eprintln!("backside-cli: can't decode font `{}`", name);
+            return Err(Fail::Invalid);
+        };
+        // Only the file name, fonts shouldn't end up outside of `dir`
+        let path: PathBuf = dir.join(Path::new(name).file_name().unwrap_or(name.as_ref()));
+        fs::write(&path, data).map_err(|e| Fail::Io(path.display().to_string(), e))?;
+        eprintln!("{}", path.display());
+    }
+    Ok(())
+}
+
+#[cfg(feature = "render")]
+fn render(args: &Args) -> Res<()> {
+    use backside::backside_renderer::{composite, Renderer, Shaping};
+
+    let Some(t) = args.opt("--time") else {
+        return usage("`render` needs `--time`");
+    };
+    let t = parse_offset(t).ok_or_else(|| Fail::Usage(format!("invalid time `{}`", t)))?;
+    let script = args.script()?;
+    let (w, h) = match args.opt("--size") {
+        Some(s) => parse_res(s)?,
+        None    => script.info.play_res(),
+    };
+
+    let mut r = Renderer::new();
+    match args.opt("--shaping") {
+        None | Some("complex") => {},
+        Some("simple") => r.set_shaping(Shaping::Simple),
+        Some(s) => return usage(format!("unknown shaping `{}`", s)),
+    }
+    let fonts = r.fonts_mut();
+    fonts.add_script_fonts(&script);
+    if let Some(f) = args.opt("--font") {
+        let data = fs::read(f).map_err(|e| Fail::Io(f.into(), e))?;
+        if fonts.add_font(data) == 0 {
+            eprintln!("backside-cli: {}: not a font", f);
+            return Err(Fail::Invalid);
+        }
+    }
+    if let Some(dir) = args.opt("--fonts-dir") {
+        scan_fonts(fonts, dir)?;
+    }
+
+    let size = (w as u32, h as u32);
+    let images = r.render(&script, size, t);
+    for f in r.fallbacks() {
+        let missing = match f.character {
+            Some(c) => format!("`{}` (U+{:04X}) not in font `{}`", c, c as u32, f.family),
+            None    => format!("font `{}` not found", f.family),
+        };
+        match &f.used {
+            Some(used) => eprintln!("backside-cli: {}, used `{}`", missing, used),
+            None       => eprintln!("backside-cli: {}", missing),
+        }
+    }
+    let mut pam = format!("P7\nWIDTH {}\nHEIGHT {}\nDEPTH 4\nMAXVAL 255\nTUPLTYPE RGB_ALPHA\nENDHDR\n", w, h).into_bytes();
+    pam.extend(composite(&images, size));
+    args.output(pam)
+}
+
+#[cfg(feature = "system-fonts")]
+fn scan_fonts(fonts: &mut backside::backside_renderer::FontDatabase, dir: &str) -> Res<()> {
+    let mut errors = Vec::new();
+    fonts.scan(dir, &mut errors).map_err(|e| Fail::Io(dir.into(), e))?;
+    for (path, e) in errors {
+        eprintln!("backside-cli: {}: {}", path.display(), e);
+    }
+    Ok(())
+}
+
+#[cfg(all(feature = "render", not(feature = "system-fonts")))]
+fn scan_fonts(_: &mut backside::backside_renderer::FontDatabase, _: &str) -> Res<()> {
+    usage("this build of `backside-cli` can't scan font directories, it needs the `system-fonts` feature")
+}
+
+#[cfg(not(feature = "render"))]
+fn render(_: &Args) -> Res<()> {
+    usage("this build of `backside-cli` can't render, it needs the `render` feature")
+}
+
+fn main() -> ExitCode {
+    let mut args = std::env::args().skip(1);
+    let Some(cmd) = args.next() else {
+        eprintln!("{}", USAGE);
+        return ExitCode::from(2);
+    };
+    if matches!(cmd.as_str(), "-h" | "--help" | "help") || std::env::args().any(|a| a == "-h" || a == "--help") {
+        println!("{}", USAGE);
+        return ExitCode::SUCCESS;
+    }
 
-// enum Section {
-//     Info,
-// }
-
-// impl Section {
-//     fn header(&self) -> &str {
-//         match self {
-//             Section::Info => "Script Info",
-//         }
-//     }
-// }
-
-// TODO: use a range instead of "*"
-//const STYLE_RE: &str = r"([^:]*): (.*)";
-
-// enum Section {
-//     Info,
-//     Styles,
-//     Fonts,
-//     Events,
-// }
-// impl std::fmt::Display for Section {
-//     fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
-//         write!(f, "{}",
-//             match self {
-//                 Section::Info   => "Info",
-//                 Section::Styles => "Styles",
-//                 Section::Fonts  => "Fonts",
-//                 Section::Events => "Events",
-//             }
-//         )
-//     }
-// }
-
-// struct Subtitles {
-//     pub styles: Vec<Style>,
-// }
-
-fn main() {
-    let input = std::fs::read_to_string("test.ass").unwrap();
-
-    //loop {
-        print!("{}", backside_parser::parse_sections(input.as_str()));
-    //    std::thread::sleep_ms(100);
-    //}
-    //println!("{}", String::from_utf8(uuenc::uuencode(Bytes::from("This is a test.")).unwrap()).unwrap());
-    // let lines: Vec<&str> = input.lines().collect();
-    // let format = style::parse_format(lines.clone());
-    //let styles = style::parse_style(lines.clone(), format);
-
-//     for style in styles {
-//         println!("
-// {}
-// '- Font: {} @ {} px", style.name, style.font_name, style.font_size)
-//     }
-
-    // r"\[(.*)\]"
-    // let re = Regex::new(STYLE_RE).unwrap();
-
-    // let caps = re.captures_iter(input.as_str());
-    // for cap in caps {
-    //     let line_type = cap.get(1).unwrap().as_str();
-    //     let fields = cap.get(2).unwrap().as_str();
-
-    //     if line_type != "Dialogue" {
-    //         println!("{}", line_type);
-    //         for field in fields.split(",") {
-    //             println!("'-> {}", field);
-    //         }
-    //     }
-    // }
-   // Ok(())
+    let res = Args::parse(args).and_then(|args| match cmd.as_str() {
+        "check"         => check(&args),
+        "fmt"           => fmt(&args),
+        "info"          => info(&args),
+        "shift"         => shift(args),
+        "convert"       => convert(&args),
+        "snap"          => snap(&args),
+        "extract-fonts" => extract_fonts(&args),
+        "render"        => render(&args),
+        _ => usage(format!("unknown command `{}`", cmd)),
+    });
+    match res {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => e.report(),
+    }
 }
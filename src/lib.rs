@@ -13,47 +13,420 @@
 
 extern crate alloc;
 
-extern crate backside_parser;
+#[cfg(feature = "read")]
+pub use backside_parser;
+#[cfg(feature = "write")]
+pub use backside_writer;
+#[cfg(feature = "render")]
+pub use backside_renderer;
 
-pub(crate) use alloc::{vec::Vec, string::{String, ToString}};
+#[cfg(feature = "read")]
+use alloc::string::String;
+#[cfg(feature = "read")]
+use alloc::vec::Vec;
+use core::ops::{Deref, DerefMut};
 
-mod error;
+pub use backside_types::{
+    Error, ErrorKind, Result,
+    Style, Event, EventKind, Attachment, ExtraSection, Colour, Timestamp, Fps,
+    ScriptInfo, InfoEntry, ScriptType, WrapStyle, YCbCrMatrix, Collisions,
+    OverrideCode, Token,
+    resample, timing, uuenc,
+};
+#[cfg(feature = "read")]
+use backside_types::{numpad, resample::{AspectRatio, Resampler}};
 
-pub use error::{Error, Result};
-//pub use style::Style;
+/// The parsed document a [`Script`] holds
+pub use backside_types::Script as Document;
 
+/// An ASS/SSA script
+///
+/// Derefs to the parsed [`Document`].
+#[derive(Default)]
 pub struct Script {
-    buf: String,
-    info: Vec<(String, String)>,
-  //  styles: Vec<Style>,
+    doc: Document,
 }
 impl Script {
-    pub fn from_str(s: &str) -> Self {
-        Self {
-            buf: String::from(s),
-            info: Vec::new(),
- //           styles: Vec::new(),
+    /// Parse a whole script at once
+    ///
+    /// For scripts too big to hold in memory, see
+    /// `backside_parser::pull` instead.
+    #[cfg(feature = "oneshot")]
+    pub fn parse(s: &str) -> Result<Self> {
+        backside_parser::parse_sections(s).map(Self::from)
+    }
+
+    /// Parse a whole script at once, getting over errors the way libass does
+    ///
+    /// Also gives what it got over, see
+    /// [`parse_sections_lenient`](backside_parser::parse_sections_lenient).
+    #[cfg(feature = "oneshot")]
+    pub fn parse_lenient(s: &str) -> (Self, Vec<Error>) {
+        let mut warnings = Vec::new();
+        let doc = backside_parser::parse_sections_lenient(s, &mut warnings);
+        (doc.into(), warnings)
+    }
+
+    /// Remap every colour, in styles and `\c` tags, from the matrix of the
+    /// `YCbCr Matrix` header to `to`, and update the header
+    ///
+    /// Without the header, colours are taken to be for BT.601 TV range, as
+    /// VSFilter does. Tags that don't parse are left alone.
+    #[cfg(feature = "read")]
+    pub fn convert_colours(&mut self, to: YCbCrMatrix) {
+        let from = self.info.ycbcr_matrix().unwrap_or(YCbCrMatrix::Bt601Tv);
+
+        for s in &mut self.doc.styles {
+            for c in [&mut s.primary_color, &mut s.secondary_color, &mut s.outline_color, &mut s.back_color] {
+                *c = c.convert(from, to);
+            }
+        }
+        for e in &mut self.doc.events {
+            map_codes(&mut e.text, |c| if let OverrideCode::Color(_, Some(c)) = c {
+                *c = c.convert(from, to);
+            });
+        }
+
+        self.doc.info.set_ycbcr_matrix(to);
+    }
+
+    /// Stretch every event by `num / den`, tags included
+    ///
+    /// Times of `\k`, `\t`, `\move`, `\fad` and `\fade` get scaled along
+    /// with the start and end of the events. Nothing changes if `den` is
+    /// zero.
+    #[cfg(feature = "read")]
+    pub fn scale(&mut self, num: i64, den: i64) {
+        self.scale_where(num, den, |_| true);
+    }
+
+    /// Stretch the events `filter` picks by `num / den`, tags included
+    ///
+    /// Tags that don't parse are kept as they are.
+    #[cfg(feature = "read")]
+    pub fn scale_where(&mut self, num: i64, den: i64, mut filter: impl FnMut(&Event) -> bool) {
+        for e in self.doc.events.iter_mut().filter(|e| filter(e)) {
+            e.scale(num, den);
+            map_codes(&mut e.text, |c| c.scale_times(num, den));
         }
     }
-    pub fn init(&self) {
-        backside_parser::parse_sections(self.buf.as_str());
+
+    /// Retime a script made for a video at `from` to one at `to`, eg. for a
+    /// PAL speedup from 24000/1001 to 25 fps
+    #[cfg(feature = "read")]
+    pub fn change_fps(&mut self, from: Fps, to: Fps) {
+        self.scale(from.num() as i64 * to.den() as i64, from.den() as i64 * to.num() as i64);
+    }
+
+    /// Change the resolution of the script to `to`, `(width, height)`
+    ///
+    /// Rewrites `PlayResX` and `PlayResY`, and scales the sizes and margins
+    /// of the styles and events along with every position, size and drawing
+    /// in the tags, see [`resample`]. Tags that don't parse are kept as they
+    /// are.
+    #[cfg(feature = "read")]
+    pub fn resample(&mut self, to: (i32, i32), ar: AspectRatio) {
+        let r = Resampler::new(self.info.play_res(), to, ar);
+        let ssa = self.info.script_type() == Some(ScriptType::V4);
+        let default = Style::default();
+
+        let doc = &mut self.doc;
+        for e in &mut doc.events {
+            // `MarginV` is from the edge the line is aligned to, which the
+            // first `\a` or `\an` decides over the style
+            let mut align = None;
+            map_text(&mut e.text, |c| {
+                match *c {
+                    OverrideCode::Alignment(a) if align.is_none() => align = Some(a.map(numpad)),
+                    OverrideCode::AlignmentNumpad(a) if align.is_none() => align = Some(a.map(|a| a.clamp(1, 9))),
+                    _ => {},
+                }
+                c.resample(&r)
+            }, |d| Some(r.drawing(d)));
+            let style = doc.styles.iter().find(|s| s.name == e.style).unwrap_or(&default);
+            e.resample(&r, align.flatten().unwrap_or_else(|| style.numpad_alignment(ssa)));
+        }
+        for s in &mut doc.styles {
+            s.resample(&r, ssa);
+        }
+
+        self.doc.info.set_play_res(to.0, to.1);
+    }
+
+    /// Write the script out as ASS/SSA text
+    #[cfg(feature = "write")]
+    pub fn save<W: core::fmt::Write>(&self, w: &mut W) -> core::fmt::Result {
+        backside_writer::write_script(w, &self.doc)
     }
 }
 
-#[macro_export]
-macro_rules! init_helper {
-    () => {
-        macro_rules! parse {
-            () => {
-                match v.parse::<i8>().unwrap() {
-                    -1 => true,
-                     0 => false,
-                    _  => unimplemented!(),
+/// Run `f` on every tag of `text`, those in `\t` included
+///
+/// Only the tags `f` changes get rewritten, everything else is kept as it
+/// was written, tags that don't parse included.
+#[cfg(feature = "read")]
+fn map_codes(text: &mut String, f: impl FnMut(&mut OverrideCode)) {
+    map_text(text, f, |_| None);
+}
+
+/// [`map_codes`], also replacing the drawings that come after `\p` with
+/// what `drawing` gives for them
+#[cfg(feature = "read")]
+fn map_text(
+    text: &mut String,
+    mut f: impl FnMut(&mut OverrideCode),
+    mut drawing: impl FnMut(&str) -> Option<String>,
+) {
+    let mut out = String::with_capacity(text.len());
+    let mut changed = false;
+    let mut drawing_mode = false;
+    let mut rest = text.as_str();
+    while !rest.is_empty() {
+        // Blocks are what's between a `{` and the next `}`, like the
+        // tokenizer has them
+        let block = rest.find('{').and_then(|st| Some((st, st + 1 + rest[st+1..].find('}')?)));
+        let (run, block, after) = match block {
+            Some((st, ed)) => (&rest[..st], Some(&rest[st+1..ed]), &rest[ed+1..]),
+            None           => (rest, None, ""),
+        };
+
+        match drawing_mode.then(|| drawing(run)).flatten() {
+            Some(d) => {
+                changed |= d != run;
+                out.push_str(&d);
+            },
+            None => out.push_str(run),
+        }
+
+        if let Some(block) = block {
+            out.push('{');
+            match block.contains('\\') {
+                true  => changed |= splice(block, &mut out, &mut f, Some(&mut drawing_mode)),
+                false => out.push_str(block),
+            }
+            out.push('}');
+        }
+        rest = after;
+    }
+    if changed {
+        *text = out;
+    }
+}
+
+/// Run `f` on the tags of `r`, the inside of an override block or `\t`,
+/// writing the ones it changes to `out` and copying the rest
+///
+/// Gives whether any changed. `drawing` is set on `\p`, outside `\t`.
+#[cfg(feature = "read")]
+fn splice(
+    r: &str,
+    out: &mut String,
+    f: &mut impl FnMut(&mut OverrideCode),
+    mut drawing: Option<&mut bool>,
+) -> bool {
+    use core::fmt::Write;
+
+    // Tags start at the backslashes that aren't in parentheses, the same
+    // way `backside_parser` splits them
+    let mut starts = Vec::new();
+    let mut depth = 0u32;
+    for (i, c) in r.bytes().enumerate() {
+        match c {
+            b'(' => depth += 1,
+            b')' if depth > 0 => depth -= 1,
+            b'\\' if depth == 0 => starts.push(i),
+            _ => {},
+        }
+    }
+
+    let mut changed = false;
+    out.push_str(&r[..starts.first().copied().unwrap_or(r.len())]);
+    for (n, &st) in starts.iter().enumerate() {
+        let tag = &r[st..starts.get(n + 1).copied().unwrap_or(r.len())];
+        let Ok(mut code) = backside_parser::parse_override(tag.as_bytes()) else {
+            out.push_str(tag);
+            continue;
+        };
+        if let (OverrideCode::Drawing(p), Some(d)) = (&code, drawing.as_deref_mut()) {
+            *d = *p > 0;
+        }
+
+        let old = code.clone();
+        f(&mut code);
+        match (old, code) {
+            (OverrideCode::Transform(t, a, _), OverrideCode::Transform(nt, na, _)) => {
+                // It parsed, so it has its parentheses
+                let (open, close) = (tag.find('(').unwrap_or(0), tag.rfind(')').unwrap_or(tag.len()));
+                let p = tag[open..close].find('\\').map_or(close, |p| open + p);
+                if (t, a) == (nt, na) {
+                    out.push_str(&tag[..p]);
+                } else {
+                    // `\t(` and the new times, without the `)`
+                    let _ = write!(out, "{}", OverrideCode::Transform(nt, na, Vec::new()));
+                    out.pop();
+                    changed = true;
                 }
-            };
-            ($type:ident) => {
-                v.parse::<$type>().unwrap()
-            };
+                changed |= splice(&tag[p..close], out, f, None);
+                out.push_str(&tag[close..]);
+            },
+            (old, code) if old != code => {
+                let _ = write!(out, "{}", code);
+                changed = true;
+            },
+            _ => out.push_str(tag),
         }
-    };
+    }
+    changed
+}
+
+/// Formats as ASS/SSA text, the same as [`Script::save`]
+#[cfg(feature = "write")]
+impl core::fmt::Display for Script {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        self.save(f)
+    }
+}
+
+impl From<Document> for Script {
+    fn from(doc: Document) -> Self {
+        Self { doc }
+    }
+}
+
+impl Deref for Script {
+    type Target = Document;
+
+    fn deref(&self) -> &Document {
+        &self.doc
+    }
+}
+
+impl DerefMut for Script {
+    fn deref_mut(&mut self) -> &mut Document {
+        &mut self.doc
+    }
+}
+
+#[cfg(all(test, feature = "oneshot", feature = "write"))]
+mod tests {
+    use alloc::string::ToString;
+
+    use super::*;
+
+    const SCRIPT: &str = "[Events]
+Format: Layer, Start, End, Style, Name, MarginL, MarginR, MarginV, Effect, Text
+Dialogue: 0,0:00:00.50,0:00:10.00,Default,,0,0,0,,{\\k100}Ka{\\kf50}ra{\\t(0,1000,\\fs20)\\move(0,0,10,10,100,200)}oke
+Comment: 0,0:01:00.00,0:01:01.00,Default,,0,0,0,,{\\fad(250,250)}Note
+";
+
+    #[test]
+    fn parse() {
+        let script = match Script::parse(SCRIPT) {
+            Ok(s) => s,
+            Err(e) => panic!("{}", e),
+        };
+        assert_eq!(script.events.len(), 2);
+        assert_eq!(script.events[1].kind, EventKind::Comment);
+        assert_eq!(script.to_string(), SCRIPT);
+
+        let broken = SCRIPT.replace("0:01:01.00", "0:01:0x.00");
+        let Err(e) = Script::parse(&broken) else {
+            panic!("parsed a bad end time");
+        };
+        assert_eq!((e.line, e.section.as_deref()), (Some(4), Some("Events")));
+
+        let (script, warnings) = Script::parse_lenient(&broken);
+        assert_eq!(script.events.len(), 2);
+        assert_eq!(warnings.len(), 1);
+        assert_eq!(warnings[0].line, Some(4));
+
+        let (_, warnings) = Script::parse_lenient(SCRIPT);
+        assert!(warnings.is_empty());
+    }
+
+    #[test]
+    fn timing() {
+        let mut script = match Script::parse(SCRIPT) {
+            Ok(s) => s,
+            Err(e) => panic!("{}", e),
+        };
+
+        script.shift_where(Timestamp(-100), |e| e.kind == EventKind::Dialogue);
+        assert_eq!((script.events[0].start, script.events[0].end), (Timestamp(0), Timestamp(900)));
+        assert_eq!(script.events[1].start, Timestamp::from_hms(0, 1, 0, 0));
+
+        script.scale(1, 2);
+        assert_eq!(script.events[1].start, Timestamp::from_hms(0, 0, 30, 0));
+        assert_eq!(script.to_string(), "[Events]
+Format: Layer, Start, End, Style, Name, MarginL, MarginR, MarginV, Effect, Text
+Dialogue: 0,0:00:00.00,0:00:04.50,Default,,0,0,0,,{\\k50}Ka{\\kf25}ra{\\t(0,500,\\fs20)\\move(0,0,10,10,50,100)}oke
+Comment: 0,0:00:30.00,0:00:30.50,Default,,0,0,0,,{\\fad(125,125)}Note
+");
+
+        script.change_fps(Fps::new(25, 1).unwrap(), Fps::new(24000, 1001).unwrap());
+        assert_eq!(script.events[1].start, Timestamp(3128));
+    }
+
+    #[test]
+    fn untouched_tags() {
+        let text = r"{TL note: pun\i1}a{\be2.5\c&H80FF00FF&\t( 0 ,100, \be2.5\fs20 )\k10 }b{\p1}m 0 0 l 1 1{\p0}{comment}";
+        let mut script = match Script::parse(&SCRIPT.replace("{\\fad(250,250)}Note", text)) {
+            Ok(s) => s,
+            Err(e) => panic!("{}", e),
+        };
+
+        // Doing nothing leaves the text as it was
+        script.scale(1, 1);
+        script.convert_colours(YCbCrMatrix::Bt601Tv);
+        script.resample((384, 288), AspectRatio::Stretch);
+        assert_eq!(script.events[1].text, text);
+
+        // and only the tags that change get rewritten
+        script.scale(2, 1);
+        assert_eq!(script.events[1].text, r"{TL note: pun\i1}a{\be2.5\c&H80FF00FF&\t(0,200,\be2.5\fs20 )\k20}b{\p1}m 0 0 l 1 1{\p0}{comment}");
+        script.resample((768, 576), AspectRatio::Stretch);
+        assert_eq!(script.events[1].text, r"{TL note: pun\i1}a{\be2.5\c&H80FF00FF&\t(0,200,\be2.5\fs40)\k20}b{\p1}m 0 0 l 2 2{\p0}{comment}");
+
+        // A broken tag doesn't keep the others in its line from changing
+        script.doc.events[1].text = r"{\foo\k10\pos(1,}a{\k5}".to_string();
+        script.scale(2, 1);
+        assert_eq!(script.events[1].text, r"{\foo\k20\pos(1,}a{\k10}");
+    }
+
+    #[test]
+    fn resample() {
+        let mut script = match Script::parse(r"[Script Info]
+PlayResX: 1280
+PlayResY: 720
+
+[V4+ Styles]
+Format: Name, Fontname, Fontsize, PrimaryColour, SecondaryColour, OutlineColour, BackColour, Bold, Italic, Underline, StrikeOut, ScaleX, ScaleY, Spacing, Angle, BorderStyle, Outline, Shadow, Alignment, MarginL, MarginR, MarginV, Encoding
+Style: Default,Arial,48,&H00FFFFFF,&H000000FF,&H00000000,&H00000000,0,0,0,0,100,100,0,0,1,2,1,2,20,20,30,1
+
+[Events]
+Format: Layer, Start, End, Style, Name, MarginL, MarginR, MarginV, Effect, Text
+Dialogue: 0,0:00:00.00,0:00:01.00,Default,,0,0,10,,{\pos(640,360)\bord3\t(\fs20)\clip(0,0,100,50)}Hi{\p1}m 0 0 l 10 10{\p0}
+") {
+            Ok(s) => s,
+            Err(e) => panic!("{}", e),
+        };
+
+        script.resample((1920, 1080), AspectRatio::Stretch);
+        assert_eq!(script.info.play_res(), (1920, 1080));
+        let s = &script.styles[0];
+        assert_eq!((s.font_size, s.outline, s.margin_l, s.margin_v), (72.0, 3.0, 30, 45));
+        let e = &script.events[0];
+        assert_eq!((e.margin_l, e.margin_v), (0, 15));
+        assert_eq!(e.text, r"{\pos(960,540)\bord4.5\t(\fs30)\clip(0,0,150,75)}Hi{\p1}m 0 0 l 15 15{\p0}");
+
+        // Borders at the bottom only move bottom margins, the first `\an` of
+        // a line winning over its style
+        let mut top = script.events[0].clone();
+        top.text = r"{\an8\an2}Top".into();
+        script.events.push(top);
+        script.resample((1920, 1080), AspectRatio::Margins(0, 0, 0, 120));
+        assert_eq!(script.styles[0].margin_v, 149);
+        let margins: Vec<_> = script.events.iter().map(|e| e.margin_v).collect();
+        assert_eq!(margins, [122, 14]);
+    }
 }
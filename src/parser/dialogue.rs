@@ -0,0 +1,147 @@
+use alloc::vec::Vec;
+
+use backside_types::*;
+
+use crate::{overrides::overrides, Mode};
+
+/// Split the `Text` field of an event into tokens
+///
+/// Override blocks are parsed, everything else borrows from `r`. A `{`
+/// without a matching `}` is kept as text. Error spans are within `r`.
+pub fn parse_dialogue(r: &str) -> Result<Vec<Token<'_>>> {
+    let mut tokens = Vec::new();
+    dialogue(r, &mut tokens, &mut Mode::Strict)?;
+    Ok(tokens)
+}
+
+/// [`parse_dialogue`], dropping unknown and broken tags like libass does
+///
+/// What got dropped ends up in `warnings`.
+pub fn parse_dialogue_lenient<'a>(r: &'a str, warnings: &mut Vec<Error>) -> Vec<Token<'a>> {
+    let mut tokens = Vec::new();
+    // Whatever stops it keeps the tokens before it
+    if let Err(e) = dialogue(r, &mut tokens, &mut Mode::Lenient(warnings)) {
+        warnings.push(e);
+    }
+    tokens
+}
+
+fn dialogue<'a>(r: &'a str, tokens: &mut Vec<Token<'a>>, mode: &mut Mode) -> Result<()> {
+    let b = r.as_bytes();
+
+    // Start of the current text run
+    let mut st: usize = 0;
+    let mut i: usize = 0;
+
+    macro_rules! flush {
+        () => {
+            if st < i {
+                tokens.push(Token::Text(&r[st..i]));
+            }
+        };
+    }
+
+    while i < b.len() {
+        let (token, len) = match b[i] {
+            b'{' => match b[i+1..].iter().position(|&c| c == b'}') {
+                Some(ed) => {
+                    let block = &r[i+1..i+1+ed];
+                    let token = match block.find('\\') {
+                        Some(p) => {
+                            let mut codes = Vec::new();
+                            overrides(&block.as_bytes()[p..], i + 1 + p, &mut codes, mode)?;
+                            // Text before the first tag is a comment, as in
+                            // a block without tags
+                            if p > 0 {
+                                flush!();
+                                tokens.push(Token::Comment(&block[..p]));
+                                st = i;
+                            }
+                            Token::OverrideBlock(codes)
+                        },
+                        None => Token::Comment(block),
+                    };
+                    (token, ed + 2)
+                },
+                None => {
+                    i += 1;
+                    continue;
+                },
+            },
+            b'\\' => match b.get(i+1) {
+                Some(b'N') => (Token::HardBreak, 2),
+                Some(b'n') => (Token::SoftBreak, 2),
+                Some(b'h') => (Token::HardSpace, 2),
+                _ => {
+                    i += 1;
+                    continue;
+                },
+            },
+            _ => {
+                i += 1;
+                continue;
+            },
+        };
+
+        flush!();
+        tokens.push(token);
+        i += len;
+        st = i;
+    }
+    flush!();
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::{string::{String, ToString}, vec};
+
+    use super::*;
+
+    #[test]
+    fn tokens() {
+        use Token::*;
+
+        let text = r"{\i1}Hi,\Nthere{TL note}\h{\b1\fs20}you\n{}x";
+        let tokens = parse_dialogue(text).unwrap();
+        assert_eq!(tokens, [
+            OverrideBlock(vec![OverrideCode::Italic(Some(true))]),
+            Text("Hi,"),
+            HardBreak,
+            Text("there"),
+            Comment("TL note"),
+            HardSpace,
+            OverrideBlock(vec![OverrideCode::Bold(Some(1)), OverrideCode::FontSize(Some(20.0))]),
+            Text("you"),
+            SoftBreak,
+            Comment(""),
+            Text("x"),
+        ]);
+        // Nothing gets lost
+        assert_eq!(tokens.iter().map(ToString::to_string).collect::<String>(), text);
+
+        // Text before the tags of a block, brackets and all
+        let text = r"a{TL (note\b1}b";
+        let tokens = parse_dialogue(text).unwrap();
+        assert_eq!(tokens, [Text("a"), Comment("TL (note"), OverrideBlock(vec![OverrideCode::Bold(Some(1))]), Text("b")]);
+        assert_eq!(tokens.iter().map(ToString::to_string).collect::<String>(), r"a{TL (note}{\b1}b");
+
+        // Unclosed blocks and other backslashes are text
+        assert_eq!(parse_dialogue(r"a{b\N"), Ok(vec![Text("a{b"), HardBreak]));
+        assert_eq!(parse_dialogue(r"C:\x}\"), Ok(vec![Text(r"C:\x}\")]));
+        assert_eq!(parse_dialogue(""), Ok(vec![]));
+    }
+
+    #[test]
+    fn errors() {
+        let text = r"Hi {\b1\bordx}there";
+        let e = parse_dialogue(text).unwrap_err();
+        assert_eq!((e.kind, &text[e.span.unwrap()]), (ErrorKind::OCInvalidParams, r"\bordx"));
+
+        let mut warnings = Vec::new();
+        let tokens = parse_dialogue_lenient(text, &mut warnings);
+        assert_eq!(tokens, [Token::Text("Hi "), Token::OverrideBlock(vec![OverrideCode::Bold(Some(1))]), Token::Text("there")]);
+        assert_eq!(warnings.len(), 1);
+    }
+}
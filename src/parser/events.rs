@@ -0,0 +1,146 @@
+use alloc::{string::{String, ToString}, vec::Vec};
+
+use backside_types::*;
+
+use crate::{offset, split_key, utf8, Mode};
+
+/// Parse the line prefix of an `[Events]` line
+pub fn parse_event_kind(k: &[u8]) -> Option<EventKind> {
+    Some(match k {
+        b"Dialogue" => EventKind::Dialogue,
+        b"Comment"  => EventKind::Comment,
+        b"Picture"  => EventKind::Picture,
+        b"Sound"    => EventKind::Sound,
+        b"Movie"    => EventKind::Movie,
+        b"Command"  => EventKind::Command,
+        _ => return None,
+    })
+}
+
+/// Parse a `Dialogue:` / `Comment:` / ... line, using the field order of the section's `Format:` line
+///
+/// The line is only split as many times as there are fields, so commas in
+/// the last field (`Text`) are kept. Lines with fewer fields than that, or
+/// formats without a `Start`, `End` or `Style`, are errors.
+pub fn parse_event(r: &[u8], format: &[&[u8]]) -> Result<Event> {
+    event(r, format, &mut Mode::Strict)
+}
+
+/// [`parse_event`], keeping the default for values it can't parse or that
+/// are missing
+///
+/// Those end up in `warnings`. Only fails on lines that aren't event lines.
+pub fn parse_event_lenient(r: &[u8], format: &[&[u8]], warnings: &mut Vec<Error>) -> Result<Event> {
+    event(r, format, &mut Mode::Lenient(warnings))
+}
+
+pub(crate) fn event(r: &[u8], format: &[&[u8]], mode: &mut Mode) -> Result<Event> {
+    // Blames the field `v`
+    let invalid = |v: &[u8]| {
+        let st = offset(r, v);
+        Error::spanned(ErrorKind::EventInvalid, st..st + v.len(), &String::from_utf8_lossy(v))
+    };
+
+    let (kind, fields) = split_key(r)
+        .and_then(|(k, v)| Some((parse_event_kind(k)?, v)))
+        .ok_or_else(|| invalid(r))?;
+
+    let mut event = Event {
+        kind,
+        ..Default::default()
+    };
+
+    for name in [&b"Start"[..], b"End", b"Style"] {
+        if !format.iter().any(|k| k.eq_ignore_ascii_case(name)) {
+            mode.warn(ErrorKind::EventInvalid.into())?;
+        }
+    }
+    // Truncated line, the fields it has still get parsed
+    if fields.splitn(format.len(), |&c| c == b',').count() < format.len() {
+        mode.warn(invalid(fields))?;
+    }
+
+    for (k, v) in format.iter().zip(fields.splitn(format.len(), |&c| c == b',')) {
+        // Everything but `Text` gets trimmed
+        let b = v.trim_ascii();
+        let t = utf8(b).map_err(|_| invalid(b))?;
+
+        // Bad values keep the default in lenient mode
+        macro_rules! or_skip {
+            ($e:expr) => {
+                match $e {
+                    Some(v) => v,
+                    None => {
+                        mode.warn(invalid(b))?;
+                        continue;
+                    },
+                }
+            };
+        }
+        macro_rules! parse {
+            (time) => {
+                or_skip!(Timestamp::parse(t))
+            };
+            ($type:ident) => {
+                or_skip!(t.parse::<$type>().ok())
+            };
+        }
+
+        field! { k {
+            b"Layer"             => event.layer = parse!(i32),
+            b"Marked"            => event.marked = t.trim_start_matches("Marked=") != "0",
+            b"Start"             => event.start = parse!(time),
+            b"End"               => event.end = parse!(time),
+            b"Style"             => event.style = t.to_string(),
+            b"Name" | b"Actor"   => event.name = t.to_string(),
+            b"MarginL"           => event.margin_l = parse!(i32),
+            b"MarginR"           => event.margin_r = parse!(i32),
+            b"MarginV"           => event.margin_v = parse!(i32),
+            b"Effect"            => event.effect = t.to_string(),
+            b"Text"              => event.text = utf8(v).map_err(|_| invalid(v))?.to_string(),
+        }}
+    }
+
+    Ok(event)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fields() {
+        let e = parse_event(b"Comment: 1,0:00:01.00,0:01:00.00,Sign,Bob,1,2,3,fx,{\\i1}a, b,", ASS_EVENT_FORMAT).unwrap();
+        assert_eq!((e.kind, e.layer, e.start, e.end), (EventKind::Comment, 1, Timestamp(100), Timestamp(6000)));
+        assert_eq!((e.style.as_str(), e.name.as_str(), e.effect.as_str()), ("Sign", "Bob", "fx"));
+        assert_eq!((e.margin_l, e.margin_r, e.margin_v), (1, 2, 3));
+        assert_eq!(e.text, "{\\i1}a, b,");
+
+        // SSA, and columns in any order
+        let e = parse_event(b"Dialogue: Marked=1,0:00:01.00,0:00:02.00,Default,,0000,0000,0000,,Hi", SSA_EVENT_FORMAT).unwrap();
+        assert!(e.marked);
+        let format: &[&[u8]] = &[b"End", b"Style", b"start", b"Text"];
+        let e = parse_event(b"Dialogue: 0:00:02.00,Default,0:00:01.00,Hi", format).unwrap();
+        assert_eq!((e.start, e.end, e.text.as_str()), (Timestamp(100), Timestamp(200), "Hi"));
+    }
+
+    #[test]
+    fn errors() {
+        let e = parse_event(b"Dialogue: 0,0:00:01.00,later,Default,,0,0,0,,Hi", ASS_EVENT_FORMAT).unwrap_err();
+        assert_eq!((e.kind, e.span, e.snippet.as_deref()), (ErrorKind::EventInvalid, Some(23..28), Some("later")));
+        assert!(parse_event(b"Style: 0,0:00:01.00,0:00:02.00,Default,,0,0,0,,Hi", ASS_EVENT_FORMAT).is_err());
+
+        // Truncated, in the middle of `End`
+        let line = b"Dialogue: 0,0:00:01.00,0:00";
+        let e = parse_event(line, ASS_EVENT_FORMAT).unwrap_err();
+        assert_eq!((e.kind, e.span), (ErrorKind::EventInvalid, Some(10..line.len())));
+        let mut warnings = Vec::new();
+        let e = parse_event_lenient(line, ASS_EVENT_FORMAT, &mut warnings).unwrap();
+        assert_eq!((e.start, e.end, e.style.as_str()), (Timestamp(100), Timestamp::ZERO, ""));
+        assert_eq!(warnings.len(), 2);
+
+        // A format that can't place the line in time
+        let format: &[&[u8]] = &[b"Layer", b"Style", b"Text"];
+        assert_eq!(parse_event(b"Dialogue: 0,Default,Hi", format).unwrap_err().kind, ErrorKind::EventInvalid);
+    }
+}
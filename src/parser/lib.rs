@@ -1,209 +1,485 @@
 //! # `backside_parser`
 //! Parser implementation for `backside`
 
+#![no_std]
+
+extern crate alloc;
+
+use alloc::{string::{String, ToString}, vec::Vec};
 use core::str::from_utf8;
 
-//mod styles;
+/// Match a `Format:` column name case-insensitively, then `continue` with the next column
+macro_rules! field {
+    ( $k:ident { $( $( $name:literal )|+ => $e:expr, )* } ) => {
+        $(
+        if $( $k.eq_ignore_ascii_case($name) )||+ {
+            $e;
+            continue;
+        }
+        )*
+    };
+}
+
+mod dialogue;
+mod events;
+mod overrides;
+mod styles;
+
+#[cfg(feature = "pull")]
+pub mod pull;
+
+pub use dialogue::{parse_dialogue, parse_dialogue_lenient};
+pub use events::{parse_event, parse_event_lenient, parse_event_kind};
+pub use overrides::{parse_override, parse_overrides, parse_overrides_lenient};
+pub use styles::{parse_style, parse_style_lenient};
+pub use backside_types::{ASS_STYLE_FORMAT, SSA_STYLE_FORMAT, ASS_EVENT_FORMAT, SSA_EVENT_FORMAT};
 
 use backside_types::*;
 
-pub fn parse_bool(r: u8) -> bool {
-    match from_utf8(&[r]).unwrap().parse::<u8>().unwrap() {
-        0 => false,
-        1 => true,
-        _ => panic!()
-    }
+/// Split a buffer into lines, dropping the `\r` of CRLF line endings
+pub fn lines(r: &[u8]) -> impl Iterator<Item = &[u8]> {
+    r.split(|&c| c == b'\n')
+        .map(|l| l.strip_suffix(b"\r").unwrap_or(l))
 }
 
-pub fn parse_override(r: &[u8]) -> OverrideCode {
-    let mut i: usize = 0;
-    let len = r.len();
+/// Split a `Key: value` line at the first colon
+///
+/// The value has its leading whitespace removed.
+pub fn split_key(r: &[u8]) -> Option<(&[u8], &[u8])> {
+    let c = r.iter().position(|&c| c == b':')?;
+    Some((&r[..c], r[c+1..].trim_ascii_start()))
+}
 
-    i += 1;
+pub(crate) fn utf8(r: &[u8]) -> Result<&str> {
+    from_utf8(r).map_err(|_| ErrorKind::StructureInvalid.into())
+}
 
-    use OverrideCode::*;
+/// How to deal with errors that libass would get over
+pub(crate) enum Mode<'w> {
+    /// Fail on the first one
+    Strict,
+    /// Keep them as warnings, and recover the way libass does
+    Lenient(&'w mut Vec<Error>),
+}
 
-    if len == 3 {
-        match &r[1] {
-            b'b' => Bold(parse_bool(r[2])),
-            b'i' => Italic(parse_bool(r[2])),
-            b'u' => Underline(parse_bool(r[2])),
-            b's' => Strikeout(parse_bool(r[2])),
-            &_ => unimplemented!()
-        }
-    } else if len == 4 {
-        match &r[1..3] {
-            b"be" => BlurEdges(parse_bool(r[3])),
-            &_ => unimplemented!()
+impl Mode<'_> {
+    /// Fail with `e`, or keep it as a warning and go on
+    pub(crate) fn warn(&mut self, e: Error) -> Result<()> {
+        match self {
+            Mode::Strict     => Err(e),
+            Mode::Lenient(w) => {
+                w.push(e);
+                Ok(())
+            },
         }
-    } else {
-        unimplemented!()
     }
-}
 
-pub fn parse_dialogue(r: &[u8]) -> String {
-    let mut i: usize = 0;
-    loop {
-        if r[i] == b'{' {
-            i += 1;
-            if r[i] == b'\\' {
-                // It's an override
-
-                let o1 = i;
-                let o2;
-                loop {
-                    if r[i+1] == b'\\' || r[i+1] == b'}' {
-                        o2 = i;
-                        break;
-                    }
-                    i += 1;
-                }
-                let ovrd = &r[o1..o2];
+    /// Number of warnings so far
+    pub(crate) fn warnings(&self) -> usize {
+        match self {
+            Mode::Strict     => 0,
+            Mode::Lenient(w) => w.len(),
+        }
+    }
 
-                parse_override(ovrd);
-            } else {
-                // It's a comment
+    /// [`Error::locate`] the warnings after the first `from`
+    pub(crate) fn locate(&mut self, from: usize, line: usize, section: Option<&str>, text: &str) {
+        if let Mode::Lenient(w) = self {
+            for e in &mut w[from..] {
+                *e = e.clone().locate(line, section, text);
             }
         }
     }
 }
 
-pub fn parse_format(r: &[u8]) -> Vec<&[u8]> {
-    let mut i: usize = 8;
-    let len = r.len();
-    let mut p: Vec<&[u8]> = Vec::new();
+/// Where `inner` starts within `outer`, which it must be a part of
+pub(crate) fn offset(outer: &[u8], inner: &[u8]) -> usize {
+    inner.as_ptr() as usize - outer.as_ptr() as usize
+}
 
-    assert_eq!(&r[0..6], b"Format");
+/// Name of a section header (`[name]`), if `r` is one
+pub fn parse_header(r: &[u8]) -> Option<Result<&[u8]>> {
+    let r = r.strip_prefix(b"[")?;
+    Some(r.iter()
+        .position(|&c| c == b']')
+        .map(|ed| &r[..ed])
+        .ok_or(ErrorKind::HeaderUnclosed.into()))
+}
 
-    // Loop until EOL
-    loop {
-        let st = i;
+/// [`parse_header`], for a line of a section holding `mode`
+///
+/// uuencoded data can start with a `[` too, so in `[Fonts]` and `[Graphics]`
+/// only lines that couldn't be data are headers. The names of the sections
+/// that end them never could, having spaces or lowercase letters.
+pub fn parse_header_in(r: &[u8], mode: Section) -> Option<Result<&[u8]>> {
+    match mode {
+        Section::Fonts | Section::Graphics if r.iter().all(|c| (33..=96).contains(c)) => None,
+        _ => parse_header(r),
+    }
+}
 
-        // Parse fields
-        loop {
-            // If there's a comma, end of field
-            if r[i] == b',' {
-                // Push field to vec
-                p.push(&r[st..i]);
+/// What a section holds, from its name
+pub fn section_kind(h: &[u8]) -> Section {
+    match h {
+        b"Script Info" => Section::ScriptInfo,
+        b"V4+ Styles"  => Section::V4PlusStyles,
+        b"V4 Styles"   => Section::V4Styles,
+        b"Fonts"       => Section::Fonts,
+        b"Graphics"    => Section::Graphics,
+        b"Events"      => Section::Events,
+        _              => Section::None,
+    }
+}
 
-                i += 1;
+/// Parse a `Format:` line into its field names
+pub fn parse_format(r: &[u8]) -> Result<Vec<&[u8]>> {
+    match split_key(r) {
+        Some((b"Format", v)) => Ok(
+            v.split(|&c| c == b',')
+                .map(|f| f.trim_ascii())
+                .collect()
+        ),
+        _ => Err(ErrorKind::StructureInvalid.into()),
+    }
+}
 
-                // If there's a space after the comma, skip over it
-                // (ASS uses ", " rather than SSA's ",")
-                //
-                // Otherwise, skip just the comma
-                if r[i] == b' ' {
-                    i += 1;
-                }
-                break;
-            }
-            // If end of line, break
-            else if i == (len-1) { break; }
-            // Otherwise, next char
-            else { i += 1; }
-        }
+/// Parse a whole script, failing on the first error
+///
+/// Everything needed to write the script back as it was, down to comments
+/// and line endings, is recorded in [`Script::layout`]. Errors carry the line
+/// number and section they were found at.
+pub fn parse_sections(tr: &str) -> Result<Script> {
+    let mut script = Script::default();
+    sections(tr, &mut script, &mut Mode::Strict)?;
+    Ok(script)
+}
 
-        if i == (len-1) {
-            break;
-        }
+/// Parse a whole script, getting over errors the way libass does
+///
+/// Bad values keep their default, unclosed section headers and stray byte
+/// order marks are fixed, and lines that make no sense are kept as they are
+/// without being parsed. Each of these ends up in `warnings`.
+pub fn parse_sections_lenient(tr: &str, warnings: &mut Vec<Error>) -> Script {
+    let mut script = Script::default();
+    // Whatever stops it keeps the lines before it
+    if let Err(e) = sections(tr, &mut script, &mut Mode::Lenient(warnings)) {
+        warnings.push(e);
     }
-
-    p
+    script
 }
 
-pub fn parse_sections(tr: &str) -> String {
+fn sections(tr: &str, script: &mut Script, errs: &mut Mode) -> Result<()> {
     let mut r = tr.as_bytes();
 
     let mut mode: Section = Section::None;
+    // `[V4 Styles]` rather than `[V4+ Styles]`
+    let mut ssa = false;
 
-    let mut style_init = false;
-    let mut fonts_init = false;
-    let mut events_init = false;
-    let mut style_fmt: Vec<&str> = Vec::new();
-    let mut events_fmt: Vec<&str> = Vec::new();
-    let mut ret = String::new();
-
-    let mut i: usize = 0;
-    loop {
-        if i >= r.len() - 1 {
-            break;
-        }
-        if r[i] == b'[' {
-            i += 1;
-
-            let h = {
-                let st = i;
-                let ed;
-                loop {
-                    if r[i] == b']' {
-                        ed = i;
-                        i += 1;
-                        break;
-                    }
-                    i += 1;
-                }
-                &r[st..ed]
+    let mut style_fmt: Vec<&[u8]> = Vec::new();
+    let mut events_fmt: Vec<&[u8]> = Vec::new();
+
+    if let Some(b) = r.strip_prefix("\u{feff}".as_bytes()) {
+        script.layout.bom = true;
+        r = b;
+    }
+    match r.strip_suffix(b"\n") {
+        Some(b) => r = b,
+        None    => script.layout.missing_final_newline = true,
+    }
+    script.layout.crlf = r.split(|&c| c == b'\n')
+        .next()
+        .is_some_and(|l| l.ends_with(b"\r"));
+    script.layout.mixed = r.split(|&c| c == b'\n')
+        .enumerate()
+        .filter(|(_, l)| l.ends_with(b"\r") != script.layout.crlf)
+        .map(|(i, _)| i)
+        .collect();
+
+    for (i, l) in lines(r).enumerate() {
+        let warnings = errs.warnings();
+        let mut line = || -> Result<()> {
+            let l = match l.strip_prefix("\u{feff}".as_bytes()) {
+                Some(b) => {
+                    errs.warn(Error::spanned(ErrorKind::StrayBom, 0..3, "\u{feff}"))?;
+                    b
+                },
+                None => l,
             };
 
-            i += 1;
+            // Section header
+            if let Some(h) = parse_header_in(l, mode) {
+                let (h, rest) = match h {
+                    Ok(h) => (h, &l[h.len() + 2..]),
+                    Err(e) => {
+                        errs.warn(e)?;
+                        (l[1..].trim_ascii_end(), &b""[..])
+                    },
+                };
+                mode = section_kind(h);
+                match mode {
+                    Section::V4PlusStyles => style_fmt = ASS_STYLE_FORMAT.to_vec(),
+                    Section::V4Styles     => {
+                        style_fmt = SSA_STYLE_FORMAT.to_vec();
+                        ssa = true;
+                    },
+                    Section::Events       => events_fmt = match ssa {
+                        true  => SSA_EVENT_FORMAT,
+                        false => ASS_EVENT_FORMAT,
+                    }.to_vec(),
+                    Section::None         => script.extra.push(ExtraSection {
+                        name: utf8(h)?.to_string(),
+                        ..Default::default()
+                    }),
+                    _ => {},
+                }
+                let n = script.item_count(mode);
+                let format = match mode {
+                    Section::V4PlusStyles | Section::V4Styles => to_strings(&style_fmt)?,
+                    Section::Events => to_strings(&events_fmt)?,
+                    _ => Vec::new(),
+                };
+                script.layout.sections.push(SectionLayout {
+                    section: mode,
+                    name: utf8(h)?.to_string(),
+                    rest: utf8(rest)?.to_string(),
+                    items: n..n,
+                    format,
+                    filler: Vec::new(),
+                });
+                return Ok(());
+            }
 
-            let body = {
-                let st = i;
-                let ed;
-                loop {
-                    if i+2 >= r.len() {
-                        ed = r.len() - 1;
-                        break;
-                    }
-                    if r[i] == b'\n' && r[i+1] == b'\n' {
-                        ed = i;
-                        i += 1;
-                        break;
-                    }
-                    i += 1;
+            // Number of items so far, which filler lines come before
+            let n = script.item_count(mode);
+            let Some(layout) = script.layout.sections.last_mut() else {
+                // Only blank lines are allowed before the first section header
+                if !l.trim_ascii().is_empty() {
+                    errs.warn(ErrorKind::StructureInvalid.into())?;
                 }
-                &r[st..ed]
+                script.layout.preamble.push(utf8(l)?.to_string());
+                return Ok(());
             };
 
-            match h {
-                b"Script Info" => {},
-                b"V4+ Styles" => {
-                    let mut i: usize = 0;
-                    let e;
-                    loop {
-                        if body[i] == b'\n' {
-                            e = i;
-                            break;
-                        }
-                        i += 1;
-                    }
-                    for f in parse_format(&body[0..e]) {
-                        ret.push_str(from_utf8(f).unwrap());
-                        ret.push('\n');
-                    }
+            let raw = |l| Ok::<_, Error>((n, Filler::Raw(utf8(l)?.to_string())));
+
+            if l.trim_ascii().is_empty() && mode != Section::None {
+                layout.filler.push(raw(l)?);
+                return Ok(());
+            }
+
+            match mode {
+                Section::ScriptInfo => {
+                    let item = match (l[0], split_key(l)) {
+                        (b';', _)        => InfoEntry::Comment(utf8(&l[1..])?.to_string()),
+                        (_, Some((k, v))) => InfoEntry::Field(utf8(k)?.to_string(), utf8(v)?.to_string()),
+                        (_, None)        => {
+                            errs.warn(ErrorKind::StructureInvalid.into())?;
+                            layout.filler.push(raw(l)?);
+                            return Ok(());
+                        },
+                    };
+                    script.layout.info.push(Source::new(utf8(l)?.to_string(), &item));
+                    script.info.entries.push(item);
                 },
-                b"Fonts" => {},
-                b"Events" => {
-                    let mut i: usize = 0;
-                    let e;
-                    loop {
-                        if body[i] == b'\n' {
-                            e = i;
-                            break;
-                        }
-                        i += 1;
-                    }
-                    for f in parse_format(&body[0..e]) {
-                        ret.push_str(from_utf8(f).unwrap());
-                        ret.push('\n');
+                Section::V4PlusStyles | Section::V4Styles => match split_key(l) {
+                    Some((b"Format", _)) => {
+                        style_fmt = parse_format(l)?;
+                        script.style_format = to_strings(&style_fmt)?;
+                        layout.format = script.style_format.clone();
+                        layout.filler.push((n, Filler::Format(utf8(l)?.to_string())));
+                    },
+                    Some((b"Style", _)) => {
+                        let item = styles::style(l, &style_fmt, errs)?;
+                        script.layout.styles.push(Source::new(utf8(l)?.to_string(), &item));
+                        script.styles.push(item);
+                    },
+                    _ => layout.filler.push(raw(l)?),
+                },
+                Section::Fonts | Section::Graphics => {
+                    let (key, list): (&[u8], _) = match mode {
+                        Section::Fonts => (b"fontname", &mut script.fonts),
+                        _              => (b"filename", &mut script.graphics),
+                    };
+                    match split_key(l) {
+                        Some((k, v)) if k == key => list.push(Attachment {
+                            name: utf8(v)?.to_string(),
+                            ..Default::default()
+                        }),
+                        _ => match list.last_mut() {
+                            Some(a) => a.lines.push(utf8(l)?.to_string()),
+                            // Data before the first `fontname:` / `filename:`
+                            None => {
+                                errs.warn(ErrorKind::StructureInvalid.into())?;
+                                layout.filler.push(raw(l)?);
+                            },
+                        },
                     }
                 },
-                &_ => {}
+                Section::Events => match split_key(l) {
+                    Some((b"Format", _)) => {
+                        events_fmt = parse_format(l)?;
+                        script.event_format = to_strings(&events_fmt)?;
+                        layout.format = script.event_format.clone();
+                        layout.filler.push((n, Filler::Format(utf8(l)?.to_string())));
+                    },
+                    Some((k, _)) if parse_event_kind(k).is_some() => {
+                        let item = events::event(l, &events_fmt, errs)?;
+                        script.layout.events.push(Source::new(utf8(l)?.to_string(), &item));
+                        script.events.push(item);
+                    },
+                    _ => layout.filler.push(raw(l)?),
+                },
+                Section::None => match script.extra.last_mut() {
+                    Some(s) => s.lines.push(utf8(l)?.to_string()),
+                    None => return Err(ErrorKind::StructureInvalid.into()),
+                },
+            }
+            Ok(())
+        };
+        let res = line();
+        let n = script.item_count(mode);
+        if let Some(s) = script.layout.sections.last_mut() {
+            s.items.end = n;
+        }
+
+        let section = script.layout.sections.last().map(|s| s.name.as_str());
+        let text = String::from_utf8_lossy(l);
+        errs.locate(warnings, i + 1, section, &text);
+        if let Err(e) = res {
+            // Kept as it is in lenient mode, like lines that make no sense
+            errs.warn(e.locate(i + 1, section, &text))?;
+            let text = text.into_owned();
+            match script.layout.sections.last_mut() {
+                Some(s) => s.filler.push((n, Filler::Raw(text))),
+                None    => script.layout.preamble.push(text),
             }
-        } else {
-            i += 1;
         }
     }
 
-    ret
+    Ok(())
+}
+
+fn to_strings(f: &[&[u8]]) -> Result<Vec<String>> {
+    f.iter().map(|f| utf8(f).map(String::from)).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::vec;
+
+    use super::*;
+
+    #[test]
+    fn document() {
+        let script = parse_sections("[Script Info]
+; comment
+Title: Test
+PlayResX: 640
+
+[V4+ Styles]
+Format: Name, Fontname, Fontsize
+Style: Default,Arial,20
+Style: Sign,Verdana,32
+
+[Fonts]
+fontname: a_0.ttf
+97*D
+
+[Aegisub Project Garbage]
+Video File: test.mkv
+
+[Events]
+Format: Layer, Start, End, Style, Text
+Dialogue: 1,0:00:01.00,0:00:02.00,Sign,Hi, there
+").unwrap();
+
+        let entry = |k: &str, v: &str| InfoEntry::Field(k.to_string(), v.to_string());
+        let comment = InfoEntry::Comment(" comment".to_string());
+        assert_eq!(script.info.entries, [comment, entry("Title", "Test"), entry("PlayResX", "640")]);
+        assert_eq!(script.style_format, ["Name", "Fontname", "Fontsize"]);
+        let styles: Vec<_> = script.styles.iter().map(|s| (s.name.as_str(), s.font_name.as_str(), s.font_size)).collect();
+        assert_eq!(styles, [("Default", "Arial", 20.0), ("Sign", "Verdana", 32.0)]);
+        assert_eq!(script.fonts[0].name, "a_0.ttf");
+        assert_eq!(script.fonts[0].lines, ["97*D"]);
+        assert_eq!(script.extra[0].name, "Aegisub Project Garbage");
+        assert_eq!(script.extra[0].lines, ["Video File: test.mkv", ""]);
+        assert_eq!(script.event_format, ["Layer", "Start", "End", "Style", "Text"]);
+        let e = &script.events[0];
+        assert_eq!((e.layer, e.start, e.style.as_str(), e.text.as_str()), (1, Timestamp(100), "Sign", "Hi, there"));
+
+        // Only blank lines can come before the first section
+        let e = parse_sections("\nTitle: Test\n[Script Info]\n").unwrap_err();
+        assert_eq!((e.kind, e.line), (ErrorKind::StructureInvalid, Some(2)));
+    }
+
+    #[test]
+    fn error_location() {
+        let e = parse_sections("[Script Info]
+Title: Test
+
+[V4+ Styles]
+Format: Name, Fontname, Fontsize
+Style: Default,Arial,big
+").unwrap_err();
+        assert_eq!(e.kind, ErrorKind::StyleInvalid);
+        assert_eq!(e.line, Some(6));
+        assert_eq!(e.span, Some(21..24));
+        assert_eq!(e.section.as_deref(), Some("V4+ Styles"));
+        assert_eq!(e.snippet.as_deref(), Some("big"));
+        assert_eq!(e.to_string(), "invalid style line at line 6, column 22 in [V4+ Styles]: `big`");
+
+        let e = parse_sections("[Script Info]\nTitle\n").unwrap_err();
+        assert_eq!((e.line, e.span), (Some(2), Some(0..5)));
+    }
+
+    #[test]
+    fn lenient() {
+        let messy = "[Script Info\r
+Title: Test\r
+PlayResX\r
+\r
+\u{feff}[V4+ Styles]\r
+Format: Name, Fontname, Fontsize, Bold,\r
+Style: Default,Arial,big,-1,\r
+\r
+[Events]\r
+Format: Layer, Start, End, Style, Name, MarginL, MarginR, MarginV, Effect, Text\r
+Dialogue: x,0:00:00.00,0:00:01.00,Default,,0,0,0,,{\\foo\\b1}Hi,\r
+";
+        assert_eq!(parse_sections(messy).unwrap_err().kind, ErrorKind::HeaderUnclosed);
+
+        let mut warnings = Vec::new();
+        let script = parse_sections_lenient(messy, &mut warnings);
+        let found: Vec<_> = warnings.iter().map(|e| (e.kind, e.line)).collect();
+        assert_eq!(found, [
+            (ErrorKind::HeaderUnclosed, Some(1)),
+            (ErrorKind::StructureInvalid, Some(3)),
+            (ErrorKind::StrayBom, Some(5)),
+            (ErrorKind::StyleInvalid, Some(7)),
+            (ErrorKind::EventInvalid, Some(11)),
+        ]);
+
+        assert_eq!(script.info.entries, [InfoEntry::Field("Title".to_string(), "Test".to_string())]);
+        assert_eq!(script.layout.sections[0].name, "Script Info");
+        assert_eq!(script.styles[0].font_size, Style::default().font_size);
+        assert!(script.styles[0].bold);
+        assert_eq!(script.events[0].layer, 0);
+        assert_eq!(script.events[0].text, "{\\foo\\b1}Hi,");
+
+        let tokens = parse_dialogue_lenient(&script.events[0].text, &mut warnings);
+        assert_eq!(tokens[0], Token::OverrideBlock(vec![OverrideCode::Bold(Some(1))]));
+        assert_eq!(warnings.last().map(|e| (e.kind, e.span.clone())), Some((ErrorKind::OCInvalid, Some(1..5))));
+    }
+
+    #[test]
+    fn override_span() {
+        let text = r"Hi {\i1\pos(1,2)\t(0,100,\fs20\bord)\move(a,b,c,d)}";
+        let e = parse_dialogue(text).unwrap_err();
+        assert_eq!(e.kind, ErrorKind::OCInvalidParams);
+        assert_eq!(&text[e.span.unwrap()], r"\move(a,b,c,d)");
+
+        let text = r"{\t(0,100,\fs20\fscxbig)}";
+        let e = parse_dialogue(text).unwrap_err();
+        assert_eq!(&text[e.span.unwrap()], r"\fscxbig");
+    }
 }
@@ -0,0 +1,446 @@
+use alloc::{string::{String, ToString}, vec::Vec};
+use core::str::FromStr;
+
+use backside_types::*;
+
+use crate::{offset, utf8, Mode};
+
+/// Tag names, longest first wherever one is a prefix of another
+const TAGS: &[&[u8]] = &[
+    b"xbord", b"ybord", b"xshad", b"yshad",
+    b"bord", b"blur", b"be", b"b",
+    b"iclip", b"i",
+    b"u",
+    b"shad", b"s",
+    b"fscx", b"fscy", b"fsc", b"fsp", b"fs",
+    b"frx", b"fry", b"frz", b"fr",
+    b"fax", b"fay", b"fade", b"fad",
+    b"fn", b"fe",
+    b"1c", b"2c", b"3c", b"4c", b"clip", b"c",
+    b"1a", b"2a", b"3a", b"4a", b"alpha", b"an", b"a",
+    b"kf", b"ko", b"kt", b"k", b"K",
+    b"q", b"r",
+    b"pos", b"pbo", b"p",
+    b"org", b"move", b"t",
+];
+
+/// Value of a tag without parentheses
+///
+/// An empty value means "reset to the style default", and gives `None`.
+fn value<T: FromStr>(v: &[u8]) -> Result<Option<T>> {
+    let v = v.trim_ascii();
+    if v.is_empty() {
+        return Ok(None);
+    }
+    utf8(v)?.parse().map(Some).map_err(|_| ErrorKind::OCInvalidParams.into())
+}
+
+/// `0` / `1` value
+fn flag(v: &[u8]) -> Result<Option<bool>> {
+    Ok(value::<i32>(v)?.map(|v| v != 0))
+}
+
+/// `&Hxx&` value
+fn hex<T: Hex>(v: &[u8]) -> Result<Option<T>> {
+    let v = v.trim_ascii();
+    if v.is_empty() {
+        return Ok(None);
+    }
+    let v = v.strip_prefix(b"&").unwrap_or(v);
+    let v = v.strip_prefix(b"H").or_else(|| v.strip_prefix(b"h")).unwrap_or(v);
+    let v = v.strip_suffix(b"&").unwrap_or(v);
+    T::from_hex(utf8(v)?).map(Some).ok_or(ErrorKind::OCInvalidParams.into())
+}
+
+/// `&Hbbggrr&` value
+fn colour(v: &[u8]) -> Result<Option<Colour>> {
+    let v = v.trim_ascii();
+    if v.is_empty() {
+        return Ok(None);
+    }
+    Colour::parse_hex(utf8(v)?).map(Some).ok_or(ErrorKind::OCInvalidParams.into())
+}
+
+trait Hex: Sized {
+    fn from_hex(s: &str) -> Option<Self>;
+}
+impl Hex for u8 {
+    fn from_hex(s: &str) -> Option<Self> { u8::from_str_radix(s, 16).ok() }
+}
+
+/// Required numeric argument
+fn num<T: FromStr>(v: &[u8]) -> Result<T> {
+    value(v)?.ok_or(ErrorKind::OCMissingParams.into())
+}
+
+/// Arguments of a tag with parentheses
+fn args(v: &[u8]) -> Result<Vec<&[u8]>> {
+    let v = v.trim_ascii()
+        .strip_prefix(b"(")
+        .and_then(|v| v.strip_suffix(b")"))
+        .ok_or(ErrorKind::OCMissingParams)?;
+    Ok(v.split(|&c| c == b',').map(|a| a.trim_ascii()).collect())
+}
+
+fn clip(v: &[u8]) -> Result<ClipShape> {
+    let a = args(v)?;
+    Ok(match a.as_slice() {
+        [x1, y1, x2, y2] => ClipShape::Rect(num(x1)?, num(y1)?, num(x2)?, num(y2)?),
+        [s, d]           => ClipShape::Vector(Some(num(s)?), utf8(d)?.to_string()),
+        [d]              => ClipShape::Vector(None, utf8(d)?.to_string()),
+        _ => return Err(ErrorKind::OCInvalidParams.into()),
+    })
+}
+
+/// `\t`, at byte `at` of the text being parsed
+fn transform(tv: &[u8], at: usize, mode: &mut Mode) -> Result<OverrideCode> {
+    let v = tv.trim_ascii()
+        .strip_prefix(b"(")
+        .and_then(|v| v.strip_suffix(b")"))
+        .ok_or(ErrorKind::OCMissingParams)?;
+
+    // Numeric arguments come before the first tag
+    let p = v.iter().position(|&c| c == b'\\').unwrap_or(v.len());
+    let a: Vec<&[u8]> = v[..p]
+        .split(|&c| c == b',')
+        .map(|a| a.trim_ascii())
+        .filter(|a| !a.is_empty())
+        .collect();
+
+    let (t, accel) = match a.as_slice() {
+        []               => (None, None),
+        [a]              => (None, Some(num(a)?)),
+        [t1, t2]         => (Some((num(t1)?, num(t2)?)), None),
+        [t1, t2, a]      => (Some((num(t1)?, num(t2)?)), Some(num(a)?)),
+        _ => return Err(ErrorKind::OCInvalidParams.into()),
+    };
+
+    let mut codes = Vec::new();
+    overrides(&v[p..], at + offset(tv, &v[p..]), &mut codes, mode)?;
+    Ok(OverrideCode::Transform(t, accel, codes))
+}
+
+/// Parse a single override tag, eg. `\bord2.5`
+///
+/// The leading backslash is optional. Errors span the whole tag, or the
+/// faulty tag inside a `\t`.
+pub fn parse_override(r: &[u8]) -> Result<OverrideCode> {
+    tag(r, 0, &mut Mode::Strict)
+}
+
+/// A tag, at byte `at` of the text being parsed
+fn tag(r: &[u8], at: usize, mode: &mut Mode) -> Result<OverrideCode> {
+    let tr = r.strip_prefix(b"\\").unwrap_or(r);
+    let name = TAGS.iter().find(|t| tr.starts_with(t));
+    let v = &tr[name.map_or(0, |n| n.len())..];
+
+    name.ok_or(ErrorKind::OCInvalid.into())
+        .and_then(|name| tag_value(name, v, at + offset(r, v), mode))
+        .map_err(|e| match e.span {
+            Some(_) => e,
+            None    => Error::spanned(e.kind, at..at + r.len(), &String::from_utf8_lossy(r)),
+        })
+}
+
+fn tag_value(name: &[u8], v: &[u8], at: usize, mode: &mut Mode) -> Result<OverrideCode> {
+    use OverrideCode::*;
+    use XOrYOrZ::*;
+
+    Ok(match name {
+        b"b"     => Bold(value(v)?),
+        b"i"     => Italic(flag(v)?),
+        b"u"     => Underline(flag(v)?),
+        b"s"     => Strikeout(flag(v)?),
+        b"bord"  => Border(None, value(v)?),
+        b"xbord" => Border(Some(X), value(v)?),
+        b"ybord" => Border(Some(Y), value(v)?),
+        b"shad"  => Shadow(None, value(v)?),
+        b"xshad" => Shadow(Some(X), value(v)?),
+        b"yshad" => Shadow(Some(Y), value(v)?),
+        b"be"    => BlurEdges(value(v)?),
+        b"blur"  => Blur(value(v)?),
+        b"fn"    => FontName(value(v)?),
+        b"fs" if matches!(v.trim_ascii().first(), Some(b'+' | b'-')) => FontSizeRelative(value(v)?.unwrap_or_default()),
+        b"fs"    => FontSize(value(v)?),
+        b"fscx"  => Scale(Some(X), value(v)?),
+        b"fscy"  => Scale(Some(Y), value(v)?),
+        b"fsc"   => Scale(None, value(v)?),
+        b"fsp"   => Spacing(value(v)?),
+        b"frx"   => Rotation(X, value(v)?),
+        b"fry"   => Rotation(Y, value(v)?),
+        b"frz"   |
+        b"fr"    => Rotation(Z, value(v)?),
+        b"fax"   => Shear(X, value(v)?),
+        b"fay"   => Shear(Y, value(v)?),
+        b"fe"    => FontEncoding(value(v)?),
+        b"c"     |
+        b"1c"    => Color(1, colour(v)?),
+        b"2c"    => Color(2, colour(v)?),
+        b"3c"    => Color(3, colour(v)?),
+        b"4c"    => Color(4, colour(v)?),
+        b"alpha" => Alpha(None, hex(v)?),
+        b"1a"    => Alpha(Some(1), hex(v)?),
+        b"2a"    => Alpha(Some(2), hex(v)?),
+        b"3a"    => Alpha(Some(3), hex(v)?),
+        b"4a"    => Alpha(Some(4), hex(v)?),
+        b"a"     => Alignment(value(v)?),
+        b"an"    => AlignmentNumpad(value(v)?),
+        b"k"     => Karaoke(KaraokeKind::K, value(v)?.unwrap_or(0)),
+        b"K"     => Karaoke(KaraokeKind::KUpper, value(v)?.unwrap_or(0)),
+        b"kf"    => Karaoke(KaraokeKind::Kf, value(v)?.unwrap_or(0)),
+        b"ko"    => Karaoke(KaraokeKind::Ko, value(v)?.unwrap_or(0)),
+        b"kt"    => Karaoke(KaraokeKind::Kt, value(v)?.unwrap_or(0)),
+        b"q"     => WrappingStyle(value(v)?),
+        b"r"     => Reset(value(v)?),
+        b"pos"   => match args(v)?.as_slice() {
+            [x, y] => Position(num(x)?, num(y)?),
+            _ => return Err(ErrorKind::OCInvalidParams.into()),
+        },
+        b"org"   => match args(v)?.as_slice() {
+            [x, y] => Origin(num(x)?, num(y)?),
+            _ => return Err(ErrorKind::OCInvalidParams.into()),
+        },
+        b"move"  => match args(v)?.as_slice() {
+            [x1, y1, x2, y2] => Move(num(x1)?, num(y1)?, num(x2)?, num(y2)?, None),
+            [x1, y1, x2, y2, t1, t2] =>
+                Move(num(x1)?, num(y1)?, num(x2)?, num(y2)?, Some((num(t1)?, num(t2)?))),
+            _ => return Err(ErrorKind::OCInvalidParams.into()),
+        },
+        b"fad"   => match args(v)?.as_slice() {
+            [t1, t2] => Fade(num(t1)?, num(t2)?),
+            _ => return Err(ErrorKind::OCInvalidParams.into()),
+        },
+        b"fade"  => match args(v)?.as_slice() {
+            [a1, a2, a3, t1, t2, t3, t4] => FadeComplex(
+                num(a1)?, num(a2)?, num(a3)?,
+                num(t1)?, num(t2)?, num(t3)?, num(t4)?,
+            ),
+            _ => return Err(ErrorKind::OCInvalidParams.into()),
+        },
+        b"clip"  => Clip(clip(v)?),
+        b"iclip" => InverseClip(clip(v)?),
+        b"p"     => Drawing(value(v)?.unwrap_or(0)),
+        b"pbo"   => BaselineOffset(value(v)?.unwrap_or(0.0)),
+        b"t"     => transform(v, at, mode)?,
+        _ => unreachable!(),
+    })
+}
+
+/// Parse the contents of an override block (without the braces) into its tags
+///
+/// Anything before the first tag is ignored, the same way renderers do.
+pub fn parse_overrides(r: &[u8]) -> Result<Vec<OverrideCode>> {
+    let mut codes = Vec::new();
+    overrides(r, 0, &mut codes, &mut Mode::Strict)?;
+    Ok(codes)
+}
+
+/// [`parse_overrides`], dropping unknown and broken tags like libass does
+///
+/// What got dropped ends up in `warnings`.
+pub fn parse_overrides_lenient(r: &[u8], warnings: &mut Vec<Error>) -> Vec<OverrideCode> {
+    let mut codes = Vec::new();
+    // Whatever stops it keeps the tags before it
+    if let Err(e) = overrides(r, 0, &mut codes, &mut Mode::Lenient(warnings)) {
+        warnings.push(e);
+    }
+    codes
+}
+
+/// Override block, at byte `at` of the text being parsed, adding its tags to
+/// `codes`
+pub(crate) fn overrides(r: &[u8], at: usize, codes: &mut Vec<OverrideCode>, mode: &mut Mode) -> Result<()> {
+    // Start of the current tag, at its backslash
+    let mut st: Option<usize> = None;
+    // Parenthesis depth, `\t` can hold other tags
+    let mut depth = 0u32;
+
+    let mut push = |t: &[u8], st: usize, mode: &mut Mode| {
+        match tag(t, at + st, mode) {
+            Ok(c)  => codes.push(c),
+            Err(e) => mode.warn(e)?,
+        }
+        Ok::<_, Error>(())
+    };
+
+    for (i, &c) in r.iter().enumerate() {
+        match c {
+            b'(' => depth += 1,
+            // A stray `)` is part of the tag it's in, like libass has it
+            b')' if depth > 0 => depth -= 1,
+            b'\\' if depth == 0 => {
+                if let Some(st) = st.filter(|&st| st + 1 < i) {
+                    push(&r[st..i], st, mode)?;
+                }
+                st = Some(i);
+            },
+            _ => {},
+        }
+    }
+    if let Some(st) = st.filter(|&st| st + 1 < r.len()) {
+        push(&r[st..], st, mode)?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::vec;
+
+    use super::*;
+
+    use OverrideCode::*;
+    use XOrYOrZ::*;
+
+    fn parse(t: &str) -> Result<OverrideCode> {
+        parse_override(t.as_bytes())
+    }
+
+    #[test]
+    fn tags() {
+        let table = [
+            (r"\b1", Bold(Some(1))),
+            (r"\b700", Bold(Some(700))),
+            (r"\b", Bold(None)),
+            (r"\i1", Italic(Some(true))),
+            (r"\i0", Italic(Some(false))),
+            (r"\u1", Underline(Some(true))),
+            (r"\s0", Strikeout(Some(false))),
+            (r"\bord2.5", Border(None, Some(2.5))),
+            (r"\xbord1", Border(Some(X), Some(1.0))),
+            (r"\ybord", Border(Some(Y), None)),
+            (r"\shad-2", Shadow(None, Some(-2.0))),
+            (r"\xshad3", Shadow(Some(X), Some(3.0))),
+            (r"\yshad4", Shadow(Some(Y), Some(4.0))),
+            (r"\be2.5", BlurEdges(Some(2.5))),
+            (r"\blur0.8", Blur(Some(0.8))),
+            (r"\fnComic Sans MS", FontName(Some("Comic Sans MS".to_string()))),
+            (r"\fn", FontName(None)),
+            (r"\fs20", FontSize(Some(20.0))),
+            (r"\fs+2", FontSizeRelative(2.0)),
+            (r"\fs-1.5", FontSizeRelative(-1.5)),
+            (r"\fscx120", Scale(Some(X), Some(120.0))),
+            (r"\fscy80", Scale(Some(Y), Some(80.0))),
+            (r"\fsc", Scale(None, None)),
+            (r"\fsp-1.5", Spacing(Some(-1.5))),
+            (r"\frx10", Rotation(X, Some(10.0))),
+            (r"\fry-20", Rotation(Y, Some(-20.0))),
+            (r"\frz30", Rotation(Z, Some(30.0))),
+            (r"\fr45", Rotation(Z, Some(45.0))),
+            (r"\fax0.5", Shear(X, Some(0.5))),
+            (r"\fay-0.1", Shear(Y, Some(-0.1))),
+            (r"\fe128", FontEncoding(Some(128))),
+            (r"\c&HFF&", Color(1, Some(Colour::rgb(0xFF, 0, 0)))),
+            (r"\1c&H00FF00&", Color(1, Some(Colour::rgb(0, 0xFF, 0)))),
+            (r"\2c&HFF0000", Color(2, Some(Colour::rgb(0, 0, 0xFF)))),
+            (r"\3cH0", Color(3, Some(Colour(0)))),
+            (r"\4c", Color(4, None)),
+            (r"\alpha&H80&", Alpha(None, Some(0x80))),
+            (r"\1a&HFF&", Alpha(Some(1), Some(0xFF))),
+            (r"\3a&H0&", Alpha(Some(3), Some(0))),
+            (r"\4a", Alpha(Some(4), None)),
+            (r"\a6", Alignment(Some(6))),
+            (r"\an7", AlignmentNumpad(Some(7))),
+            (r"\k50", Karaoke(KaraokeKind::K, 50)),
+            (r"\K30", Karaoke(KaraokeKind::KUpper, 30)),
+            (r"\kf20", Karaoke(KaraokeKind::Kf, 20)),
+            (r"\ko10", Karaoke(KaraokeKind::Ko, 10)),
+            (r"\kt100", Karaoke(KaraokeKind::Kt, 100)),
+            (r"\k", Karaoke(KaraokeKind::K, 0)),
+            (r"\q2", WrappingStyle(Some(2))),
+            (r"\r", Reset(None)),
+            (r"\rSign", Reset(Some("Sign".to_string()))),
+            (r"\pos(10.5, 20)", Position(10.5, 20.0)),
+            (r"\org(-5,5)", Origin(-5.0, 5.0)),
+            (r"\move(0,0,100,200)", Move(0.0, 0.0, 100.0, 200.0, None)),
+            (r"\move(0,0,100,200,500,1000)", Move(0.0, 0.0, 100.0, 200.0, Some((500, 1000)))),
+            (r"\fad(200,300)", Fade(200, 300)),
+            (r"\fade(255,0,255,0,100,900,1000)", FadeComplex(255, 0, 255, 0, 100, 900, 1000)),
+            (r"\clip(0,0,100,50)", Clip(ClipShape::Rect(0.0, 0.0, 100.0, 50.0))),
+            (r"\iclip(1,2,3,4)", InverseClip(ClipShape::Rect(1.0, 2.0, 3.0, 4.0))),
+            (r"\clip(m 0 0 l 10 0 10 10)", Clip(ClipShape::Vector(None, "m 0 0 l 10 0 10 10".to_string()))),
+            (r"\iclip(2,m 0 0 l 10 10)", InverseClip(ClipShape::Vector(Some(2), "m 0 0 l 10 10".to_string()))),
+            (r"\p1", Drawing(1)),
+            (r"\p", Drawing(0)),
+            (r"\pbo-10", BaselineOffset(-10.0)),
+            // The backslash is optional, whitespace around values is not kept
+            ("bord 3 ", Border(None, Some(3.0))),
+        ];
+        for (text, code) in table {
+            assert_eq!(parse(text), Ok(code), "{}", text);
+        }
+        assert_eq!((FontSizeRelative(2.0).to_string(), FontSizeRelative(-1.5).to_string()), (r"\fs+2".into(), r"\fs-1.5".into()));
+    }
+
+    #[test]
+    fn transforms() {
+        let table = [
+            (r"\t(\fs20)", Transform(None, None, vec![FontSize(Some(20.0))])),
+            (r"\t(2,\fs20)", Transform(None, Some(2.0), vec![FontSize(Some(20.0))])),
+            (r"\t(0,500,\fs20\bord2)", Transform(Some((0, 500)), None, vec![FontSize(Some(20.0)), Border(None, Some(2.0))])),
+            (r"\t(0, 500, 0.5, \frz90)", Transform(Some((0, 500)), Some(0.5), vec![Rotation(Z, Some(90.0))])),
+            (r"\t(\clip(0,0,10,10)\1c&HFF&)", Transform(None, None, vec![
+                Clip(ClipShape::Rect(0.0, 0.0, 10.0, 10.0)),
+                Color(1, Some(Colour::rgb(0xFF, 0, 0))),
+            ])),
+            (r"\t(0,500,)", Transform(Some((0, 500)), None, vec![])),
+        ];
+        for (text, code) in table {
+            assert_eq!(parse(text), Ok(code), "{}", text);
+        }
+
+        let codes = parse_overrides(br"\pos(1,2)\t(0,100,\fs20)\i1").unwrap();
+        assert_eq!(codes, [Position(1.0, 2.0), Transform(Some((0, 100)), None, vec![FontSize(Some(20.0))]), Italic(Some(true))]);
+    }
+
+    #[test]
+    fn malformed() {
+        let table = [
+            (r"\foo", ErrorKind::OCInvalid),
+            (r"\", ErrorKind::OCInvalid),
+            (r"\bordx", ErrorKind::OCInvalidParams),
+            (r"\b1.5", ErrorKind::OCInvalidParams),
+            (r"\an-1", ErrorKind::OCInvalidParams),
+            (r"\c&HGG&", ErrorKind::OCInvalidParams),
+            (r"\alpha&H100&", ErrorKind::OCInvalidParams),
+            (r"\k1.5", ErrorKind::OCInvalidParams),
+            (r"\pos(1)", ErrorKind::OCInvalidParams),
+            (r"\pos(1,)", ErrorKind::OCMissingParams),
+            (r"\pos", ErrorKind::OCMissingParams),
+            (r"\pos(1,2", ErrorKind::OCMissingParams),
+            (r"\move(1,2,3,4,5)", ErrorKind::OCInvalidParams),
+            (r"\fad(1,2,3)", ErrorKind::OCInvalidParams),
+            (r"\fade(256,0,0,0,0,0,0)", ErrorKind::OCInvalidParams),
+            (r"\clip(1,2,3)", ErrorKind::OCInvalidParams),
+            (r"\clip(a,m 0 0)", ErrorKind::OCInvalidParams),
+            (r"\t(1,2,3,4,\fs1)", ErrorKind::OCInvalidParams),
+            (r"\t(\fs20", ErrorKind::OCMissingParams),
+            (r"\t(\fsbig)", ErrorKind::OCInvalidParams),
+            (r"\p-1", ErrorKind::OCInvalidParams),
+        ];
+        for (text, kind) in table {
+            let e = parse(text).unwrap_err();
+            assert_eq!(e.kind, kind, "{}", text);
+        }
+
+        // Errors blame the tag, or the faulty one inside a `\t`
+        let e = parse(r"\t(0,100,\fs20\fscxbig)").unwrap_err();
+        assert_eq!((e.span, e.snippet.as_deref()), (Some(14..22), Some(r"\fscxbig")));
+        assert_eq!(parse(r"\bordx").unwrap_err().span, Some(0..6));
+
+        // Lenient parsing drops what's broken, text before the first tag too
+        let mut warnings = Vec::new();
+        let codes = parse_overrides_lenient(br"note\bord2\foo\t(\fsx\i1)\b1", &mut warnings);
+        assert_eq!(codes, [Border(None, Some(2.0)), Transform(None, None, vec![Italic(Some(true))]), Bold(Some(1))]);
+        assert_eq!(warnings.iter().map(|e| e.span.clone()).collect::<Vec<_>>(), [Some(10..14), Some(17..21)]);
+    }
+
+    #[test]
+    fn stray_paren() {
+        let mut warnings = Vec::new();
+        let codes = parse_overrides_lenient(br"\b1)\t(\bord2)\i1", &mut warnings);
+        assert_eq!(codes, [Transform(None, None, vec![Border(None, Some(2.0))]), Italic(Some(true))]);
+        assert_eq!(warnings.iter().map(|e| e.span.clone()).collect::<Vec<_>>(), [Some(0..4)]);
+    }
+}
@@ -0,0 +1,367 @@
+//! Streaming parser
+//!
+//! [`Parser`] goes through a script one line at a time and hands out what it
+//! finds, without ever building the whole [`Script`]. Memory use only depends
+//! on the longest line, so it suits huge karaoke and typesetting scripts.
+
+use alloc::{string::String, vec::Vec};
+
+use backside_types::*;
+
+use crate::*;
+use crate::{events::event, styles::style};
+
+/// Something found by the [`Parser`]
+#[derive(Clone, PartialEq, Debug)]
+pub enum Item<'a> {
+    /// `[name]`
+    SectionHeader(&'a str),
+    /// `Key: value` line of `[Script Info]`
+    InfoField(&'a str, &'a str),
+    /// `Format:` line of `[V4+ Styles]` / `[V4 Styles]` / `[Events]`
+    Format(Vec<&'a str>),
+    /// `Style:` line
+    Style(Style),
+    /// `Dialogue:` / `Comment:` / ... line
+    Event(Event),
+    /// One line of uuencoded data of the font named `name`
+    FontChunk {
+        name: &'a str,
+        data: &'a str,
+    },
+    /// One line of uuencoded data of the image named `name`
+    GraphicChunk {
+        name: &'a str,
+        data: &'a str,
+    },
+    /// `;` line
+    Comment(&'a str),
+    /// Any other line, eg. in sections the parser doesn't know about
+    Other(&'a str),
+}
+
+/// Pull parser, yielding one [`Item`] per non-blank line
+///
+/// Errors carry the line number and section they were found at, and parsing
+/// can go on with the next line after one. A [`lenient`](Self::lenient)
+/// parser gets over them the way [`parse_sections_lenient`] does instead.
+///
+/// ```
+/// # use backside_parser::pull::{Parser, Item};
+/// let script = b"[Events]\nDialogue: 0,0:00:00.00,0:00:01.00,Default,,0,0,0,,Hi\n";
+/// let n = Parser::new(script)
+///     .filter(|i| matches!(i, Ok(Item::Event(_))))
+///     .count();
+/// assert_eq!(n, 1);
+/// ```
+pub struct Parser<'a> {
+    rest: &'a [u8],
+
+    mode: Section,
+    // `[V4 Styles]` rather than `[V4+ Styles]`
+    ssa: bool,
+    style_fmt: Vec<&'a [u8]>,
+    events_fmt: Vec<&'a [u8]>,
+    // Name of the current font / image
+    attachment: &'a str,
+    section: Option<&'a str>,
+    lenient: bool,
+
+    /// What a lenient parser got over so far
+    pub warnings: Vec<Error>,
+
+    /// Number of the line last returned, starting at 1
+    pub line: usize,
+}
+
+impl<'a> Parser<'a> {
+    pub fn new(r: &'a [u8]) -> Self {
+        Self {
+            rest: r.strip_prefix("\u{feff}".as_bytes()).unwrap_or(r),
+            mode: Section::None,
+            ssa: false,
+            style_fmt: Vec::new(),
+            events_fmt: Vec::new(),
+            attachment: "",
+            section: None,
+            lenient: false,
+            warnings: Vec::new(),
+            line: 0,
+        }
+    }
+
+    /// Parser that keeps errors in [`warnings`](Self::warnings) and goes on
+    pub fn lenient(r: &'a [u8]) -> Self {
+        Self {
+            lenient: true,
+            ..Self::new(r)
+        }
+    }
+
+    fn next_line(&mut self) -> Option<&'a [u8]> {
+        if self.rest.is_empty() {
+            return None;
+        }
+        let (l, rest) = match self.rest.iter().position(|&c| c == b'\n') {
+            Some(i) => (&self.rest[..i], &self.rest[i+1..]),
+            None    => (self.rest, &self.rest[self.rest.len()..]),
+        };
+        self.rest = rest;
+        self.line += 1;
+        Some(l.strip_suffix(b"\r").unwrap_or(l))
+    }
+
+    /// Item of a non-blank line, `None` for `fontname:` / `filename:` lines
+    fn item(&mut self, l: &'a [u8], errs: &mut Mode) -> Result<Option<Item<'a>>> {
+        let l = match l.strip_prefix("\u{feff}".as_bytes()) {
+            Some(b) => {
+                errs.warn(Error::spanned(ErrorKind::StrayBom, 0..3, "\u{feff}"))?;
+                b
+            },
+            None => l,
+        };
+
+        if let Some(h) = parse_header_in(l, self.mode) {
+            let h = match h {
+                Ok(h) => h,
+                Err(e) => {
+                    errs.warn(e)?;
+                    l[1..].trim_ascii_end()
+                },
+            };
+            self.section = Some(utf8(h)?);
+            self.mode = section_kind(h);
+            self.attachment = "";
+            match self.mode {
+                Section::V4PlusStyles => self.style_fmt = ASS_STYLE_FORMAT.to_vec(),
+                Section::V4Styles     => {
+                    self.style_fmt = SSA_STYLE_FORMAT.to_vec();
+                    self.ssa = true;
+                },
+                Section::Events       => self.events_fmt = match self.ssa {
+                    true  => SSA_EVENT_FORMAT,
+                    false => ASS_EVENT_FORMAT,
+                }.to_vec(),
+                _ => {},
+            }
+            return Ok(Some(Item::SectionHeader(utf8(h)?)));
+        }
+
+        // uuencoded data can start with a `;`
+        if l[0] == b';' && !matches!(self.mode, Section::Fonts | Section::Graphics) {
+            return Ok(Some(Item::Comment(utf8(l)?)));
+        }
+
+        Ok(Some(match (self.mode, split_key(l)) {
+            (Section::ScriptInfo, Some((k, v))) => Item::InfoField(utf8(k)?, utf8(v)?),
+            (Section::ScriptInfo, None) => {
+                errs.warn(ErrorKind::StructureInvalid.into())?;
+                Item::Other(utf8(l)?)
+            },
+
+            (Section::V4PlusStyles | Section::V4Styles, Some((b"Format", _))) => {
+                self.style_fmt = parse_format(l)?;
+                Item::Format(self.style_fmt.iter().map(|f| utf8(f)).collect::<Result<_>>()?)
+            },
+            (Section::V4PlusStyles | Section::V4Styles, Some((b"Style", _))) =>
+                Item::Style(style(l, &self.style_fmt, errs)?),
+
+            (Section::Events, Some((b"Format", _))) => {
+                self.events_fmt = parse_format(l)?;
+                Item::Format(self.events_fmt.iter().map(|f| utf8(f)).collect::<Result<_>>()?)
+            },
+            (Section::Events, Some((k, _))) if parse_event_kind(k).is_some() =>
+                Item::Event(event(l, &self.events_fmt, errs)?),
+
+            (Section::Fonts, Some((b"fontname", v))) |
+            (Section::Graphics, Some((b"filename", v))) => {
+                self.attachment = utf8(v)?;
+                return Ok(None);
+            },
+            (Section::Fonts, _) if !self.attachment.is_empty() => Item::FontChunk {
+                name: self.attachment,
+                data: utf8(l)?,
+            },
+            (Section::Graphics, _) if !self.attachment.is_empty() => Item::GraphicChunk {
+                name: self.attachment,
+                data: utf8(l)?,
+            },
+            // Data before the first `fontname:` / `filename:`
+            (Section::Fonts | Section::Graphics, _) => {
+                errs.warn(ErrorKind::StructureInvalid.into())?;
+                Item::Other(utf8(l)?)
+            },
+
+            _ => Item::Other(utf8(l)?),
+        }))
+    }
+}
+
+impl<'a> Iterator for Parser<'a> {
+    type Item = Result<Item<'a>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let l = self.next_line()?;
+            if l.trim_ascii().is_empty() {
+                continue;
+            }
+
+            let mut warnings = core::mem::take(&mut self.warnings);
+            let from = warnings.len();
+            let res = match self.lenient {
+                true  => self.item(l, &mut Mode::Lenient(&mut warnings)),
+                false => self.item(l, &mut Mode::Strict),
+            };
+
+            let text = String::from_utf8_lossy(l);
+            Mode::Lenient(&mut warnings).locate(from, self.line, self.section, &text);
+            self.warnings = warnings;
+
+            match res {
+                Ok(Some(item)) => return Some(Ok(item)),
+                Ok(None)       => continue,
+                Err(e)         => return Some(Err(e.locate(self.line, self.section, &text))),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::string::ToString;
+
+    const SCRIPT: &str = "\u{feff}[Script Info]
+; Script generated by hand
+Title: Test
+ScriptType: v4.00+
+
+[V4+ Styles]
+Format: Name, Fontname, Fontsize, PrimaryColour, SecondaryColour, OutlineColour, BackColour, Bold, Italic, Underline, StrikeOut, ScaleX, ScaleY, Spacing, Angle, BorderStyle, Outline, Shadow, Alignment, MarginL, MarginR, MarginV, Encoding
+; styles
+Style: Default,Arial,48,&H00FFFFFF,&H000000FF,&H00000000,&H00000000,0,0,0,0,100,100,0,0,1,2,2,2,10,10,10,1
+Style: Sign,Arial,20,&H00FFFFFF,&H000000FF,&H00000000,&H00000000,-1,0,0,0,100,100,0,0,1,2,2,8,10,10,10,1
+
+[Fonts]
+fontname: a_0.ttf
+97*D:!
+;GHI
+fontname: b_0.ttf
+97*D
+
+[Graphics]
+filename: logo.png
+97*D:!
+
+[Aegisub Project Garbage]
+Video File: test.mkv
+; kept as is
+
+[Events]
+Format: Layer, Start, End, Style, Name, MarginL, MarginR, MarginV, Effect, Text
+Dialogue: 0,0:00:01.00,0:00:03.50,Default,,0,0,0,,Hello, {\\i1}world{\\i0}!
+; a comment
+Comment: 0,0:00:04.00,0:00:05.00,Sign,,0,0,0,,Note
+";
+
+    /// What `parse_sections` would make of the items, and the comments of
+    /// the known sections
+    fn collect(p: Parser) -> (Script, Vec<String>) {
+        let mut script = Script::default();
+        let mut comments = Vec::new();
+        let mut kind = Section::None;
+        for item in p {
+            let chunk = |list: &mut Vec<Attachment>, name: &str, data: &str| {
+                if list.last().is_none_or(|a| a.name != name) {
+                    list.push(Attachment { name: name.to_string(), lines: Vec::new() });
+                }
+                list.last_mut().unwrap().lines.push(data.to_string());
+            };
+            match item.unwrap() {
+                Item::SectionHeader(h) => {
+                    kind = section_kind(h.as_bytes());
+                    if kind == Section::None {
+                        script.extra.push(ExtraSection { name: h.to_string(), lines: Vec::new() });
+                    }
+                },
+                Item::Comment(l) | Item::Other(l) if kind == Section::None =>
+                    script.extra.last_mut().unwrap().lines.push(l.to_string()),
+                Item::InfoField(k, v) => script.info.entries.push(InfoEntry::Field(k.to_string(), v.to_string())),
+                Item::Comment(l) if kind == Section::ScriptInfo => script.info.add_comment(&l[1..]),
+                Item::Format(f) => *match kind {
+                    Section::Events => &mut script.event_format,
+                    _               => &mut script.style_format,
+                } = f.iter().map(|f| f.to_string()).collect(),
+                Item::Style(s) => script.styles.push(s),
+                Item::Event(e) => script.events.push(e),
+                Item::FontChunk { name, data } => chunk(&mut script.fonts, name, data),
+                Item::GraphicChunk { name, data } => chunk(&mut script.graphics, name, data),
+                Item::Comment(l) => comments.push(l.to_string()),
+                Item::Other(l) => panic!("unexpected line {:?}", l),
+            }
+        }
+        (script, comments)
+    }
+
+    #[test]
+    fn same_as_sections() {
+        let script = parse_sections(SCRIPT).unwrap();
+        let (pulled, comments) = collect(Parser::new(SCRIPT.as_bytes()));
+
+        assert_eq!(pulled.info.entries, script.info.entries);
+        assert_eq!(pulled.style_format, script.style_format);
+        assert_eq!(pulled.styles, script.styles);
+        assert_eq!(pulled.event_format, script.event_format);
+        assert_eq!(pulled.events, script.events);
+        assert_eq!(pulled.fonts, script.fonts);
+        assert_eq!(pulled.graphics, script.graphics);
+        assert_eq!(pulled.extra, script.extra.iter().map(|e| ExtraSection {
+            name: e.name.clone(),
+            // The pull parser skips blank lines
+            lines: e.lines.iter().filter(|l| !l.is_empty()).cloned().collect(),
+        }).collect::<Vec<_>>());
+
+        let raw: Vec<_> = script.layout.sections.iter()
+            .flat_map(|s| &s.filler)
+            .filter_map(|(_, f)| match f {
+                Filler::Raw(l) if l.starts_with(';') => Some(l.as_str()),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(comments, raw);
+        assert_eq!(comments, ["; styles", "; a comment"]);
+        assert_eq!(pulled.info.entries[0], InfoEntry::Comment(" Script generated by hand".to_string()));
+        assert_eq!(pulled.fonts[0].lines, ["97*D:!", ";GHI"]);
+    }
+
+    #[test]
+    fn bracket_data() {
+        let data: Vec<u8> = (0..20000u32).map(|i| (i.wrapping_mul(2654435761) >> 13) as u8).collect();
+        let lines = uuenc::uuencode(&data);
+        assert!(lines.iter().any(|l| l.starts_with('[')));
+
+        let src = ["[Fonts]\nfontname: a_0.ttf\n", &lines.join("\n"), "\n[Events]\n"].concat();
+        let (script, _) = collect(Parser::new(src.as_bytes()));
+        assert_eq!(script.fonts[0].lines, lines);
+        assert_eq!(script.fonts, parse_sections(&src).unwrap().fonts);
+    }
+
+    #[test]
+    fn same_warnings() {
+        let src = SCRIPT
+            .replace("Title: Test", "Title Test")
+            .replace(",-1,0,0,0,100", ",x,0,0,0,100")
+            .replace("0:00:05.00", "0:00:0x.00");
+
+        let mut warnings = Vec::new();
+        let script = parse_sections_lenient(&src, &mut warnings);
+        let mut p = Parser::lenient(src.as_bytes());
+        let styles = p.by_ref().filter(|i| matches!(i, Ok(Item::Style(_)))).count();
+
+        assert_eq!(styles, script.styles.len());
+        assert_eq!(p.warnings, warnings);
+        assert_eq!(warnings.len(), 3);
+        assert!(Parser::new(src.as_bytes()).any(|i| i.is_err()));
+    }
+}
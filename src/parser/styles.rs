@@ -1,27 +1,127 @@
-use backside::*;
+use alloc::{string::{String, ToString}, vec::Vec};
 
-pub fn parse_style(r: &[u8]) -> Result<Style> {
-  let mut i: usize = 7;
-  assert_eq!(&r[0..5], b"Style");
+use backside_types::*;
 
-  let mut style = Style::default();
+use crate::{offset, split_key, utf8, Mode};
 
-  for f in fields.enumerate() {
-    let st = i;
-    let ed;
+/// Parse a `Style:` line, using the field order of the section's `Format:` line
+///
+/// Columns may come in any order and any of them may be missing, in which
+/// case the value from [`Style::default`] is kept. Unknown columns are ignored.
+pub fn parse_style(r: &[u8], format: &[&[u8]]) -> Result<Style> {
+    style(r, format, &mut Mode::Strict)
+}
+
+/// [`parse_style`], keeping the default for values it can't parse
+///
+/// Those end up in `warnings`. Only fails on lines that aren't `Style:` lines.
+pub fn parse_style_lenient(r: &[u8], format: &[&[u8]], warnings: &mut Vec<Error>) -> Result<Style> {
+    style(r, format, &mut Mode::Lenient(warnings))
+}
+
+pub(crate) fn style(r: &[u8], format: &[&[u8]], mode: &mut Mode) -> Result<Style> {
+    // Blames the field `v`
+    let invalid = |v: &[u8]| {
+        let st = offset(r, v);
+        Error::spanned(ErrorKind::StyleInvalid, st..st + v.len(), &String::from_utf8_lossy(v))
+    };
 
-    comma_sep!(r[i], ed);
+    let fields = match split_key(r) {
+        Some((b"Style", v)) => v,
+        _ => return Err(invalid(r)),
+    };
 
-    let v = &r[st..ed]
-    match f {
-      b"Name" => style.name = v,
-      b"Fontname" => style.font_name = v,
-      b"Fontsize" => style.font_size = v,
-      &_ => {
-        return Error::StyleInvalid;
-      }
+    let mut style = Style::default();
+
+    for (k, v) in format.iter().zip(fields.split(|&c| c == b',')) {
+        let b = v.trim_ascii();
+        let v = utf8(b).map_err(|_| invalid(b))?;
+
+        // Bad values keep the default in lenient mode
+        macro_rules! or_skip {
+            ($e:expr) => {
+                match $e {
+                    Some(v) => v,
+                    None => {
+                        mode.warn(invalid(b))?;
+                        continue;
+                    },
+                }
+            };
+        }
+        macro_rules! parse {
+            () => {
+                or_skip!(v.parse::<i32>().ok()) != 0
+            };
+            (color) => {
+                or_skip!(Colour::parse(v))
+            };
+            ($type:ident) => {
+                or_skip!(v.parse::<$type>().ok())
+            };
+        }
+
+        field! { k {
+            b"Name"                             => style.name = v.to_string(),
+            b"Fontname"                         => style.font_name = v.to_string(),
+            b"Fontsize"                         => style.font_size = parse!(f32),
+            b"PrimaryColour"                    => style.primary_color = parse!(color),
+            b"SecondaryColour"                  => style.secondary_color = parse!(color),
+            b"OutlineColour" | b"TertiaryColour" => style.outline_color = parse!(color),
+            b"BackColour"                       => style.back_color = parse!(color),
+            b"Bold"                             => style.bold = parse!(),
+            b"Italic"                           => style.italic = parse!(),
+            b"Underline"                        => style.underline = parse!(),
+            b"StrikeOut"                        => style.strikeout = parse!(),
+            b"ScaleX"                           => style.scale_x = parse!(f32),
+            b"ScaleY"                           => style.scale_y = parse!(f32),
+            b"Spacing"                          => style.spacing = parse!(f32),
+            b"Angle"                            => style.angle = parse!(f32),
+            b"BorderStyle"                      => style.border_style = parse!(i8),
+            b"Outline"                          => style.outline = parse!(f32),
+            b"Shadow"                           => style.shadow = parse!(f32),
+            b"Alignment"                        => style.alignment = parse!(i8),
+            b"MarginL"                          => style.margin_l = parse!(i32),
+            b"MarginR"                          => style.margin_r = parse!(i32),
+            b"MarginV"                          => style.margin_v = parse!(i32),
+            b"AlphaLevel"                       => style.alpha_level = parse!(i32),
+            b"Encoding"                         => style.encoding = parse!(i32),
+        }}
     }
-  }
 
-  Ok(style)
+    Ok(style)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn columns() {
+        // Any order, unknown columns skipped, missing ones left at their default
+        let format: &[&[u8]] = &[b"Fontsize", b"Name", b"Blah", b"marginv", b"PrimaryColour", b"Bold"];
+        let s = parse_style(b"Style: 24.5, Sign ,x,42,&H8000FF00,-1", format).unwrap();
+        assert_eq!((s.name.as_str(), s.font_size, s.margin_v, s.bold), ("Sign", 24.5, 42, true));
+        assert_eq!(s.primary_color, Colour::rgba(0, 0xFF, 0, 0x80));
+        assert_eq!((s.font_name.as_str(), s.margin_l), (Style::default().font_name.as_str(), Style::default().margin_l));
+
+        // SSA's columns, with decimal colours
+        let s = parse_style(b"Style: Default,Arial,20,16777215,65535,255,0,0,-1,1,2,3,2,10,20,30,0,1", SSA_STYLE_FORMAT).unwrap();
+        assert_eq!((s.primary_color, s.outline_color), (Colour(0xFFFFFF), Colour(0xFF)));
+        assert_eq!((s.italic, s.border_style, s.margin_r, s.alpha_level, s.encoding), (true, 1, 20, 0, 1));
+    }
+
+    #[test]
+    fn errors() {
+        let format: &[&[u8]] = &[b"Name", b"Fontsize", b"Outline"];
+        let e = parse_style(b"Style: Default,big,2", format).unwrap_err();
+        assert_eq!((e.kind, e.span, e.snippet.as_deref()), (ErrorKind::StyleInvalid, Some(15..18), Some("big")));
+        assert_eq!(parse_style(b"Dialogue: Default,20,2", format).unwrap_err().kind, ErrorKind::StyleInvalid);
+
+        let mut warnings = Vec::new();
+        let s = parse_style_lenient(b"Style: Default,big,x", format, &mut warnings).unwrap();
+        assert_eq!((s.font_size, s.outline), (Style::default().font_size, Style::default().outline));
+        let spans: Vec<_> = warnings.iter().map(|e| e.span.clone()).collect();
+        assert_eq!(spans, [Some(15..18), Some(19..20)]);
+    }
 }
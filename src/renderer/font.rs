@@ -0,0 +1,506 @@
+//! Font files, and finding the one a family name stands for
+
+use alloc::{borrow::ToOwned, string::String, sync::Arc, vec::Vec};
+
+use backside_types::Script;
+use ttf_parser::{name_id, Face, GlyphId};
+
+use crate::outline::{Builder, Outline};
+
+/// How many characters of a name GDI looks at, `LF_FACESIZE` less the NUL
+const FACE_SIZE: usize = 31;
+
+/// A face out of a font file
+#[derive(Clone, Debug)]
+pub struct Font {
+    data: Data,
+    index: u32,
+    /// Family names, in every language the font has them in
+    pub families: Vec<String>,
+    /// Full names, like `Arial Bold Italic`
+    pub full_names: Vec<String>,
+    pub postscript_name: Option<String>,
+    /// `100` to `900`, `400` being regular and `700` bold
+    pub weight: u16,
+    pub italic: bool,
+    /// Ranges of the characters it has glyphs for, sorted
+    coverage: Vec<(u32, u32)>,
+    /// `usWinAscent + usWinDescent`, the height GDI gives to a font size
+    height: f32,
+    ascent: f32,
+    /// The names as [`name_key`] gives them, for [`Font::is_family`] and
+    /// [`Font::is_face`]
+    family_keys: Vec<String>,
+    face_keys: Vec<String>,
+}
+
+/// Where the bytes of a [`Font`] are
+#[derive(Clone, Debug)]
+enum Data {
+    Memory(Arc<[u8]>),
+    #[cfg(feature = "std")]
+    File(Arc<File>),
+}
+
+/// A font file, only read once one of its fonts gets used
+#[cfg(feature = "std")]
+#[derive(Debug)]
+struct File {
+    path: std::path::PathBuf,
+    data: std::sync::OnceLock<Option<Arc<[u8]>>>,
+}
+
+impl Font {
+    /// Every face of a font file or collection that parses
+    pub fn load(data: Arc<[u8]>) -> Vec<Font> {
+        Font::faces(&data, || Data::Memory(data.clone()))
+    }
+
+    /// Every face of the font file at `path`, which gets read again
+    /// when one of them is first used
+    #[cfg(feature = "std")]
+    pub fn load_file(path: impl Into<std::path::PathBuf>) -> std::io::Result<Vec<Font>> {
+        let path = path.into();
+        let data = std::fs::read(&path)?;
+        let file = Arc::new(File { path, data: std::sync::OnceLock::new() });
+        Ok(Font::faces(&data, || Data::File(file.clone())))
+    }
+
+    fn faces(data: &[u8], source: impl Fn() -> Data) -> Vec<Font> {
+        let n = ttf_parser::fonts_in_collection(data).unwrap_or(1);
+        (0..n).filter_map(|i| Font::new(data, source(), i)).collect()
+    }
+
+    fn new(bytes: &[u8], data: Data, index: u32) -> Option<Font> {
+        let face = Face::parse(bytes, index).ok()?;
+        let names = |id| face.names().into_iter()
+            .filter(|n| n.name_id == id)
+            .filter_map(|n| decode_name(n.name, n.is_unicode()))
+            .fold(Vec::new(), |mut v: Vec<String>, n| {
+                if !v.contains(&n) {
+                    v.push(n);
+                }
+                v
+            });
+        let (ascent, descent) = match face.tables().os2 {
+            Some(os2) if os2.windows_ascender() > 0 => (os2.windows_ascender() as f32, -os2.windows_descender() as f32),
+            _ => (face.ascender() as f32, -face.descender() as f32),
+        };
+        // Some fonts give `usWeightClass` in hundreds, as `1` to `9`
+        let (weight, italic) = match face.tables().os2 {
+            Some(os2) => match os2.weight().to_number() {
+                w @ 1..=9 => (w * 100, os2.style() != ttf_parser::Style::Normal),
+                w => (w, os2.style() != ttf_parser::Style::Normal),
+            },
+            None => (if face.is_bold() { 700 } else { 400 }, face.is_italic()),
+        };
+        let families = names(name_id::FAMILY);
+        let full_names = names(name_id::FULL_NAME);
+        let postscript_name = names(name_id::POST_SCRIPT_NAME).into_iter().next();
+        Some(Font {
+            data,
+            index,
+            family_keys: families.iter().map(|n| name_key(n)).collect(),
+            face_keys: full_names.iter().chain(&postscript_name).map(|n| name_key(n)).collect(),
+            families,
+            full_names,
+            postscript_name,
+            weight,
+            italic,
+            coverage: coverage(&face),
+            height: (ascent + descent).max(1.0),
+            ascent,
+        })
+    }
+
+    fn bytes(&self) -> Option<&[u8]> {
+        match &self.data {
+            Data::Memory(data) => Some(data),
+            #[cfg(feature = "std")]
+            Data::File(file) => file.data.get_or_init(|| std::fs::read(&file.path).ok().map(Into::into)).as_deref(),
+        }
+    }
+
+    /// `None` if it's a file that can't be read anymore
+    pub fn face(&self) -> Option<Face<'_>> {
+        Face::parse(self.bytes()?, self.index).ok()
+    }
+
+    /// Pixels per font unit at font size `size`
+    pub fn scale(&self, size: f32) -> f32 {
+        size / self.height
+    }
+
+    /// Ascent and descent at font size `size`, which add up to it
+    pub fn metrics(&self, size: f32) -> (f32, f32) {
+        let s = self.scale(size);
+        (self.ascent * s, (self.height - self.ascent) * s)
+    }
+
+    /// Whether it has a glyph for `c`
+    pub fn covers(&self, c: char) -> bool {
+        let c = c as u32;
+        match self.coverage.binary_search_by_key(&c, |r| r.0) {
+            Ok(_) => true,
+            Err(i) => i > 0 && c <= self.coverage[i - 1].1,
+        }
+    }
+
+    /// Whether `name` is one of the family names, the way VSFilter
+    /// compares them
+    pub fn is_family(&self, name: &str) -> bool {
+        self.family_keys.contains(&name_key(name))
+    }
+
+    /// Whether `name` is one of the full names or the PostScript name,
+    /// which stand for this face only
+    pub fn is_face(&self, name: &str) -> bool {
+        self.face_keys.contains(&name_key(name))
+    }
+
+    /// Outline of `id` in font units, y going up, for being drawn at
+    /// `detail` pixels per unit
+    pub(crate) fn outline(face: &Face<'_>, id: GlyphId, detail: f32) -> Outline {
+        let mut b = Builder::new(detail);
+        face.outline_glyph(id, &mut b);
+        b.finish()
+    }
+}
+
+/// What a [`FontProvider`] found for a family name
+#[derive(Clone, Copy, Debug)]
+pub struct Selected<'a> {
+    pub font: &'a Font,
+    /// Whether it isn't of the family asked for, but stands in for it
+    pub fallback: bool,
+}
+
+/// Finds the fonts `\fn` and the styles' `Fontname` stand for
+pub trait FontProvider {
+    /// Font to draw `family` with, the closest to `weight` and `italic`,
+    /// `None` if there's none at all
+    ///
+    /// `family` is the name as scripts give it, maybe with the `@` of
+    /// vertical fonts.
+    fn select(&self, family: &str, weight: u32, italic: bool) -> Option<Selected<'_>>;
+
+    /// Font to draw `c` with when the one selected doesn't have it, the
+    /// closest to `weight` and `italic`
+    ///
+    /// Without one, `c` gets drawn as the selected font's missing glyph,
+    /// which is all there is by default.
+    fn fallback(&self, c: char, weight: u32, italic: bool) -> Option<&Font> {
+        let _ = (c, weight, italic);
+        None
+    }
+}
+
+/// A font that was asked for and not found, or a character it didn't
+/// have, and what was used instead
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Fallback {
+    /// As the script gives it
+    pub family: String,
+    /// The character missing from `family`, `None` if the font itself was
+    pub character: Option<char>,
+    /// First family name of the font used instead, `None` if there was none
+    pub used: Option<String>,
+}
+
+/// Fonts given to the renderer or found in directories
+///
+/// Family names match like in VSFilter: ignoring case and a leading `@`,
+/// and only on their first 31 characters. Full and PostScript names match
+/// a single face. Fonts embedded in the script come first: a family
+/// they have is only looked for among them.
+///
+/// Characters a font doesn't have are looked for in the families of
+/// [`add_fallback`](Self::add_fallback) in turn, then in the
+/// [default](Self::set_default) one, then in any font.
+#[derive(Clone, Default, Debug)]
+pub struct FontDatabase {
+    /// And whether they're embedded
+    fonts: Vec<(Font, bool)>,
+    default: Option<String>,
+    fallbacks: Vec<String>,
+}
+
+impl FontDatabase {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add the fonts of a TrueType / OpenType file or collection, giving
+    /// how many there were
+    pub fn add_font(&mut self, data: impl Into<Arc<[u8]>>) -> usize {
+        self.add(Font::load(data.into()), false)
+    }
+
+    /// Add the fonts embedded in the `[Fonts]` section of `script`, giving
+    /// how many there were
+    ///
+    /// Those that can't be decoded are skipped.
+    pub fn add_script_fonts(&mut self, script: &Script) -> usize {
+        script.fonts.iter()
+            .filter_map(|f| f.data().ok())
+            .map(|data| self.add(Font::load(data.into()), true))
+            .sum()
+    }
+
+    /// Add the `.ttf`, `.otf`, `.ttc` and `.otc` files in `dir` and its
+    /// subdirectories, giving how many fonts there were
+    ///
+    /// Files get read whole for the names of their fonts, and read again when
+    /// those first get used. Files that don't parse are skipped, and so are
+    /// entries that can't be read, which end up in `errors`: only `dir` itself
+    /// not being readable fails the scan.
+    #[cfg(feature = "std")]
+    pub fn scan(
+        &mut self,
+        dir: impl AsRef<std::path::Path>,
+        errors: &mut Vec<(std::path::PathBuf, std::io::Error)>,
+    ) -> std::io::Result<usize> {
+        let dir = dir.as_ref();
+        let mut seen = std::collections::HashSet::new();
+        seen.insert(dir.canonicalize()?);
+        self.scan_dir(dir, &mut seen, errors)
+    }
+
+    /// [`scan`](Self::scan), skipping the directories in `seen` that symlinks
+    /// lead back to
+    #[cfg(feature = "std")]
+    fn scan_dir(
+        &mut self,
+        dir: &std::path::Path,
+        seen: &mut std::collections::HashSet<std::path::PathBuf>,
+        errors: &mut Vec<(std::path::PathBuf, std::io::Error)>,
+    ) -> std::io::Result<usize> {
+        let mut paths = Vec::new();
+        for e in std::fs::read_dir(dir)? {
+            match e {
+                Ok(e)  => paths.push(e.path()),
+                Err(e) => errors.push((dir.to_owned(), e)),
+            }
+        }
+        // The same fonts each time, whatever order the system lists them in
+        paths.sort();
+        let mut n = 0;
+        for path in paths {
+            if path.is_dir() {
+                let res = path.canonicalize().and_then(|c| match seen.insert(c) {
+                    true  => self.scan_dir(&path, seen, errors),
+                    false => Ok(0),
+                });
+                match res {
+                    Ok(m)  => n += m,
+                    Err(e) => errors.push((path, e)),
+                }
+                continue;
+            }
+            let ext = path.extension().and_then(|e| e.to_str()).map(str::to_ascii_lowercase);
+            if matches!(ext.as_deref(), Some("ttf" | "otf" | "ttc" | "otc")) {
+                match Font::load_file(path.clone()) {
+                    Ok(fonts) => n += self.add(fonts, false),
+                    Err(e)    => errors.push((path, e)),
+                }
+            }
+        }
+        Ok(n)
+    }
+
+    /// Fall back on `family` rather than on the closest font of any family
+    pub fn set_default(&mut self, family: &str) {
+        self.default = Some(family.to_owned());
+    }
+
+    /// Look for characters fonts don't have in `family`, after the
+    /// families added before
+    pub fn add_fallback(&mut self, family: &str) {
+        self.fallbacks.push(family.to_owned());
+    }
+
+    pub fn fonts(&self) -> impl Iterator<Item = &Font> {
+        self.fonts.iter().map(|(f, _)| f)
+    }
+
+    fn add(&mut self, fonts: Vec<Font>, embedded: bool) -> usize {
+        let n = fonts.len();
+        self.fonts.extend(fonts.into_iter().map(|f| (f, embedded)));
+        n
+    }
+
+    /// The closest to `weight` and `italic` of the fonts `matches`, only
+    /// among the embedded ones if some are
+    fn closest(&self, matches: impl Fn(&Font) -> bool, weight: u32, italic: bool) -> Option<&Font> {
+        let embedded = self.fonts.iter().any(|(f, e)| *e && matches(f));
+        self.fonts.iter()
+            .filter(|(f, e)| (*e || !embedded) && matches(f))
+            .map(|(f, _)| f)
+            .min_by_key(|f| (f.weight as i32 - weight as i32).abs() + if f.italic == italic { 0 } else { 1000 })
+    }
+}
+
+impl FontProvider for FontDatabase {
+    fn select(&self, family: &str, weight: u32, italic: bool) -> Option<Selected<'_>> {
+        let found = self.closest(|f| f.is_family(family), weight, italic)
+            .or_else(|| self.closest(|f| f.is_face(family), weight, italic));
+        if let Some(font) = found {
+            return Some(Selected { font, fallback: false });
+        }
+        let default = self.default.as_ref().and_then(|d| self.closest(|f| f.is_family(d), weight, italic));
+        default.or_else(|| self.closest(|_| true, weight, italic)).map(|font| Selected { font, fallback: true })
+    }
+
+    fn fallback(&self, c: char, weight: u32, italic: bool) -> Option<&Font> {
+        self.fallbacks.iter()
+            .chain(&self.default)
+            .find_map(|family| self.closest(|f| f.is_family(family) && f.covers(c), weight, italic))
+            .or_else(|| self.closest(|f| f.covers(c), weight, italic))
+    }
+}
+
+/// Ranges of the characters `face` has glyphs for
+fn coverage(face: &Face<'_>) -> Vec<(u32, u32)> {
+    let mut chars = Vec::new();
+    let subtables = face.tables().cmap.into_iter().flat_map(|cmap| cmap.subtables);
+    for table in subtables.filter(|t| t.is_unicode()) {
+        table.codepoints(|c| {
+            if table.glyph_index(c).is_some_and(|id| id.0 != 0) {
+                chars.push(c);
+            }
+        });
+    }
+    chars.sort_unstable();
+    chars.dedup();
+    let mut ranges: Vec<(u32, u32)> = Vec::new();
+    for c in chars {
+        match ranges.last_mut() {
+            Some(r) if r.1 + 1 == c => r.1 = c,
+            _ => ranges.push((c, c)),
+        }
+    }
+    ranges
+}
+
+/// `name` the way VSFilter compares it: GDI's first 31 characters, without
+/// the `@` of vertical fonts, ignoring case
+fn name_key(name: &str) -> String {
+    let name = name.trim();
+    name.strip_prefix('@').unwrap_or(name).chars().take(FACE_SIZE).flat_map(char::to_lowercase).collect()
+}
+
+/// A `name` table string, UTF-16BE if `unicode`, otherwise taken as
+/// Mac Roman's ASCII subset
+fn decode_name(data: &[u8], unicode: bool) -> Option<String> {
+    let s: String = if unicode {
+        let units = data.chunks_exact(2).map(|c| u16::from_be_bytes([c[0], c[1]]));
+        char::decode_utf16(units).collect::<Result<_, _>>().ok()?
+    } else {
+        data.iter().map(|&b| if b.is_ascii() { b as char } else { '?' }).collect()
+    };
+    Some(s).filter(|s| !s.is_empty())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn font(family: &str, full: &str, weight: u16, italic: bool) -> Font {
+        Font {
+            data: Data::Memory(Arc::from([])),
+            index: 0,
+            families: alloc::vec![family.into()],
+            full_names: alloc::vec![full.into()],
+            postscript_name: None,
+            weight,
+            italic,
+            coverage: alloc::vec![(0x20, 0x7e)],
+            height: 1.0,
+            ascent: 1.0,
+            family_keys: alloc::vec![name_key(family)],
+            face_keys: alloc::vec![name_key(full)],
+        }
+    }
+
+    #[test]
+    fn selection() {
+        let mut db = FontDatabase::new();
+        db.fonts = alloc::vec![
+            (font("Sans", "Sans", 400, false), false),
+            (font("Serif", "Serif", 400, false), false),
+            (font("Serif", "Serif Bold Italic", 700, true), false),
+            (font("A Family Name Longer Than GDI's Limit", "Long", 400, false), false),
+        ];
+        fn select<'a>(db: &'a FontDatabase, name: &str, weight: u32, italic: bool) -> Option<(&'a str, bool)> {
+            db.select(name, weight, italic).map(|s| (s.font.full_names[0].as_str(), s.fallback))
+        }
+
+        // Italics matter more than weight
+        assert_eq!(select(&db, "serif", 700, false), Some(("Serif", false)));
+        assert_eq!(select(&db, " @SERIF", 400, true), Some(("Serif Bold Italic", false)));
+        assert_eq!(select(&db, "Serif", 400, false), Some(("Serif", false)));
+        // A full name picks the face whatever the style
+        assert_eq!(select(&db, "serif bold italic", 400, false), Some(("Serif Bold Italic", false)));
+        assert_eq!(select(&db, "A Family Name Longer Than GDI's", 400, false), Some(("Long", false)));
+
+        assert_eq!(select(&db, "Mono", 400, false), Some(("Sans", true)));
+        assert_eq!(select(&db, "Mono", 700, true), Some(("Serif Bold Italic", true)));
+        db.set_default("Serif");
+        assert_eq!(select(&db, "Mono", 400, false), Some(("Serif", true)));
+
+        // Embedded fonts of a family hide the others
+        db.fonts.push((font("Serif", "Embedded Serif", 100, false), true));
+        assert_eq!(select(&db, "Serif", 400, false), Some(("Embedded Serif", false)));
+        assert_eq!(select(&db, "Sans", 400, false), Some(("Sans", false)));
+
+        assert!(FontDatabase::new().select("Sans", 400, false).is_none());
+    }
+
+    #[cfg(all(feature = "std", unix))]
+    #[test]
+    fn scan() {
+        use std::{fs, os::unix::fs::symlink};
+
+        let dir = std::env::temp_dir().join(alloc::format!("backside-scan-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(dir.join("sub")).unwrap();
+        fs::write(dir.join("sub/junk.ttf"), b"not a font").unwrap();
+        // A way back up, and a font that isn't there
+        symlink(&dir, dir.join("sub/up")).unwrap();
+        symlink(dir.join("gone.otf"), dir.join("broken.ttf")).unwrap();
+
+        let mut db = FontDatabase::new();
+        let mut errors = Vec::new();
+        assert_eq!(db.scan(&dir, &mut errors).unwrap(), 0);
+        let failed: Vec<_> = errors.iter().map(|(p, e)| (p.strip_prefix(&dir).unwrap(), e.kind())).collect();
+        assert_eq!(failed, [(std::path::Path::new("broken.ttf"), std::io::ErrorKind::NotFound)]);
+
+        assert!(db.scan(dir.join("nope"), &mut errors).is_err());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn fallback() {
+        let mut db = FontDatabase::new();
+        let mut cjk = font("CJK", "CJK", 400, false);
+        cjk.coverage = alloc::vec![(0x20, 0x20), (0x3000, 0x30ff), (0x4e00, 0x9fff)];
+        let mut emoji = font("Emoji", "Emoji", 400, false);
+        emoji.coverage = alloc::vec![(0x20, 0x20), (0x1f300, 0x1faff)];
+        db.fonts = alloc::vec![(font("Sans", "Sans", 400, false), false), (cjk, false), (emoji.clone(), false)];
+        let fallback = |db: &FontDatabase, c| db.fallback(c, 400, false).map(|f| f.full_names[0].clone());
+
+        assert!(db.fonts[1].0.covers('\u{3042}') && db.fonts[1].0.covers(' ') && !db.fonts[1].0.covers('!'));
+        assert_eq!(fallback(&db, '\u{3042}').as_deref(), Some("CJK"));
+        assert_eq!(fallback(&db, '\u{1f600}').as_deref(), Some("Emoji"));
+        assert_eq!(fallback(&db, '\u{10000}'), None);
+
+        // The chain first, then embedded fonts before the others
+        emoji.full_names[0] = "Embedded Emoji".into();
+        db.fonts.push((emoji, true));
+        assert_eq!(fallback(&db, ' ').as_deref(), Some("Embedded Emoji"));
+        db.add_fallback("Nope");
+        db.add_fallback("cjk");
+        assert_eq!(fallback(&db, ' ').as_deref(), Some("CJK"));
+        assert_eq!(fallback(&db, '\u{1f600}').as_deref(), Some("Embedded Emoji"));
+    }
+}
@@ -0,0 +1,531 @@
+//! Laying text out: glyphs, bidirectional text, line breaks and wrapping
+
+use alloc::vec::Vec;
+use core::ops::Range;
+
+use backside_types::WrapStyle;
+use unicode_bidi::{BidiInfo, Level};
+use unicode_script::{Script, UnicodeScript};
+
+use crate::font::Font;
+use crate::outline::{self, Outline, Point};
+use crate::shape::{self, Shaper, Shaping};
+use crate::state::State;
+
+/// Slant of synthetic italics
+const ITALIC_SLANT: f32 = 0.2;
+
+/// First character `@` fonts lay on their side, like libass
+const VERTICAL_FROM: char = '\u{2f1}';
+
+/// What a [`Glyph`] stands for
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Kind {
+    /// A character or drawing
+    Char,
+    /// A space lines may get wrapped at
+    Space,
+    /// `\N`
+    HardBreak,
+    /// `\n`, a space unless the wrapping style is `2`
+    SoftBreak,
+}
+
+/// A character or drawing, in script pixels
+#[derive(Clone, Debug)]
+pub struct Glyph {
+    pub kind: Kind,
+    /// Relative to the pen position on the baseline, y going down
+    pub outline: Outline,
+    pub advance: f32,
+    pub ascent: f32,
+    pub descent: f32,
+    /// Index of the [`State`] it's drawn with
+    pub state: usize,
+    /// Bidi embedding level, odd for right-to-left text
+    pub level: u8,
+    /// Underline then strikeout, as their top and thickness
+    pub decorations: [Option<(f32, f32)>; 2],
+    /// Pen position on its line, set by [`wrap`]
+    pub x: f32,
+}
+
+impl Glyph {
+    fn empty(kind: Kind, state: usize, ascent: f32, descent: f32) -> Self {
+        Self {
+            kind,
+            outline: Outline::default(),
+            advance: 0.0,
+            ascent,
+            descent,
+            state,
+            level: 0,
+            decorations: [None, None],
+            x: 0.0,
+        }
+    }
+
+    fn is_space(&self) -> bool {
+        matches!(self.kind, Kind::Space | Kind::SoftBreak)
+    }
+}
+
+/// What a [`Span`] of an event's text stands for
+#[derive(Clone, Copy, Debug)]
+pub enum Source<'a> {
+    /// Text, in the font it's drawn with if there's one
+    Text(Option<&'a Font>),
+    /// A drawing, its commands
+    Drawing(&'a str),
+    /// `\N`, or `\n` if `hard` is false, with the font around it
+    Break(Option<&'a Font>, bool),
+}
+
+/// Part of an event's text, drawn with the [`State`] at index `state`
+#[derive(Clone, Debug)]
+pub struct Span<'a> {
+    pub range: Range<usize>,
+    pub state: usize,
+    pub source: Source<'a>,
+}
+
+/// Glyphs of an event's `text`, in logical order
+///
+/// Bidi levels come from the Unicode Bidirectional Algorithm, with bracket
+/// pairs, run over the whole text like libass does, so that override
+/// blocks don't break runs up; `\N` separates paragraphs. They take the
+/// direction of their first strong character if `auto`, and are
+/// left-to-right otherwise. Runs get shaped with the text around them, so
+/// letters join across style changes.
+pub fn glyphs(text: &str, spans: &[Span<'_>], states: &[State], auto: bool, shaper: &Shaper, detail: f32) -> Vec<Glyph> {
+    let bidi = BidiInfo::new(text, (!auto).then_some(Level::ltr()));
+    let level = |at: usize| bidi.levels.get(at).map_or(0, |l| l.number());
+    let mut out = Vec::new();
+    for span in spans {
+        let (state, si) = (&states[span.state], span.state);
+        let level = level(span.range.start);
+        match span.source {
+            Source::Drawing(d) => out.push(Glyph { level, ..drawing(d, state, si, detail) }),
+            Source::Break(font, hard) => out.push(Glyph { level, ..line_break(font, hard, state, si) }),
+            Source::Text(None) => {},
+            Source::Text(Some(font)) => {
+                for (range, level) in items(text, span.range.clone(), &bidi.levels, shaper.mode == Shaping::Complex) {
+                    let run = Run { font, text, range, level, si };
+                    shape_run(&run, state, shaper, detail, &mut out);
+                }
+            },
+        }
+    }
+    out
+}
+
+/// `range` of `text` cut where its bidi level changes, and its script if
+/// `by_script`, with the level of each part
+///
+/// Characters common to scripts, like spaces and punctuation, go with
+/// those around them.
+fn items(text: &str, range: Range<usize>, levels: &[Level], by_script: bool) -> Vec<(Range<usize>, u8)> {
+    let common = |s: Script| matches!(s, Script::Common | Script::Inherited | Script::Unknown);
+    let mut out: Vec<(Range<usize>, u8, Script)> = Vec::new();
+    for (i, c) in text[range.clone()].char_indices() {
+        let at = range.start + i;
+        let (level, script) = (levels[at].number(), if by_script { c.script() } else { Script::Common });
+        match out.last_mut() {
+            Some((r, l, s)) if *l == level && (common(script) || common(*s) || *s == script) => {
+                r.end = at + c.len_utf8();
+                if common(*s) {
+                    *s = script;
+                }
+            },
+            _ => out.push((at..at + c.len_utf8(), level, script)),
+        }
+    }
+    out.into_iter().map(|(r, l, _)| (r, l)).collect()
+}
+
+/// Text in the same font, bidi level and script, and drawn with the same
+/// state, the one at index `si`
+struct Run<'a> {
+    font: &'a Font,
+    /// All of the event's text, for context
+    text: &'a str,
+    range: Range<usize>,
+    level: u8,
+    si: usize,
+}
+
+/// Glyphs of `run` drawn with `state`, shaped with `shaper`
+///
+/// `detail` is the number of frame pixels per script pixel.
+fn shape_run(run: &Run<'_>, state: &State, shaper: &Shaper, detail: f32, out: &mut Vec<Glyph>) {
+    let (font, text) = (run.font, run.text);
+    let Some(face) = font.face() else {
+        return;
+    };
+    let scale = font.scale(state.size);
+    let (sx, sy) = (scale * state.scale.0, scale * state.scale.1);
+    let (ascent, descent) = font.metrics(state.size);
+    let (ascent, descent) = (ascent * state.scale.1, descent * state.scale.1);
+    let slant = if state.italic && !font.italic { ITALIC_SLANT } else { 0.0 };
+    let vertical = state.font.trim_start().starts_with('@');
+    let (above, below) = (ascent / state.scale.1 / scale, descent / state.scale.1 / scale);
+
+    let decorations = [
+        state.underline.then(|| face.underline_metrics()).map(|m| match m {
+            Some(m) => (-m.position as f32 * sy, m.thickness as f32 * sy),
+            None    => (descent / 4.0, state.size * state.scale.1 / 20.0),
+        }),
+        state.strikeout.then(|| face.strikeout_metrics()).map(|m| match m {
+            Some(m) => (-(m.position + m.thickness) as f32 * sy, m.thickness as f32 * sy),
+            None    => (-ascent / 3.0, state.size * state.scale.1 / 20.0),
+        }),
+    ];
+
+    for g in shape::shape(&face, text, run.range.clone(), run.level % 2 == 1, shaper, state) {
+        let c = text[g.cluster..].chars().next().unwrap_or(' ');
+        let kind = if c == ' ' { Kind::Space } else { Kind::Char };
+        let mut outline = Font::outline(&face, g.id, sx.max(sy) * detail);
+        let mut advance = g.advance as f32;
+        if vertical && c >= VERTICAL_FROM {
+            // Turned a quarter anticlockwise, the em box staying in place
+            advance = face.glyph_ver_advance(g.id).map_or(font.scale(1.0).recip(), f32::from);
+            outline.map(|p| Point::new(above - p.y, p.x - below));
+        }
+        let (dx, dy) = (g.offset.0 as f32, g.offset.1 as f32);
+        outline.map(|p| Point::new((p.x + dx + (p.y + dy) * slant) * sx, -(p.y + dy) * sy));
+        let advance = advance * sx + state.spacing * state.scale.0;
+        out.push(Glyph {
+            outline,
+            advance,
+            decorations,
+            level: run.level,
+            ..Glyph::empty(kind, run.si, ascent, descent)
+        });
+    }
+}
+
+/// Glyph of the drawing `d`, at `state`'s `\p` level
+///
+/// Like libass, its bottom sits `\pbo` pixels below the baseline.
+pub fn drawing(d: &str, state: &State, si: usize, detail: f32) -> Glyph {
+    let (sx, sy) = state.scale;
+    let mut outline = outline::drawing(d, state.drawing, sx.max(sy) * detail);
+    outline.map(|p| Point::new(p.x * sx, p.y * sy));
+    let Some((lo, hi)) = outline.bounds() else {
+        return Glyph::empty(Kind::Char, si, 0.0, 0.0);
+    };
+    let descent = state.pbo * sy;
+    let ascent = hi.y - lo.y - descent;
+    outline.map(|p| Point::new(p.x, p.y - ascent));
+    Glyph {
+        outline,
+        advance: hi.x - lo.x,
+        ..Glyph::empty(Kind::Char, si, ascent, descent)
+    }
+}
+
+/// `\N`, or `\n` if `hard` is false, with the height of `state`'s font
+pub fn line_break(font: Option<&Font>, hard: bool, state: &State, si: usize) -> Glyph {
+    let (ascent, descent) = font.map(|f| f.metrics(state.size)).unwrap_or((state.size, 0.0));
+    let kind = if hard { Kind::HardBreak } else { Kind::SoftBreak };
+    let mut g = Glyph::empty(kind, si, ascent * state.scale.1, descent * state.scale.1);
+    if !hard {
+        // Wide as a space, should it be one
+        let space = font.and_then(|f| {
+            let face = f.face()?;
+            Some(face.glyph_hor_advance(face.glyph_index(' ')?)? as f32 * f.scale(state.size))
+        });
+        g.advance = space.unwrap_or(0.0) * state.scale.0 + state.spacing * state.scale.0;
+    }
+    g
+}
+
+/// Glyphs `start..end` of a line, and its size
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Line {
+    pub start: usize,
+    pub end: usize,
+    pub width: f32,
+    pub ascent: f32,
+    pub descent: f32,
+}
+
+/// Break `glyphs` into lines no wider than `max` if `style` allows it,
+/// setting where each glyph sits on its line
+pub fn wrap(glyphs: &mut [Glyph], max: f32, style: WrapStyle) -> Vec<Line> {
+    // Pen position before each glyph
+    let mut pos = Vec::with_capacity(glyphs.len() + 1);
+    let mut x = 0.0;
+    for g in glyphs.iter_mut() {
+        if g.kind == Kind::SoftBreak && style == WrapStyle::None {
+            g.kind = Kind::HardBreak;
+        }
+        if g.kind == Kind::HardBreak {
+            g.advance = 0.0;
+        }
+        pos.push(x);
+        x += g.advance;
+    }
+    pos.push(x);
+    let space: Vec<bool> = glyphs.iter().map(Glyph::is_space).collect();
+
+    // Trailing spaces don't count
+    let width = |s: usize, mut e: usize| {
+        while e > s && space[e - 1] {
+            e -= 1;
+        }
+        pos[e] - pos[s]
+    };
+
+    let mut ranges = Vec::new();
+    let mut start = 0;
+    for end in (0..=glyphs.len()).filter(|&i| i == glyphs.len() || glyphs[i].kind == Kind::HardBreak) {
+        let first = ranges.len();
+        if style == WrapStyle::None {
+            ranges.push((start, end));
+        } else {
+            // Greedy: break at the last space before what doesn't fit
+            let mut s = start;
+            let mut brk = None;
+            let spaces = (start..end).filter(|&i| space[i]).chain([end]);
+            for e in spaces {
+                if let Some(k) = brk.filter(|_| width(s, e) > max) {
+                    ranges.push((s, k));
+                    s = k + 1;
+                }
+                brk = (e < end).then_some(e);
+            }
+            ranges.push((s, end));
+        }
+        if matches!(style, WrapStyle::Smart | WrapStyle::SmartLower) {
+            balance(&mut ranges[first..], &width, &space, max, style);
+        }
+        start = end + 1;
+    }
+
+    ranges.into_iter()
+        .map(|(s, e)| {
+            for (g, x) in glyphs[s..e].iter_mut().zip(&pos[s..e]) {
+                g.x = x - pos[s];
+            }
+            // Empty lines get the height of the break around them
+            let metrics = match () {
+                _ if s < e => &glyphs[s..e],
+                _ if e < glyphs.len() => &glyphs[e..=e],
+                _ => &glyphs[s.saturating_sub(1)..s],
+            };
+            let ascent = metrics.iter().map(|g| g.ascent).fold(0.0, f32::max);
+            let descent = metrics.iter().map(|g| g.descent).fold(0.0, f32::max);
+            Line { start: s, end: e, width: width(s, e), ascent, descent }
+        })
+        .collect()
+}
+
+/// Put the glyphs of each of `lines` in visual order, setting where they
+/// sit on it
+///
+/// Rule L2 of the Unicode Bidirectional Algorithm: from the highest level
+/// to the lowest odd one, every sequence of glyphs at that level or above
+/// gets reversed. Spaces ending a line stay at its end, as with rule L1.
+pub fn reorder(glyphs: &mut [Glyph], lines: &[Line]) {
+    for line in lines {
+        let mut end = line.end;
+        while end > line.start && glyphs[end - 1].is_space() {
+            end -= 1;
+        }
+        let mut order: Vec<usize> = (line.start..end).collect();
+        let levels = || order.iter().map(|&i| glyphs[i].level);
+        let top = levels().max().unwrap_or(0);
+        let lowest_odd = levels().filter(|l| l % 2 == 1).min().unwrap_or(top + 1);
+        for level in (lowest_odd..=top).rev() {
+            let mut i = 0;
+            while i < order.len() {
+                let n = order[i..].iter().take_while(|&&g| glyphs[g].level >= level).count();
+                order[i..i + n].reverse();
+                i += n.max(1);
+            }
+        }
+
+        let mut x = 0.0;
+        for i in order.into_iter().chain(end..line.end) {
+            glyphs[i].x = x;
+            x += glyphs[i].advance;
+        }
+    }
+}
+
+/// Move words down the wrapped lines of a paragraph to even them out,
+/// keeping the top lines wider for [`WrapStyle::Smart`] and the bottom ones
+/// for [`WrapStyle::SmartLower`]
+fn balance(
+    lines: &mut [(usize, usize)],
+    width: &impl Fn(usize, usize) -> f32,
+    space: &[bool],
+    max: f32,
+    style: WrapStyle,
+) {
+    for _ in 0..lines.len() * 8 {
+        let mut moved = false;
+        for i in (0..lines.len().saturating_sub(1)).rev() {
+            let ((s1, e1), (_, e2)) = (lines[i], lines[i + 1]);
+            let Some(k) = (s1..e1).rev().find(|&k| space[k]) else {
+                continue;
+            };
+            let (w1, w2) = (width(s1, e1), width(lines[i + 1].0, e2));
+            let (n1, n2) = (width(s1, k), width(k + 1, e2));
+            let better = match style {
+                WrapStyle::SmartLower => w1 > w2,
+                _ => n1 >= n2,
+            };
+            if better && n2 <= max {
+                lines[i] = (s1, k);
+                lines[i + 1] = (k + 1, e2);
+                moved = true;
+            }
+        }
+        if !moved {
+            break;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use backside_types::Style;
+
+    /// A word per letter of `words`, each glyph 10 pixels wide
+    fn glyphs(words: &str) -> Vec<Glyph> {
+        words.chars()
+            .map(|c| {
+                let kind = match c {
+                    ' '  => Kind::Space,
+                    '|'  => Kind::HardBreak,
+                    _    => Kind::Char,
+                };
+                Glyph { advance: 10.0, ..Glyph::empty(kind, 0, 8.0, 2.0) }
+            })
+            .collect()
+    }
+
+    fn spans(lines: &[Line]) -> Vec<(usize, usize)> {
+        lines.iter().map(|l| (l.start, l.end)).collect()
+    }
+
+    #[test]
+    fn wrapping() {
+        let mut g = glyphs("aaa bb cc d");
+        assert_eq!(spans(&wrap(&mut g, 1000.0, WrapStyle::Smart)), [(0, 11)]);
+
+        let lines = wrap(&mut g, 75.0, WrapStyle::EndOfLine);
+        assert_eq!(spans(&lines), [(0, 6), (7, 11)]);
+        assert_eq!((lines[0].width, lines[1].width, g[8].x), (60.0, 40.0, 10.0));
+
+        // Top line wider, bottom line wider
+        assert_eq!(spans(&wrap(&mut g, 100.0, WrapStyle::EndOfLine)), [(0, 9), (10, 11)]);
+        assert_eq!(spans(&wrap(&mut g, 100.0, WrapStyle::Smart)), [(0, 6), (7, 11)]);
+        assert_eq!(spans(&wrap(&mut g, 100.0, WrapStyle::SmartLower)), [(0, 3), (4, 11)]);
+
+        let mut g = glyphs("aa bb|cc||d");
+        let lines = wrap(&mut g, 10.0, WrapStyle::None);
+        assert_eq!(spans(&lines), [(0, 5), (6, 8), (9, 9), (10, 11)]);
+        assert_eq!((lines[2].ascent, lines[2].descent), (8.0, 2.0));
+
+        let mut d = drawing("m 0 0 l 20 0 20 10 0 10", &State::new(&Style::default()), 0, 1.0);
+        assert_eq!((d.advance, d.ascent, d.descent), (20.0, 10.0, 0.0));
+        d.outline.map(|p| Point::new(p.x, p.y + 10.0));
+        assert_eq!(d.outline, outline::drawing("m 0 0 l 20 0 20 10 0 10", 1, 1.0));
+    }
+
+    #[test]
+    fn shaping() {
+        let font = crate::fixture();
+        let face = font.face().unwrap();
+        let advance = |c| face.glyph_hor_advance(face.glyph_index(c).unwrap()).unwrap() as f32;
+        let shaper = Shaper { mode: Shaping::Complex, kerning: false };
+        let mut state = State::new(&Style::default());
+        let scale = font.scale(state.size);
+        let shaped = |state: &State| {
+            let run = Run { font: &font, text: "xa b", range: 1..4, level: 0, si: 3 };
+            let mut out = Vec::new();
+            shape_run(&run, state, &shaper, 1.0, &mut out);
+            out
+        };
+
+        let g = shaped(&state);
+        let kinds: Vec<_> = g.iter().map(|g| (g.kind, g.state, g.outline.is_empty())).collect();
+        assert_eq!(kinds, [(Kind::Char, 3, false), (Kind::Space, 3, true), (Kind::Char, 3, false)]);
+        assert_eq!(g[0].advance, advance('a') * scale);
+        assert_eq!((g[0].ascent + g[0].descent, g[0].decorations), (state.size, [None, None]));
+
+        // Stretched, spaced and underlined
+        state.scale = (2.0, 1.0);
+        state.spacing = 3.0;
+        state.underline = true;
+        let g = shaped(&state);
+        assert_eq!(g[1].advance, advance(' ') * scale * 2.0 + 6.0);
+        assert!(g.iter().all(|g| g.decorations[0].is_some() && g.decorations[1].is_none()));
+        let (min, max) = g[0].outline.bounds().unwrap();
+        assert!(max.x - min.x > advance('a') * scale);
+    }
+
+    #[test]
+    fn bidi() {
+        let font = crate::fixture();
+        let face = font.face().unwrap();
+        let shaper = Shaper { mode: Shaping::Complex, kerning: false };
+        let style = Style::default();
+        let mut states = [State::new(&style), State::new(&style)];
+        states[1].italic = true;
+
+        // `ab \u{5e9}\u{5dc}{\i1}\u{5d5} c\N\u{5d0}\u{5d1} d`, the block in the middle of a word
+        let text = "ab \u{5e9}\u{5dc}\u{5d5} c\u{2029}\u{5d0}\u{5d1} d";
+        let spans = [
+            Span { range: 0..7, state: 0, source: Source::Text(Some(&font)) },
+            Span { range: 7..11, state: 1, source: Source::Text(Some(&font)) },
+            Span { range: 11..14, state: 1, source: Source::Break(Some(&font), true) },
+            Span { range: 14..20, state: 1, source: Source::Text(Some(&font)) },
+        ];
+        let levels = |auto| super::glyphs(text, &spans, &states, auto, &shaper, 1.0).iter().map(|g| g.level).collect::<Vec<_>>();
+        // Each paragraph takes the direction of its first letter, the second
+        // one right to left with `d` embedded
+        assert_eq!(levels(true), [0, 0, 0, 1, 1, 1, 0, 0, 0, 1, 1, 1, 2]);
+        assert_eq!(levels(false), [0, 0, 0, 1, 1, 1, 0, 0, 0, 1, 1, 0, 0]);
+
+        // Visual order: the Hebrew word the block is in reversed as a whole,
+        // the embedded `d` reversed twice
+        let mut g = super::glyphs(text, &spans, &states, true, &shaper, 1.0);
+        let lines = wrap(&mut g, 1000.0, WrapStyle::None);
+        reorder(&mut g, &lines);
+        let order = |line: &Line| {
+            let mut i: Vec<usize> = (line.start..line.end).filter(|&i| g[i].kind != Kind::HardBreak).collect();
+            i.sort_by(|&a, &b| g[a].x.total_cmp(&g[b].x));
+            i
+        };
+        assert_eq!(lines.len(), 2);
+        assert_eq!(order(&lines[0]), [0, 1, 2, 5, 4, 3, 6, 7]);
+        assert_eq!(order(&lines[1]), [12, 11, 10, 9]);
+
+        // Letters join across blocks
+        let text = "\u{628}\u{628}";
+        let spans = [
+            Span { range: 0..2, state: 0, source: Source::Text(Some(&font)) },
+            Span { range: 2..4, state: 1, source: Source::Text(Some(&font)) },
+        ];
+        let advances: Vec<f32> = super::glyphs(text, &spans, &states, true, &shaper, 1.0).iter().map(|g| g.advance).collect();
+        let advance = |c| face.glyph_hor_advance(face.glyph_index(c).unwrap()).unwrap() as f32 * font.scale(states[0].size);
+        assert_eq!(advances, [advance('\u{fe91}'), advance('\u{fe90}')]);
+
+        // Cut by direction, then by script, neutrals going along
+        let text = "\u{5e9}\u{5dc} ab, \u{3b1}\u{3b2} 1";
+        let bidi = BidiInfo::new(text, None);
+        let cuts = |by_script| {
+            items(text, 0..text.len(), &bidi.levels, by_script).into_iter()
+                .map(|(r, l)| (&text[r], l))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(cuts(false), [("\u{5e9}\u{5dc} ", 1), ("ab, \u{3b1}\u{3b2} 1", 2)]);
+        assert_eq!(cuts(true), [("\u{5e9}\u{5dc} ", 1), ("ab, ", 2), ("\u{3b1}\u{3b2} 1", 2)]);
+    }
+}
@@ -0,0 +1,311 @@
+#![no_std]
+
+//! # `backside_renderer`
+//! Draws scripts into alpha bitmaps, the way libass does
+//!
+//! Rendering a frame gives a list of [`Image`]s, each a coverage bitmap to
+//! blend in a single colour at some position, from the bottom up. Like
+//! libass' `ASS_Image`, every event gives the shadows of its text, then its
+//! borders, then its fill.
+//!
+//! Fonts come from a [`FontProvider`], by default a [`FontDatabase`] of
+//! the fonts it's given, embedded in the script or, with the `std`
+//! feature, found in directories.
+
+extern crate alloc;
+#[cfg(feature = "std")]
+extern crate std;
+
+mod font;
+mod layout;
+mod outline;
+mod raster;
+mod render;
+mod shape;
+mod state;
+
+use alloc::{vec, vec::Vec};
+
+use backside_types::{Colour, Error, EventKind, Script, Timestamp};
+
+pub use font::{Fallback, Font, FontDatabase, FontProvider, Selected};
+pub use shape::Shaping;
+use raster::Bitmap;
+use render::Frame;
+
+/// Which part of the text an [`Image`] is
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum ImageKind {
+    /// The fill, in the primary colour (or secondary, for karaoke)
+    Character,
+    /// The border, in the outline colour
+    Outline,
+    /// The shadow, in the back colour
+    Shadow,
+}
+
+/// A bitmap to blend onto the frame in a single colour
+#[derive(Clone, PartialEq, Debug)]
+pub struct Image {
+    /// Position of the top-left pixel on the frame, which the image fits in
+    pub x: i32,
+    pub y: i32,
+    pub w: usize,
+    pub h: usize,
+    /// Bytes from one row of [`bitmap`](Self::bitmap) to the next
+    pub stride: usize,
+    /// Coverage of each pixel, `255` being the colour at its full opacity
+    pub bitmap: Vec<u8>,
+    /// Colour, its alpha being a transparency the coverage gets applied on
+    pub colour: Colour,
+    pub kind: ImageKind,
+}
+
+impl Image {
+    fn new(b: Bitmap, colour: Colour, kind: ImageKind) -> Self {
+        Self {
+            x: b.x,
+            y: b.y,
+            w: b.w,
+            h: b.h,
+            stride: b.w,
+            bitmap: b.data,
+            colour,
+            kind,
+        }
+    }
+}
+
+/// Draws scripts with the fonts of its [`FontProvider`]
+#[derive(Default)]
+pub struct Renderer<F = FontDatabase> {
+    fonts: F,
+    fallbacks: Vec<Fallback>,
+    warnings: Vec<Error>,
+    shaping: Shaping,
+}
+
+impl Renderer {
+    /// A renderer without any font, only drawing drawings until some get
+    /// added to [`fonts_mut`](Self::fonts_mut)
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl<F: FontProvider> Renderer<F> {
+    pub fn with_fonts(fonts: F) -> Self {
+        Self { fonts, fallbacks: Vec::new(), warnings: Vec::new(), shaping: Shaping::default() }
+    }
+
+    /// Shape text like VSFilter or with OpenType, the default
+    pub fn set_shaping(&mut self, shaping: Shaping) {
+        self.shaping = shaping;
+    }
+
+    pub fn fonts(&self) -> &F {
+        &self.fonts
+    }
+
+    pub fn fonts_mut(&mut self) -> &mut F {
+        &mut self.fonts
+    }
+
+    /// Fonts that were missing from the events rendered so far, once each
+    pub fn fallbacks(&self) -> &[Fallback] {
+        &self.fallbacks
+    }
+
+    /// Tags that were dropped from the events rendered so far, once each
+    ///
+    /// Their spans are within the text of the event they were in.
+    pub fn warnings(&self) -> &[Error] {
+        &self.warnings
+    }
+
+    /// Draw the events showing at `t` on a frame of `size`, `(width, height)`
+    ///
+    /// Events are drawn by layer, then in the order of the script. The
+    /// script gets stretched over the whole frame, and borders and shadows
+    /// along with it if `ScaledBorderAndShadow` is set.
+    pub fn render(&mut self, script: &Script, size: (u32, u32), t: Timestamp) -> Vec<Image> {
+        let (px, py) = script.info.play_res();
+        let (px, py) = (px.max(1) as f32, py.max(1) as f32);
+        let scale = (size.0 as f32 / px, size.1 as f32 / py);
+        let frame = Frame {
+            w: size.0 as i32,
+            h: size.1 as i32,
+            scale,
+            border_scale: if script.info.scaled_border_and_shadow() { scale } else { (1.0, 1.0) },
+            play_res: (px, py),
+            shaping: self.shaping,
+        };
+
+        let mut events: Vec<_> = script.events.iter()
+            .filter(|e| e.kind == EventKind::Dialogue && e.start <= t && t < e.end)
+            .collect();
+        events.sort_by_key(|e| e.layer);
+
+        let collisions = script.info.collisions();
+        let mut placed = Vec::new();
+        let mut layer = None;
+        let mut images = Vec::new();
+        for e in events {
+            // Only events of the same layer push each other around
+            if layer != Some(e.layer) {
+                layer = Some(e.layer);
+                placed.clear();
+            }
+            let Some(mut laid) = render::layout(&self.fonts, script, e, &frame, t, &mut self.fallbacks, &mut self.warnings) else {
+                continue;
+            };
+            if !laid.is_positioned() {
+                laid.collide(&mut placed, collisions);
+            }
+            images.extend(render::draw(&laid, &frame));
+        }
+        images
+    }
+}
+
+/// Fixture Sans, Latin, Hebrew and a few Arabic letters out of DejaVu Sans
+/// made by `testdata/subset.py`
+#[cfg(test)]
+pub(crate) const FIXTURE: &[u8] = include_bytes!("testdata/fixture-sans.ttf");
+
+/// Fixture Sans Latin, only its ASCII
+#[cfg(test)]
+pub(crate) const FIXTURE_LATIN: &[u8] = include_bytes!("testdata/fixture-sans-latin.ttf");
+
+#[cfg(test)]
+pub(crate) fn fixture() -> Font {
+    Font::load(FIXTURE.into()).remove(0)
+}
+
+/// Blend `images` in order onto a transparent frame of `size`, giving its
+/// RGBA pixels
+pub fn composite(images: &[Image], size: (u32, u32)) -> Vec<u8> {
+    let (w, h) = (size.0 as usize, size.1 as usize);
+    let mut frame = vec![0u8; w * h * 4];
+    for img in images {
+        let [r, g, b, a] = img.colour.to_rgba();
+        for y in 0..img.h {
+            let fy = img.y + y as i32;
+            if fy < 0 || fy as usize >= h {
+                continue;
+            }
+            for x in 0..img.w {
+                let fx = img.x + x as i32;
+                if fx < 0 || fx as usize >= w {
+                    continue;
+                }
+                let k = raster::mul(img.bitmap[y * img.stride + x], a) as u32;
+                if k == 0 {
+                    continue;
+                }
+                let p = &mut frame[(fy as usize * w + fx as usize) * 4..][..4];
+                // Straight alpha "over"
+                let below = p[3] as u32 * (255 - k) / 255;
+                let out = k + below;
+                for (c, v) in p[..3].iter_mut().zip([r, g, b]) {
+                    *c = ((v as u32 * k + *c as u32 * below) / out) as u8;
+                }
+                p[3] = out as u8;
+            }
+        }
+    }
+    frame
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SCRIPT: &str = r"[Script Info]
+PlayResX: 320
+PlayResY: 240
+ScaledBorderAndShadow: yes
+
+[V4+ Styles]
+Format: Name, Fontname, Fontsize, PrimaryColour, SecondaryColour, OutlineColour, BackColour, Bold, Italic, Underline, StrikeOut, ScaleX, ScaleY, Spacing, Angle, BorderStyle, Outline, Shadow, Alignment, MarginL, MarginR, MarginV, Encoding
+Style: Default,Arial,20,&H00FFFFFF,&H000000FF,&H00000000,&H80000000,0,0,0,0,100,100,0,0,1,2,2,7,10,10,10,1
+
+[Events]
+Format: Layer, Start, End, Style, Name, MarginL, MarginR, MarginV, Effect, Text
+Dialogue: 0,0:00:00.00,0:00:01.00,Default,,0,0,0,,{\p1}m 0 0 l 40 0 40 20 0 20
+Dialogue: 0,0:00:00.00,0:00:01.00,Default,,0,0,0,,{\pos(100,100)\bord0\shad0\1a&H80&\p1}m 0 0 l 10 0 10 10 0 10
+Dialogue: 0,0:00:00.50,0:00:01.00,Default,,0,0,0,,{\an2\pos(160,240)\fad(1000,0)\p1}m 0 0 l 10 0 10 10 0 10
+Dialogue: 0,0:00:00.50,0:00:01.00,Default,,0,0,0,,{\bord0\shad0\p1}m 0 0 l 40 0 40 20 0 20
+";
+
+    fn script() -> Script {
+        backside_parser::parse_sections(SCRIPT).unwrap()
+    }
+
+    #[test]
+    fn render() {
+        let script = script();
+        let mut r = Renderer::new();
+
+        let images = r.render(&script, (640, 480), Timestamp(10));
+        let kinds: Vec<_> = images.iter().map(|i| (i.kind, i.x, i.y, i.w, i.h)).collect();
+        assert_eq!(kinds, [
+            // Frame is twice the script size, border and shadow with it
+            (ImageKind::Shadow, 20, 20, 88, 48),
+            (ImageKind::Outline, 16, 16, 88, 48),
+            (ImageKind::Character, 20, 20, 80, 40),
+            (ImageKind::Character, 200, 200, 20, 20),
+        ]);
+        assert_eq!(images[0].colour, Colour(0x80000000));
+        assert_eq!(images[3].colour, Colour(0x80FFFFFF));
+        assert!(images[2].bitmap.iter().all(|&v| v == 255));
+        // The border doesn't cover the fill
+        assert_eq!(images[1].bitmap[10 * 88 + 10], 0);
+
+        // Fading in, and pushed below the first event
+        let images = r.render(&script, (320, 240), Timestamp(75));
+        let fade = images.iter().find(|i| i.kind == ImageKind::Character && i.x == 155).unwrap();
+        assert_eq!((fade.y, fade.colour.alpha()), (230, 191));
+        assert!(images.iter().any(|i| (i.x, i.y, i.w, i.h) == (10, 30, 40, 20)));
+        assert!(r.render(&script, (320, 240), Timestamp(100)).is_empty());
+
+        let frame = composite(&images, (320, 240));
+        assert_eq!(&frame[(15 * 320 + 15) * 4..][..4], [255, 255, 255, 255]);
+        assert_eq!(&frame[(32 * 320 + 52) * 4..][..4], [0, 0, 0, 127]);
+    }
+
+    #[test]
+    fn warnings() {
+        let script = SCRIPT.replace(r"{\p1}m 0 0 l 40", r"{\foo\p1}m 0 0 l 40");
+        let script = backside_parser::parse_sections(&script).unwrap();
+        let mut r = Renderer::new();
+        // Still drawn without the broken tag, and noted once
+        assert!(!r.render(&script, (320, 240), Timestamp(10)).is_empty());
+        r.render(&script, (320, 240), Timestamp(20));
+        let found: Vec<_> = r.warnings().iter().map(|e| (e.kind, e.span.clone())).collect();
+        assert_eq!(found, [(backside_types::ErrorKind::OCInvalid, Some(1..5))]);
+    }
+
+    #[test]
+    fn hostile_values() {
+        // Clamped to what libass allows, or this would never finish
+        let script = SCRIPT.replace(r"{\p1}m 0 0 l 40", r"{\be4000000000\blur1e30\p1}m 0 0 l 40");
+        let script = backside_parser::parse_sections(&script).unwrap();
+        let images = Renderer::new().render(&script, (64, 48), Timestamp(10));
+        let border = images.iter().find(|i| i.kind == ImageKind::Outline).unwrap();
+        // Blurred well past the 8x4 fill, but no further than the clamp goes
+        assert!((20..100).contains(&border.w));
+
+        // None of these overflow, and borders stay about the size of the frame
+        for tags in [
+            r"\bord1e30", r"\shad1e30", r"\xshad-1e30", r"\pbo1e30", r"\pbo-1e30", r"\bord5000",
+            r"\k2000000000\kf2000000000", r"\k-2000000000\kf-2000000000",
+            r"\t(-2147483648,2147483647,\bord9)",
+        ] {
+            let script = SCRIPT.replace(r"{\p1}m 0 0 l 40", &["{", tags, r"\p1}m 0 0 l 40"].concat());
+            let script = backside_parser::parse_sections(&script).unwrap();
+            let images = Renderer::new().render(&script, (64, 48), Timestamp(10));
+            assert!(images.iter().all(|i| i.w <= 64 && i.h <= 48), "{}", tags);
+        }
+    }
+}
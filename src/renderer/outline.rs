@@ -0,0 +1,288 @@
+//! Vector outlines of glyphs and drawings
+
+use alloc::vec::Vec;
+
+#[cfg(not(any(test, feature = "std")))]
+use core_maths::CoreFloat;
+
+#[derive(Clone, Copy, Default, PartialEq, Debug)]
+pub struct Point {
+    pub x: f32,
+    pub y: f32,
+}
+
+impl Point {
+    pub const fn new(x: f32, y: f32) -> Self {
+        Self { x, y }
+    }
+
+    fn lerp(self, o: Self, t: f32) -> Self {
+        Self::new(self.x + (o.x - self.x) * t, self.y + (o.y - self.y) * t)
+    }
+
+    fn dist(self, o: Self) -> f32 {
+        ((o.x - self.x).powi(2) + (o.y - self.y).powi(2)).sqrt()
+    }
+}
+
+/// Closed polygons, with the curves already turned into lines
+///
+/// Filled with the non-zero winding rule.
+#[derive(Clone, Default, PartialEq, Debug)]
+pub struct Outline {
+    pub contours: Vec<Vec<Point>>,
+}
+
+impl Outline {
+    pub fn is_empty(&self) -> bool {
+        self.contours.iter().all(|c| c.len() < 3)
+    }
+
+    /// Rectangle from `(x0, y0)` to `(x1, y1)`
+    pub fn rect(x0: f32, y0: f32, x1: f32, y1: f32) -> Self {
+        Self {
+            contours: alloc::vec![alloc::vec![
+                Point::new(x0, y0),
+                Point::new(x1, y0),
+                Point::new(x1, y1),
+                Point::new(x0, y1),
+            ]],
+        }
+    }
+
+    pub fn append(&mut self, o: Outline) {
+        self.contours.extend(o.contours);
+    }
+
+    /// Move every point through `f`
+    pub fn map(&mut self, mut f: impl FnMut(Point) -> Point) {
+        for p in self.contours.iter_mut().flatten() {
+            *p = f(*p);
+        }
+    }
+
+    /// Top-left and bottom-right corners of the bounding box
+    pub fn bounds(&self) -> Option<(Point, Point)> {
+        let mut pts = self.contours.iter().flatten();
+        let first = *pts.next()?;
+        Some(pts.fold((first, first), |(lo, hi), p| (
+            Point::new(lo.x.min(p.x), lo.y.min(p.y)),
+            Point::new(hi.x.max(p.x), hi.y.max(p.y)),
+        )))
+    }
+}
+
+/// Builds an [`Outline`] out of lines and curves
+///
+/// `detail` is the number of pixels per unit the outline ends up drawn at,
+/// which decides how many lines curves become.
+pub struct Builder {
+    outline: Outline,
+    cur: Vec<Point>,
+    detail: f32,
+}
+
+impl Builder {
+    pub fn new(detail: f32) -> Self {
+        Self {
+            outline: Outline::default(),
+            cur: Vec::new(),
+            detail: detail.abs().max(1e-3),
+        }
+    }
+
+    fn last(&self) -> Point {
+        self.cur.last().copied().unwrap_or_default()
+    }
+
+    /// Lines to approximate a curve going along `len` units
+    fn steps(&self, len: f32) -> usize {
+        ((len * self.detail * 2.0).sqrt().ceil() as usize).clamp(1, 64)
+    }
+
+    pub fn move_to(&mut self, p: Point) {
+        self.close();
+        self.cur.push(p);
+    }
+
+    pub fn line_to(&mut self, p: Point) {
+        if self.cur.is_empty() {
+            self.cur.push(Point::default());
+        }
+        self.cur.push(p);
+    }
+
+    pub fn quad_to(&mut self, c: Point, p: Point) {
+        let s = self.last();
+        let n = self.steps(s.dist(c) + c.dist(p));
+        for i in 1..=n {
+            let t = i as f32 / n as f32;
+            self.line_to(s.lerp(c, t).lerp(c.lerp(p, t), t));
+        }
+    }
+
+    pub fn cubic_to(&mut self, c1: Point, c2: Point, p: Point) {
+        let s = self.last();
+        let n = self.steps(s.dist(c1) + c1.dist(c2) + c2.dist(p));
+        for i in 1..=n {
+            let t = i as f32 / n as f32;
+            let (a, b, c) = (s.lerp(c1, t), c1.lerp(c2, t), c2.lerp(p, t));
+            self.line_to(a.lerp(b, t).lerp(b.lerp(c, t), t));
+        }
+    }
+
+    pub fn close(&mut self) {
+        if self.cur.len() >= 3 {
+            self.outline.contours.push(core::mem::take(&mut self.cur));
+        }
+        self.cur.clear();
+    }
+
+    pub fn finish(mut self) -> Outline {
+        self.close();
+        self.outline
+    }
+}
+
+impl ttf_parser::OutlineBuilder for Builder {
+    fn move_to(&mut self, x: f32, y: f32) {
+        Builder::move_to(self, Point::new(x, y));
+    }
+
+    fn line_to(&mut self, x: f32, y: f32) {
+        Builder::line_to(self, Point::new(x, y));
+    }
+
+    fn quad_to(&mut self, x1: f32, y1: f32, x: f32, y: f32) {
+        Builder::quad_to(self, Point::new(x1, y1), Point::new(x, y));
+    }
+
+    fn curve_to(&mut self, x1: f32, y1: f32, x2: f32, y2: f32, x: f32, y: f32) {
+        Builder::cubic_to(self, Point::new(x1, y1), Point::new(x2, y2), Point::new(x, y));
+    }
+
+    fn close(&mut self) {
+        Builder::close(self);
+    }
+}
+
+/// Parse ASS drawing commands, as used by `\p` and `\clip`
+///
+/// `scale` is the `\p` level, coordinates being in 1/2^(scale-1) pixels.
+/// Supports `m`, `n`, `l`, `b`, and the `s` / `p` / `c` B-splines; like
+/// libass, anything unexpected ends the drawing.
+pub fn drawing(d: &str, scale: u32, detail: f32) -> Outline {
+    let unit = 1.0 / (1u64 << scale.clamp(1, 32).saturating_sub(1)) as f32;
+    let mut b = Builder::new(detail / unit);
+    let mut words = d.split_ascii_whitespace().peekable();
+
+    let mut cmd = 'm';
+    // Control points of the current spline
+    let mut spline: Vec<Point> = Vec::new();
+
+    let flush = |b: &mut Builder, spline: &mut Vec<Point>, closed: bool| {
+        if spline.len() >= 4 || (closed && spline.len() >= 3) {
+            if closed {
+                let wrap: Vec<_> = spline.iter().take(3).copied().collect();
+                spline.extend(wrap);
+            }
+            for (i, w) in spline.windows(4).enumerate() {
+                // Uniform cubic B-spline to Bézier
+                let p = |a: f32, b: f32, c: f32, d: f32| Point::new(
+                    (a * w[0].x + b * w[1].x + c * w[2].x + d * w[3].x) / 6.0,
+                    (a * w[0].y + b * w[1].y + c * w[2].y + d * w[3].y) / 6.0,
+                );
+                if i == 0 {
+                    b.line_to(p(1.0, 4.0, 1.0, 0.0));
+                }
+                b.cubic_to(p(0.0, 4.0, 2.0, 0.0), p(0.0, 2.0, 4.0, 0.0), p(0.0, 1.0, 4.0, 1.0));
+            }
+        }
+        spline.clear();
+    };
+
+    while let Some(&w) = words.peek() {
+        if let Some(c) = w.chars().next().filter(|c| c.is_ascii_alphabetic()) {
+            words.next();
+            let closed = c == 'c';
+            if !spline.is_empty() && (cmd == 's' || cmd == 'p') && c != 'p' {
+                flush(&mut b, &mut spline, closed);
+            }
+            match c {
+                'm' | 'n' | 'l' | 'b' => cmd = c,
+                's' => {
+                    cmd = 's';
+                    spline.push(b.last());
+                },
+                'p' => cmd = 'p',
+                'c' => {},
+                _ => break,
+            }
+            continue;
+        }
+
+        // A run of coordinates
+        let mut num = || words.next_if(|w| w.parse::<f32>().is_ok()).and_then(|w| w.parse::<f32>().ok());
+        let mut point = || Some(Point::new(num()? * unit, num()? * unit));
+        match cmd {
+            // Contours get closed either way when filling
+            'm' | 'n' => match point() {
+                Some(p) => {
+                    b.move_to(p);
+                    // Coordinates after a move are lines
+                    cmd = 'l';
+                },
+                None => break,
+            },
+            'l' => match point() {
+                Some(p) => b.line_to(p),
+                None => break,
+            },
+            'b' => match (point(), point(), point()) {
+                (Some(c1), Some(c2), Some(p)) => b.cubic_to(c1, c2, p),
+                _ => break,
+            },
+            's' | 'p' => match point() {
+                Some(p) => spline.push(p),
+                None => break,
+            },
+            _ => break,
+        }
+    }
+    if !spline.is_empty() {
+        flush(&mut b, &mut spline, false);
+    }
+    b.finish()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn drawings() {
+        let square = drawing("m 0 0 l 10 0 10 10 0 10", 1, 1.0);
+        assert_eq!(square.contours, [[
+            Point::new(0.0, 0.0),
+            Point::new(10.0, 0.0),
+            Point::new(10.0, 10.0),
+            Point::new(0.0, 10.0),
+        ]]);
+
+        let half = drawing("m 0 0 l 20 0 20 20 0 20", 2, 1.0);
+        assert_eq!(half.bounds(), Some((Point::new(0.0, 0.0), Point::new(10.0, 10.0))));
+
+        let curve = drawing("m 0 0 b 0 10 10 10 10 0", 1, 1.0);
+        let (lo, hi) = curve.bounds().unwrap();
+        assert!(curve.contours[0].len() > 4);
+        assert!(lo.y == 0.0 && hi.y > 7.0 && hi.y <= 7.5);
+
+        let two = drawing("m 0 0 l 1 0 1 1 m 5 5 l 6 5 6 6", 1, 1.0);
+        assert_eq!(two.contours.len(), 2);
+
+        let spline = drawing("m 0 0 s 10 0 10 10 0 10 c", 1, 1.0);
+        assert!(!spline.is_empty());
+
+        assert!(drawing("m 0 0 l 10", 1, 1.0).is_empty());
+    }
+}
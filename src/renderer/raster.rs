@@ -0,0 +1,454 @@
+//! Turning outlines into alpha bitmaps, and the filters applied on them
+
+use alloc::{vec, vec::Vec};
+
+#[cfg(not(any(test, feature = "std")))]
+use core_maths::CoreFloat;
+
+use crate::outline::{Outline, Point};
+
+/// Rectangle of pixels, `x1` and `y1` excluded
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Rect {
+    pub x0: i32,
+    pub y0: i32,
+    pub x1: i32,
+    pub y1: i32,
+}
+
+impl Rect {
+    pub fn new(x0: i32, y0: i32, x1: i32, y1: i32) -> Self {
+        Self { x0, y0, x1, y1 }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.x0 >= self.x1 || self.y0 >= self.y1
+    }
+
+    pub fn intersect(&self, o: &Rect) -> Rect {
+        Rect::new(self.x0.max(o.x0), self.y0.max(o.y0), self.x1.min(o.x1), self.y1.min(o.y1))
+    }
+
+    /// Grown by `by` pixels on every side
+    pub fn grow(&self, by: i32) -> Rect {
+        Rect::new(self.x0.saturating_sub(by), self.y0.saturating_sub(by), self.x1.saturating_add(by), self.y1.saturating_add(by))
+    }
+}
+
+/// 8-bit coverage of the pixels of a rectangle, `255` being fully covered
+#[derive(Clone, PartialEq, Debug)]
+pub struct Bitmap {
+    /// Position of the top-left pixel
+    pub x: i32,
+    pub y: i32,
+    pub w: usize,
+    pub h: usize,
+    pub data: Vec<u8>,
+}
+
+impl Bitmap {
+    pub fn new(r: Rect) -> Self {
+        let (w, h) = ((r.x1 - r.x0).max(0) as usize, (r.y1 - r.y0).max(0) as usize);
+        Self { x: r.x0, y: r.y0, w, h, data: vec![0; w * h] }
+    }
+
+    pub fn rect(&self) -> Rect {
+        Rect::new(self.x, self.y, self.x.saturating_add(self.w as i32), self.y.saturating_add(self.h as i32))
+    }
+
+    /// Coverage at `(x, y)` in frame pixels, `0` outside
+    pub fn get(&self, x: i32, y: i32) -> u8 {
+        let (x, y) = (x - self.x, y - self.y);
+        if x < 0 || y < 0 || x as usize >= self.w || y as usize >= self.h {
+            return 0;
+        }
+        self.data[y as usize * self.w + x as usize]
+    }
+
+    /// Copy over `r`, with `0` where `self` has nothing
+    pub fn to_rect(&self, r: Rect) -> Bitmap {
+        let mut out = Bitmap::new(r);
+        let common = r.intersect(&self.rect());
+        if common.is_empty() {
+            return out;
+        }
+        let n = (common.x1 - common.x0) as usize;
+        for y in common.y0..common.y1 {
+            let src = (y - self.y) as usize * self.w + (common.x0 - self.x) as usize;
+            let dst = (y - r.y0) as usize * out.w + (common.x0 - r.x0) as usize;
+            out.data[dst..dst + n].copy_from_slice(&self.data[src..src + n]);
+        }
+        out
+    }
+
+    /// Smallest copy holding every pixel that isn't `0`
+    pub fn trim(self) -> Bitmap {
+        let rows = |y: usize| &self.data[y * self.w..(y + 1) * self.w];
+        let Some(y0) = (0..self.h).find(|&y| rows(y).iter().any(|&v| v != 0)) else {
+            return Bitmap::new(Rect::new(self.x, self.y, self.x, self.y));
+        };
+        let y1 = (0..self.h).rev().find(|&y| rows(y).iter().any(|&v| v != 0)).unwrap_or(y0) + 1;
+        let col = |x: usize| (y0..y1).any(|y| self.data[y * self.w + x] != 0);
+        let x0 = (0..self.w).find(|&x| col(x)).unwrap_or(0);
+        let x1 = (0..self.w).rev().find(|&x| col(x)).unwrap_or(x0) + 1;
+        if (x0, y0, x1, y1) == (0, 0, self.w, self.h) {
+            return self;
+        }
+        let (x, y) = (self.x + x0 as i32, self.y + y0 as i32);
+        self.to_rect(Rect::new(x, y, self.x + x1 as i32, self.y + y1 as i32))
+    }
+
+    /// Zero every pixel `keep` says no to, given frame coordinates
+    pub fn mask(&mut self, mut keep: impl FnMut(i32, i32) -> u8) {
+        for y in 0..self.h {
+            for x in 0..self.w {
+                let v = &mut self.data[y * self.w + x];
+                if *v != 0 {
+                    *v = mul(*v, keep(self.x + x as i32, self.y + y as i32));
+                }
+            }
+        }
+    }
+
+    /// Take `o` away from `self`, eg. the fill from its border
+    pub fn subtract(&mut self, o: &Bitmap) {
+        for y in 0..self.h {
+            for x in 0..self.w {
+                let v = &mut self.data[y * self.w + x];
+                *v = v.saturating_sub(o.get(self.x + x as i32, self.y + y as i32));
+            }
+        }
+    }
+
+    /// Moved by `(dx, dy)` pixels, fractions included
+    pub fn shift(&self, dx: f32, dy: f32) -> Bitmap {
+        let (ix, iy) = (dx.floor(), dy.floor());
+        let (fx, fy) = (dx - ix, dy - iy);
+        let mut out = Bitmap::new(Rect::new(0, 0, self.w as i32 + 1, self.h as i32 + 1));
+        out.x = self.x.saturating_add(ix as i32);
+        out.y = self.y.saturating_add(iy as i32);
+        // Bilinear filtering, each pixel spreading over its four neighbours
+        for y in 0..self.h {
+            for x in 0..self.w {
+                let v = self.data[y * self.w + x] as f32;
+                if v == 0.0 {
+                    continue;
+                }
+                for (ox, oy, k) in [
+                    (0, 0, (1.0 - fx) * (1.0 - fy)),
+                    (1, 0, fx * (1.0 - fy)),
+                    (0, 1, (1.0 - fx) * fy),
+                    (1, 1, fx * fy),
+                ] {
+                    let o = &mut out.data[(y + oy) * out.w + x + ox];
+                    *o = (*o as f32 + v * k + 0.5).min(255.0) as u8;
+                }
+            }
+        }
+        out
+    }
+}
+
+/// `a * b / 255`, rounded
+pub fn mul(a: u8, b: u8) -> u8 {
+    let v = a as u32 * b as u32 + 128;
+    ((v + (v >> 8)) >> 8) as u8
+}
+
+/// Smallest rectangle of pixels holding `outline`, within `limit`
+pub fn bounds(outline: &Outline, limit: Rect) -> Rect {
+    match outline.bounds() {
+        Some((lo, hi)) => Rect::new(
+            lo.x.floor().max(i32::MIN as f32) as i32,
+            lo.y.floor().max(i32::MIN as f32) as i32,
+            hi.x.ceil().min(i32::MAX as f32) as i32,
+            hi.y.ceil().min(i32::MAX as f32) as i32,
+        ).intersect(&limit),
+        None => Rect::new(0, 0, 0, 0),
+    }
+}
+
+/// Coverage of `outline` over `limit`, cropped to the outline
+pub fn fill(outline: &Outline, limit: Rect) -> Bitmap {
+    rasterize(outline, bounds(outline, limit))
+}
+
+/// Coverage of `outline` over exactly the pixels of `r`
+///
+/// Accumulates the signed area each edge covers in each pixel, then sums
+/// them along the rows, the way font-rs does.
+pub fn rasterize(outline: &Outline, r: Rect) -> Bitmap {
+    let mut out = Bitmap::new(r);
+    if r.is_empty() {
+        return out;
+    }
+    let (w, h) = (out.w, out.h);
+    // Room for the cell right of the last pixel of a row
+    let stride = w + 2;
+    let mut acc = vec![0f32; stride * h];
+    let origin = Point::new(r.x0 as f32, r.y0 as f32);
+
+    for c in &outline.contours {
+        for (i, &p1) in c.iter().enumerate() {
+            let p0 = c[if i == 0 { c.len() - 1 } else { i - 1 }];
+            // Clamping along x keeps the winding inside the rectangle right
+            let local = |p: Point| Point::new((p.x - origin.x).clamp(0.0, w as f32), p.y - origin.y);
+            line(&mut acc, stride, h, local(p0), local(p1));
+        }
+    }
+
+    for y in 0..h {
+        let mut sum = 0.0;
+        for x in 0..w {
+            sum += acc[y * stride + x];
+            out.data[y * w + x] = (sum.abs().min(1.0) * 255.0 + 0.5) as u8;
+        }
+    }
+    out
+}
+
+fn line(acc: &mut [f32], stride: usize, h: usize, p0: Point, p1: Point) {
+    if (p0.y - p1.y).abs() <= f32::EPSILON {
+        return;
+    }
+    let (dir, p0, p1) = if p0.y < p1.y { (1.0, p0, p1) } else { (-1.0, p1, p0) };
+    if p1.y <= 0.0 || p0.y >= h as f32 {
+        return;
+    }
+    let dxdy = (p1.x - p0.x) / (p1.y - p0.y);
+    let mut x = p0.x;
+    if p0.y < 0.0 {
+        x -= p0.y * dxdy;
+    }
+    let last = stride - 1;
+    for y in p0.y.max(0.0) as usize..h.min(p1.y.ceil() as usize) {
+        let row = &mut acc[y * stride..(y + 1) * stride];
+        let dy = ((y + 1) as f32).min(p1.y) - (y as f32).max(p0.y);
+        let xnext = (x + dxdy * dy).max(0.0);
+        let d = dy * dir;
+        let (x0, x1) = if x < xnext { (x, xnext) } else { (xnext, x) };
+        let x0floor = x0.floor();
+        let x0i = x0floor as usize;
+        let x1ceil = x1.ceil();
+        let x1i = x1ceil as usize;
+        if x1i <= x0i + 1 {
+            let xmf = 0.5 * (x + xnext) - x0floor;
+            row[x0i.min(last)] += d - d * xmf;
+            row[(x0i + 1).min(last)] += d * xmf;
+        } else {
+            let s = (x1 - x0).recip();
+            let x0f = x0 - x0floor;
+            let a0 = 0.5 * s * (1.0 - x0f) * (1.0 - x0f);
+            let x1f = x1 - x1ceil + 1.0;
+            let am = 0.5 * s * x1f * x1f;
+            row[x0i] += d * a0;
+            if x1i == x0i + 2 {
+                row[x0i + 1] += d * (1.0 - a0 - am);
+            } else {
+                let a1 = s * (1.5 - x0f);
+                row[x0i + 1] += d * (a1 - a0);
+                for v in &mut row[x0i + 2..x1i - 1] {
+                    *v += d * s;
+                }
+                let a2 = a1 + (x1i - x0i - 3) as f32 * s;
+                row[x1i - 1] += d * (1.0 - a2 - am);
+            }
+            row[x1i.min(last)] += d * am;
+        }
+        x = xnext;
+    }
+}
+
+/// Gaussian blur of standard deviation `sigma` pixels, `\blur`
+pub fn blur(b: &Bitmap, sigma: f32) -> Bitmap {
+    if sigma <= 0.0 {
+        return b.clone();
+    }
+    let r = (sigma * 3.0).ceil() as usize;
+    let kernel: Vec<f32> = {
+        let k: Vec<f32> = (0..=2 * r)
+            .map(|i| (-((i as f32 - r as f32).powi(2)) / (2.0 * sigma * sigma)).exp())
+            .collect();
+        let sum: f32 = k.iter().sum();
+        k.into_iter().map(|v| v / sum).collect()
+    };
+    convolve(b, &kernel)
+}
+
+/// `n` passes of a 3x3 box blur, `\be`
+pub fn blur_edges(b: &Bitmap, n: u32) -> Bitmap {
+    let mut out = b.clone();
+    for _ in 0..n {
+        out = convolve(&out, &[0.25, 0.5, 0.25]);
+    }
+    out
+}
+
+/// Apply the symmetric `kernel` along both axes, growing the bitmap
+fn convolve(b: &Bitmap, kernel: &[f32]) -> Bitmap {
+    let r = kernel.len() / 2;
+    let (w, h) = (b.w + 2 * r, b.h + 2 * r);
+
+    // Horizontal pass, into rows as wide as the result
+    let mut tmp = vec![0f32; w * b.h];
+    for y in 0..b.h {
+        for x in 0..b.w {
+            let v = b.data[y * b.w + x] as f32;
+            if v != 0.0 {
+                for (i, k) in kernel.iter().enumerate() {
+                    tmp[y * w + x + i] += v * k;
+                }
+            }
+        }
+    }
+
+    let mut out = Bitmap::new(b.rect().grow(r as i32));
+    let mut col = vec![0f32; h];
+    for x in 0..w {
+        col.iter_mut().for_each(|v| *v = 0.0);
+        for y in 0..b.h {
+            let v = tmp[y * w + x];
+            if v != 0.0 {
+                for (i, k) in kernel.iter().enumerate() {
+                    col[y + i] += v * k;
+                }
+            }
+        }
+        for (y, v) in col.iter().enumerate() {
+            out.data[y * w + x] = (v + 0.5).min(255.0) as u8;
+        }
+    }
+    out
+}
+
+/// Border of `rx` by `ry` pixels around what `b` covers
+///
+/// Takes the distance of every pixel to the nearest covered one, scaled
+/// so that the border is an ellipse, and antialiases its edge. The result
+/// covers what `b` does too.
+pub fn border(b: &Bitmap, rx: f32, ry: f32) -> Bitmap {
+    let (rx, ry) = (rx.max(0.0), ry.max(0.0));
+    let grow = rx.max(ry).ceil() as i32 + 1;
+    let mut out = Bitmap::new(b.rect().grow(grow));
+    let (w, h) = (out.w, out.h);
+
+    const INF: f32 = 1e20;
+    // Squared distance to the nearest covered pixel, in units of the radius
+    let mut d: Vec<f32> = (0..w * h)
+        .map(|i| {
+            let (x, y) = ((i % w) as i32 + out.x, (i / w) as i32 + out.y);
+            if b.get(x, y) >= 128 { 0.0 } else { INF }
+        })
+        .collect();
+    let wx = 1.0 / rx.max(1e-3).powi(2);
+    let wy = 1.0 / ry.max(1e-3).powi(2);
+
+    let mut f = vec![0f32; w.max(h)];
+    let mut g = vec![0f32; w.max(h)];
+    for x in 0..w {
+        for y in 0..h {
+            f[y] = d[y * w + x];
+        }
+        distance(&f[..h], &mut g[..h], wy);
+        for y in 0..h {
+            d[y * w + x] = g[y];
+        }
+    }
+    for y in 0..h {
+        f[..w].copy_from_slice(&d[y * w..(y + 1) * w]);
+        distance(&f[..w], &mut g[..w], wx);
+        d[y * w..(y + 1) * w].copy_from_slice(&g[..w]);
+    }
+
+    let r = rx.max(ry);
+    for (i, v) in out.data.iter_mut().enumerate() {
+        let a = ((1.0 - d[i].sqrt()) * r + 0.5).clamp(0.0, 1.0);
+        let (x, y) = ((i % w) as i32 + out.x, (i / w) as i32 + out.y);
+        *v = ((a * 255.0 + 0.5) as u8).max(b.get(x, y));
+    }
+    out
+}
+
+/// One-dimensional distance transform of Felzenszwalb and Huttenlocher,
+/// `g[p] = min(f[q] + k * (p - q)²)`
+fn distance(f: &[f32], g: &mut [f32], k: f32) {
+    let n = f.len();
+    if n == 0 {
+        return;
+    }
+    // Parabolas of the lower envelope, and where each starts
+    let mut v = vec![0usize; n];
+    let mut z = vec![0f32; n + 1];
+    let mut j = 0;
+    z[0] = f32::NEG_INFINITY;
+    z[1] = f32::INFINITY;
+    let meet = |q: usize, p: usize| {
+        let (q2, p2) = ((q * q) as f32, (p * p) as f32);
+        ((f[q] + k * q2) - (f[p] + k * p2)) / (2.0 * k * (q as f32 - p as f32))
+    };
+    for q in 1..n {
+        let mut s = meet(q, v[j]);
+        while s <= z[j] && j > 0 {
+            j -= 1;
+            s = meet(q, v[j]);
+        }
+        if s <= z[j] {
+            // Only possible with j = 0, the new parabola takes over
+            v[0] = q;
+            z[1] = f32::INFINITY;
+            continue;
+        }
+        j += 1;
+        v[j] = q;
+        z[j] = s;
+        z[j + 1] = f32::INFINITY;
+    }
+    j = 0;
+    for (p, out) in g.iter_mut().enumerate() {
+        while z[j + 1] < p as f32 {
+            j += 1;
+        }
+        let dp = p as f32 - v[j] as f32;
+        *out = f[v[j]] + k * dp * dp;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fill_and_filters() {
+        let limit = Rect::new(0, 0, 100, 100);
+        let square = fill(&Outline::rect(2.0, 2.0, 6.0, 6.5), limit);
+        assert_eq!(square.rect(), Rect::new(2, 2, 6, 7));
+        assert_eq!(square.get(3, 3), 255);
+        assert_eq!(square.get(3, 6), 128);
+        assert_eq!(square.get(1, 3), 0);
+
+        // Upside down contours fill the same
+        assert_eq!(fill(&Outline::rect(2.0, 6.5, 6.0, 2.0), limit), square);
+
+        // Cropped to the limit
+        let big = fill(&Outline::rect(-50.0, -50.0, 50.0, 50.0), limit);
+        assert_eq!((big.rect(), big.get(0, 0)), (Rect::new(0, 0, 50, 50), 255));
+
+        let b = border(&square, 2.0, 2.0);
+        assert_eq!((b.get(3, 3), b.get(1, 3), b.get(0, 3), b.get(-1, 3)), (255, 255, 128, 0));
+        assert_eq!(b.get(6, 7), 255);
+
+        let x = border(&square, 2.0, 0.0);
+        assert_eq!((x.get(1, 3), x.get(3, 1)), (255, 0));
+
+        let blurred = blur(&square, 1.0);
+        assert!(blurred.get(1, 3) > 0 && blurred.get(3, 3) < 255);
+        let be = blur_edges(&square, 1);
+        assert_eq!((be.get(1, 4), be.get(0, 4)), (64, 0));
+
+        let moved = square.shift(10.5, 0.0);
+        assert_eq!((moved.get(12, 3), moved.get(13, 3), moved.get(16, 3)), (128, 255, 128));
+
+        let trimmed = big.to_rect(Rect::new(-10, -10, 60, 60)).trim();
+        assert_eq!(trimmed.rect(), Rect::new(0, 0, 50, 50));
+    }
+}
@@ -0,0 +1,474 @@
+//! Laying out and drawing a single event
+
+use alloc::{string::String, vec, vec::Vec};
+
+use backside_types::{
+    ClipShape, Collisions, Colour, Error, Event, KaraokeKind, Script, ScriptType, Style, Token, Timestamp,
+};
+#[cfg(not(any(test, feature = "std")))]
+use core_maths::CoreFloat;
+
+use crate::font::{Fallback, Font, FontProvider};
+use crate::layout::{self, Glyph, Line, Source, Span};
+use crate::outline::{self, Outline, Point};
+use crate::raster::{self, Bitmap, Rect};
+use crate::shape::{Shaper, Shaping};
+use crate::state::{faded, Ctx, Settings, State};
+use crate::{Image, ImageKind};
+
+/// Distance of the viewer to the screen for `\frx` and `\fry`, in pixels
+const PERSPECTIVE: f32 = 20000.0;
+
+/// The video frame being drawn on, and how
+pub struct Frame {
+    pub w: i32,
+    pub h: i32,
+    /// Frame pixels per script pixel
+    pub scale: (f32, f32),
+    /// Frame pixels per pixel of border, shadow and blur
+    pub border_scale: (f32, f32),
+    /// `PlayResX` and `PlayResY`
+    pub play_res: (f32, f32),
+    pub shaping: Shaping,
+}
+
+/// An event laid out, in script pixels
+pub struct Laid {
+    glyphs: Vec<Glyph>,
+    states: Vec<State>,
+    lines: Vec<Line>,
+    settings: Settings,
+    /// Left end of the baseline of each line
+    origins: Vec<Point>,
+    /// Top and bottom of the text
+    top: f32,
+    bottom: f32,
+    /// Point the alignment is relative to, `\org` by default
+    anchor: Point,
+    /// Milliseconds since the start of the event
+    t: i32,
+}
+
+impl Laid {
+    pub fn is_positioned(&self) -> bool {
+        self.settings.pos.is_some()
+    }
+
+    fn shift(&mut self, dy: f32) {
+        for o in &mut self.origins {
+            o.y += dy;
+        }
+        self.top += dy;
+        self.bottom += dy;
+        self.anchor.y += dy;
+    }
+
+    /// Move out of the way of the `placed` events, given as their top and
+    /// bottom, and add this one to them
+    pub fn collide(&mut self, placed: &mut Vec<(f32, f32)>, c: Collisions) {
+        let top = (self.settings.align - 1) / 3 == 2;
+        let up = top != (c == Collisions::Normal);
+        for _ in 0..=placed.len() {
+            let Some(&(t, b)) = placed.iter().find(|&&(t, b)| t < self.bottom && self.top < b) else {
+                break;
+            };
+            self.shift(if up { t - self.bottom } else { b - self.top });
+        }
+        placed.push((self.top, self.bottom));
+    }
+}
+
+/// Style `e` refers to, libass' default one if there is no such style
+fn style_of<'a>(script: &'a Script, e: &Event, default: &'a Style) -> &'a Style {
+    script.styles.iter().find(|s| s.name == e.style).unwrap_or(default)
+}
+
+/// Font for `state`, noting in `fallbacks` if it stands in for another
+fn select<'a>(fonts: &'a dyn FontProvider, state: &State, fallbacks: &mut Vec<Fallback>) -> Option<&'a Font> {
+    let found = fonts.select(&state.font, state.weight, state.italic);
+    if found.is_none_or(|s| s.fallback) {
+        note(fallbacks, Fallback {
+            family: state.font.clone(),
+            character: None,
+            used: found.and_then(|s| s.font.families.first().cloned()),
+        });
+    }
+    found.map(|s| s.font)
+}
+
+/// `text` cut where it goes from being drawn in `font` to the fallback
+/// of the characters it doesn't have, noting those in `fallbacks`
+fn runs<'a, 's>(
+    fonts: &'a dyn FontProvider,
+    font: Option<&'a Font>,
+    text: &'s str,
+    state: &State,
+    fallbacks: &mut Vec<Fallback>,
+) -> Vec<(Option<&'a Font>, &'s str)> {
+    let Some(font) = font else {
+        return vec![(None, text)];
+    };
+    let mut runs: Vec<(Option<&Font>, &str)> = Vec::new();
+    let mut start = 0;
+    for (i, c) in text.char_indices() {
+        let f = match font.covers(c) {
+            true => font,
+            false => {
+                let fallback = fonts.fallback(c, state.weight, state.italic);
+                note(fallbacks, Fallback {
+                    family: state.font.clone(),
+                    character: Some(c),
+                    used: fallback.and_then(|f| f.families.first().cloned()),
+                });
+                // Drawn as missing if there's nothing better
+                fallback.unwrap_or(font)
+            },
+        };
+        match runs.last_mut() {
+            Some((Some(last), run)) if core::ptr::eq(*last, f) => *run = &text[start..i + c.len_utf8()],
+            _ => {
+                start = i;
+                runs.push((Some(f), &text[i..i + c.len_utf8()]));
+            },
+        }
+    }
+    runs
+}
+
+fn note(fallbacks: &mut Vec<Fallback>, fallback: Fallback) {
+    if !fallbacks.contains(&fallback) {
+        fallbacks.push(fallback);
+    }
+}
+
+/// Lay `e` out at `t`, `None` if there's nothing to draw
+///
+/// Fonts and characters that were missing are added to `fallbacks`, tags
+/// that got dropped to `warnings`.
+pub fn layout(
+    fonts: &dyn FontProvider,
+    script: &Script,
+    e: &Event,
+    frame: &Frame,
+    t: Timestamp,
+    fallbacks: &mut Vec<Fallback>,
+    warnings: &mut Vec<Error>,
+) -> Option<Laid> {
+    let default = Style::default();
+    let style = style_of(script, e, &default);
+    let ctx = Ctx {
+        style,
+        styles: &script.styles,
+        t: (t - e.start).as_ms() as i32,
+        dur: (e.end - e.start).as_ms() as i32,
+    };
+    let align = style.numpad_alignment(script.info.script_type() == Some(ScriptType::V4));
+
+    let mut state = State::new(style);
+    let mut settings = Settings::new(align, script.info.wrap_style());
+    let mut states = vec![state.clone()];
+    // All of the text, for bidi and shaping to see past override blocks
+    let mut text = String::new();
+    let mut spans = Vec::new();
+    let detail = frame.scale.0.max(frame.scale.1);
+    let shaper = Shaper { mode: frame.shaping, kerning: script.info.kerning() };
+    let mut dropped = Vec::new();
+    let tokens = backside_parser::parse_dialogue_lenient(&e.text, &mut dropped);
+    for w in dropped {
+        if !warnings.contains(&w) {
+            warnings.push(w);
+        }
+    }
+    for token in tokens {
+        let si = states.len() - 1;
+        // Only looked for when there's text, for fallbacks to be those that show
+        let face = match token {
+            Token::OverrideBlock(_) | Token::Comment(_) => None,
+            Token::Text(_) if state.drawing > 0 => None,
+            _ => select(fonts, &state, fallbacks),
+        };
+        match token {
+            Token::OverrideBlock(codes) => {
+                for c in &codes {
+                    state.apply(c, &ctx);
+                    settings.apply(c, &ctx);
+                }
+                states.push(state.clone());
+            },
+            // Drawings are neutral to bidi, like libass' object replacement
+            Token::Text(d) if state.drawing > 0 => spans.push(span(&mut text, "\u{fffc}", si, Source::Drawing(d))),
+            Token::Text(_) | Token::HardSpace => {
+                let s = match token { Token::Text(s) => s, _ => "\u{a0}" };
+                for (font, run) in runs(fonts, face, s, &state, fallbacks) {
+                    spans.push(span(&mut text, run, si, Source::Text(font)));
+                }
+            },
+            Token::HardBreak => spans.push(span(&mut text, "\u{2029}", si, Source::Break(face, true))),
+            Token::SoftBreak => spans.push(span(&mut text, " ", si, Source::Break(face, false))),
+            Token::Comment(_) => {},
+        }
+    }
+    // Like libass, encoding -1 has the text take its own direction
+    let auto = style.encoding == -1;
+    let mut glyphs = layout::glyphs(&text, &spans, &states, auto, &shaper, detail);
+    if glyphs.iter().all(|g| g.outline.is_empty() && g.decorations == [None, None]) {
+        return None;
+    }
+
+    let margin = |e: i32, s: i32| if e != 0 { e } else { s } as f32;
+    let (ml, mr, mv) = (margin(e.margin_l, style.margin_l), margin(e.margin_r, style.margin_r), margin(e.margin_v, style.margin_v));
+    let (pw, ph) = frame.play_res;
+    let lines = layout::wrap(&mut glyphs, pw - ml - mr, settings.wrap);
+    layout::reorder(&mut glyphs, &lines);
+
+    let height: f32 = lines.iter().map(|l| l.ascent + l.descent).sum();
+    let width = lines.iter().map(|l| l.width).fold(0.0, f32::max);
+    // 0 to 2, left to right and bottom to top
+    let (h, v) = ((settings.align - 1) % 3, (settings.align - 1) / 3);
+    let (hk, vk) = (h as f32 / 2.0, [1.0, 0.5, 0.0][v as usize]);
+
+    let (left, top) = match settings.pos {
+        Some((x, y)) => (x - width * hk, y - height * vk),
+        None => (
+            [ml, ml + (pw - ml - mr - width) / 2.0, pw - mr - width][h as usize],
+            [ph - mv - height, (ph - height) / 2.0, mv][v as usize],
+        ),
+    };
+    let mut y = top;
+    let origins = lines.iter()
+        .map(|l| {
+            let o = Point::new(left + (width - l.width) * hk, y + l.ascent);
+            y += l.ascent + l.descent;
+            o
+        })
+        .collect();
+
+    Some(Laid {
+        glyphs,
+        states,
+        lines,
+        settings,
+        origins,
+        top,
+        bottom: top + height,
+        anchor: Point::new(left + width * hk, top + height * vk),
+        t: ctx.t,
+    })
+}
+
+/// `s` added to the end of `text`, as a span of it
+fn span<'a>(text: &mut String, s: &str, state: usize, source: Source<'a>) -> Span<'a> {
+    let start = text.len();
+    text.push_str(s);
+    Span { range: start..text.len(), state, source }
+}
+
+/// `\frx`, `\fry`, `\frz`, `\fax` and `\fay` the way VSFilter applies them,
+/// around `org` and shearing relative to `origin`
+fn transform(p: Point, s: &State, org: Point, origin: Point) -> Point {
+    let x = p.x + s.shear.0 * (p.y - origin.y) - org.x;
+    let y = p.y + s.shear.1 * (p.x - origin.x) - org.y;
+    let [(sax, cax), (say, cay), (saz, caz)] = s.rotation.map(|a| a.to_radians().sin_cos());
+
+    let (xx, yy) = (x * caz + y * saz, -(x * saz - y * caz));
+    let (y, z) = (yy * cax, yy * sax);
+    let (xx, zz) = (xx * cay + z * say, xx * say - z * cay);
+    let zz = zz.max(-(PERSPECTIVE - 1000.0));
+    let k = PERSPECTIVE / (zz + PERSPECTIVE);
+    Point::new(xx * k + org.x, y * k + org.y)
+}
+
+/// Draw a laid out event: every shadow, then every border, then every fill
+pub fn draw(laid: &Laid, frame: &Frame) -> Vec<Image> {
+    let (sx, sy) = frame.scale;
+    let (bx, by) = frame.border_scale;
+    let screen = Rect::new(0, 0, frame.w, frame.h);
+    let s = &laid.settings;
+    let org = s.org.map_or(laid.anchor, |(x, y)| Point::new(x, y));
+    let to_frame = |o: &mut Outline, st: &State, origin: Point| {
+        o.map(|p| {
+            let p = transform(p, st, org, origin);
+            Point::new(p.x * sx, p.y * sy)
+        })
+    };
+
+    let clip = s.clip.as_ref().map(|(shape, inverse)| {
+        let mask = match shape {
+            ClipShape::Rect(x1, y1, x2, y2) => {
+                let o = Outline::rect(x1.min(*x2) * sx, y1.min(*y2) * sy, x1.max(*x2) * sx, y1.max(*y2) * sy);
+                raster::fill(&o, screen)
+            },
+            ClipShape::Vector(scale, d) => {
+                let mut o = outline::drawing(d, scale.unwrap_or(1), sx.max(sy));
+                o.map(|p| Point::new(p.x * sx, p.y * sy));
+                raster::fill(&o, screen)
+            },
+        };
+        (mask, *inverse)
+    });
+
+    let mut out = [Vec::new(), Vec::new(), Vec::new()];
+    let mut push = |b: Bitmap, colour: Colour, kind: ImageKind| {
+        let mut b = b.to_rect(b.rect().intersect(&screen));
+        if let Some((mask, inverse)) = &clip {
+            b.mask(|x, y| if *inverse { 255 - mask.get(x, y) } else { mask.get(x, y) });
+        }
+        let b = b.trim();
+        let alpha = faded(colour.alpha(), s.fade);
+        if b.w > 0 && alpha < 255 {
+            let i = match kind {
+                ImageKind::Shadow    => 0,
+                ImageKind::Outline   => 1,
+                ImageKind::Character => 2,
+            };
+            out[i].push(Image::new(b, colour.with_alpha(alpha), kind));
+        }
+    };
+
+    for (line, &origin) in laid.lines.iter().zip(&laid.origins) {
+        let glyphs = &laid.glyphs[line.start..line.end];
+        for run in glyphs.chunk_by(|a, b| a.state == b.state) {
+            let st = &laid.states[run[0].state];
+            let mut o = Outline::default();
+            for g in run {
+                let at = Point::new(origin.x + g.x, origin.y);
+                let mut go = g.outline.clone();
+                go.map(|p| Point::new(p.x + at.x, p.y + at.y));
+                o.append(go);
+                for (top, thickness) in g.decorations.iter().flatten() {
+                    o.append(Outline::rect(at.x, at.y + top, at.x + g.advance, at.y + top + thickness));
+                }
+            }
+            // Glyphs are in logical order, so the run may go either way
+            let x0 = origin.x + run.iter().map(|g| g.x).fold(f32::INFINITY, f32::min);
+            let x1 = origin.x + run.iter().map(|g| g.x + g.advance).fold(f32::NEG_INFINITY, f32::max);
+            let rtl = run[0].level % 2 == 1;
+            to_frame(&mut o, st, origin);
+
+            // No wider than the frame, so that its bitmap stays about its size
+            let most = (screen.x1 - screen.x0).max(screen.y1 - screen.y0) as f32;
+            let border = ((st.border.0 * bx).min(most), (st.border.1 * by).min(most));
+            let blur = st.blur * (bx + by) / 2.0;
+            let be = st.be.round() as u32;
+            let pad = (border.0.max(border.1) + blur * 3.0 + be as f32 + st.shadow.0.abs().max(st.shadow.1.abs()) * bx.max(by)).ceil() as i32;
+            let pad = pad.saturating_add(2);
+            let fill = raster::fill(&o, screen.grow(pad));
+
+            let mut outline = if st.border_style == 3 {
+                // An opaque box around the line
+                let (ex, ey) = (border.0 / sx, border.1 / sy);
+                let mut b = Outline::rect(x0 - ex, origin.y - line.ascent - ey, x1 + ex, origin.y + line.descent + ey);
+                to_frame(&mut b, st, origin);
+                Some(raster::fill(&b, screen.grow(pad)))
+            } else if border.0 > 0.0 || border.1 > 0.0 {
+                Some(raster::border(&fill, border.0, border.1))
+            } else {
+                None
+            };
+            if fill.w == 0 && outline.is_none() {
+                continue;
+            }
+
+            let blurred = blur > 0.0 || be > 0;
+            let mut fill = fill;
+            let soft = |b: &Bitmap| raster::blur_edges(&raster::blur(b, blur), be);
+            match &mut outline {
+                Some(b) if blurred => *b = soft(b),
+                None if blurred => fill = soft(&fill),
+                _ => {},
+            }
+
+            let shadow = (st.shadow.0 * bx, st.shadow.1 * by);
+            if shadow != (0.0, 0.0) {
+                let src = outline.as_ref().unwrap_or(&fill);
+                push(src.shift(shadow.0, shadow.1), st.colours[3], ImageKind::Shadow);
+            }
+
+            let [primary, secondary, border_colour, _] = st.colours;
+            let started = |start: i32| laid.t >= start;
+            let (colour, sweep, show_border) = match st.karaoke {
+                None => (primary, None, true),
+                Some((KaraokeKind::Ko, start, _)) => (if started(start) { primary } else { secondary }, None, started(start)),
+                Some((KaraokeKind::Kf | KaraokeKind::KUpper, start, dur)) => {
+                    let k = if dur <= 0 { started(start) as i32 as f32 } else { ((laid.t as i64 - start as i64) as f32 / dur as f32).clamp(0.0, 1.0) };
+                    // Right-to-left text fills from the right
+                    let at = if rtl { x1 - (x1 - x0) * k } else { x0 + (x1 - x0) * k };
+                    (primary, Some(at * sx), true)
+                },
+                Some((_, start, _)) => (if started(start) { primary } else { secondary }, None, true),
+            };
+
+            if let Some(mut b) = outline.filter(|_| show_border) {
+                if st.border_style != 3 && !blurred {
+                    b.subtract(&fill);
+                }
+                push(b, border_colour, ImageKind::Outline);
+            }
+            match sweep {
+                Some(at) => {
+                    let (mut done, mut todo) = (fill.clone(), fill);
+                    let is_done = |x: i32| ((x as f32) < at) != rtl;
+                    done.mask(|x, _| if is_done(x) { 255 } else { 0 });
+                    todo.mask(|x, _| if is_done(x) { 0 } else { 255 });
+                    push(done, primary, ImageKind::Character);
+                    push(todo, secondary, ImageKind::Character);
+                },
+                None => push(fill, colour, ImageKind::Character),
+            }
+        }
+    }
+
+    let [shadows, borders, fills] = out;
+    shadows.into_iter().chain(borders).chain(fills).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::font::FontDatabase;
+
+    #[test]
+    fn fallback_runs() {
+        let mut db = FontDatabase::new();
+        db.add_font(crate::FIXTURE_LATIN);
+        db.add_font(crate::FIXTURE);
+        let style = Style { font_name: "Fixture Sans Latin".into(), ..Style::default() };
+        let state = State::new(&style);
+        let mut fallbacks = Vec::new();
+        let font = select(&db, &state, &mut fallbacks);
+
+        // Cut where the characters the font doesn't have start and end
+        let text = "ab \u{5e9}\u{5dc}\u{5d5}\u{5dd} c\u{5d0}\u{4e2d}";
+        let found: Vec<_> = runs(&db, font, text, &state, &mut fallbacks).into_iter()
+            .map(|(f, run)| (f.map(|f| f.families[0].as_str()), run))
+            .collect();
+        assert_eq!(found, [
+            (Some("Fixture Sans Latin"), "ab "),
+            (Some("Fixture Sans"), "\u{5e9}\u{5dc}\u{5d5}\u{5dd}"),
+            (Some("Fixture Sans Latin"), " c"),
+            (Some("Fixture Sans"), "\u{5d0}"),
+            // Nothing has it, drawn as missing in the font asked for
+            (Some("Fixture Sans Latin"), "\u{4e2d}"),
+        ]);
+
+        // Once each, with what stood in for them
+        let missing: Vec<_> = fallbacks.iter().map(|f| (f.family.as_str(), f.character, f.used.as_deref())).collect();
+        assert_eq!(missing, [
+            ("Fixture Sans Latin", Some('\u{5e9}'), Some("Fixture Sans")),
+            ("Fixture Sans Latin", Some('\u{5dc}'), Some("Fixture Sans")),
+            ("Fixture Sans Latin", Some('\u{5d5}'), Some("Fixture Sans")),
+            ("Fixture Sans Latin", Some('\u{5dd}'), Some("Fixture Sans")),
+            ("Fixture Sans Latin", Some('\u{5d0}'), Some("Fixture Sans")),
+            ("Fixture Sans Latin", Some('\u{4e2d}'), None),
+        ]);
+        runs(&db, font, "\u{5e9}", &state, &mut fallbacks);
+        assert_eq!(fallbacks.len(), 6);
+
+        // A family there's no font of
+        let state = State::new(&Style { font_name: "Nowhere".into(), ..Style::default() });
+        let font = select(&db, &state, &mut fallbacks).unwrap();
+        assert_eq!(font.families[0], "Fixture Sans Latin");
+        assert_eq!(fallbacks.last(), Some(&Fallback { family: "Nowhere".into(), character: None, used: Some("Fixture Sans Latin".into()) }));
+    }
+}
@@ -0,0 +1,284 @@
+//! Shaping: turning characters into positioned glyphs
+
+use alloc::vec::Vec;
+use core::ops::Range;
+
+use rustybuzz::{Direction, Feature, UnicodeBuffer};
+use ttf_parser::{Face, GlyphId, Tag};
+
+use crate::state::State;
+
+/// How text gets shaped, like libass' `ASS_ShapingLevel`
+#[derive(Clone, Copy, PartialEq, Eq, Default, Debug)]
+pub enum Shaping {
+    /// The way VSFilter does: a glyph per character, Arabic joined through
+    /// its presentation forms, and kerning out of the `kern` table only
+    Simple,
+    /// OpenType shaping, for ligatures, marks and the scripts that need it
+    #[default]
+    Complex,
+}
+
+/// How the text of a script gets shaped
+#[derive(Clone, Copy, Debug)]
+pub struct Shaper {
+    pub mode: Shaping,
+    /// The `Kerning` header
+    pub kerning: bool,
+}
+
+/// A glyph out of shaping, in font units
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Shaped {
+    pub id: GlyphId,
+    /// Byte offset of the first character it stands for
+    pub cluster: usize,
+    pub advance: i32,
+    /// From the pen position, y going up
+    pub offset: (i32, i32),
+}
+
+/// Glyphs of `range` of `text` in `face`, in the order of the text,
+/// right to left if `rtl`
+///
+/// The rest of `text` is the context the range gets shaped in, for
+/// letters to join across it.
+pub fn shape(face: &Face<'_>, text: &str, range: Range<usize>, rtl: bool, shaper: &Shaper, state: &State) -> Vec<Shaped> {
+    match shaper.mode {
+        Shaping::Simple => simple(face, text, range, rtl, shaper.kerning),
+        Shaping::Complex => complex(face, text, range, rtl, shaper.kerning, state),
+    }
+}
+
+fn complex(face: &Face<'_>, text: &str, range: Range<usize>, rtl: bool, kerning: bool, state: &State) -> Vec<Shaped> {
+    let hb = rustybuzz::Face::from_face(face.clone());
+    let mut buffer = UnicodeBuffer::new();
+    buffer.push_str(&text[range.clone()]);
+    buffer.set_pre_context(&text[..range.start]);
+    buffer.set_post_context(&text[range.end..]);
+    buffer.set_direction(if rtl { Direction::RightToLeft } else { Direction::LeftToRight });
+
+    let feature = |tag: &[u8; 4], on: bool| Feature::new(Tag::from_bytes(tag), on as u32, ..);
+    // Like libass: letter spacing breaks ligatures up, and `@` fonts get
+    // their vertical forms
+    let spaced = state.spacing != 0.0;
+    let vertical = state.font.trim_start().starts_with('@');
+    let mut features = Vec::from([feature(b"kern", kerning), feature(b"liga", !spaced), feature(b"clig", !spaced)]);
+    if vertical {
+        features.extend([feature(b"vert", true), feature(b"vkna", true)]);
+    }
+
+    let glyphs = rustybuzz::shape(&hb, &features, buffer);
+    let mut glyphs: Vec<Shaped> = glyphs.glyph_infos().iter()
+        .zip(glyphs.glyph_positions())
+        .map(|(info, pos)| Shaped {
+            id: GlyphId(info.glyph_id as u16),
+            cluster: range.start + info.cluster as usize,
+            advance: pos.x_advance,
+            offset: (pos.x_offset, pos.y_offset),
+        })
+        .collect();
+    // Right-to-left glyphs come out from left to right
+    if rtl && kern_flips(face, kerning).is_multiple_of(2) {
+        glyphs.reverse();
+    }
+    glyphs
+}
+
+/// How many times rustybuzz 0.20 turns right-to-left glyphs around once too
+/// often: with `kern` off, it skips each plain horizontal subtable of the
+/// `kern` table it falls back on after turning the glyphs around for it,
+/// but before turning them back
+fn kern_flips(face: &Face<'_>, kerning: bool) -> usize {
+    let t = face.tables();
+    let gpos_kern = t.gpos.is_some_and(|g| g.features.into_iter().any(|f| f.tag == Tag::from_bytes(b"kern")));
+    if kerning || t.kerx.is_some() || gpos_kern {
+        return 0;
+    }
+    t.kern.map_or(0, |k| k.subtables.into_iter().filter(|s| s.horizontal && !s.variable && !s.has_state_machine).count())
+}
+
+fn simple(face: &Face<'_>, text: &str, range: Range<usize>, rtl: bool, kerning: bool) -> Vec<Shaped> {
+    let has = |c| face.glyph_index(c).is_some();
+    let mut glyphs: Vec<Shaped> = arabic_forms(text, range, has).into_iter()
+        .map(|(cluster, c)| {
+            // Mirrored like FriBidi does, brackets and all
+            let c = unicode_bidi_mirroring::get_mirrored(c).filter(|&m| rtl && has(m)).unwrap_or(c);
+            let id = face.glyph_index(c).unwrap_or(GlyphId(0));
+            let advance = face.glyph_hor_advance(id).unwrap_or(0) as i32;
+            Shaped { id, cluster, advance, offset: (0, 0) }
+        })
+        .collect();
+
+    // Pairs go from left to right, the advance of the left one changing
+    let kern = face.tables().kern.filter(|_| kerning);
+    for subtable in kern.into_iter().flat_map(|k| k.subtables).filter(|s| s.horizontal && !s.variable) {
+        for i in 1..glyphs.len() {
+            let (l, r) = if rtl { (i, i - 1) } else { (i - 1, i) };
+            if let Some(k) = subtable.glyphs_kerning(glyphs[l].id, glyphs[r].id) {
+                glyphs[l].advance += k as i32;
+            }
+        }
+    }
+    glyphs
+}
+
+/// How an Arabic letter joins its neighbours
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+enum Joining {
+    None,
+    /// To the letter before it only
+    Right,
+    Dual,
+    /// Tatweel, joining both neighbours without changing itself
+    Causing,
+    /// Marks, which letters join across
+    Transparent,
+}
+
+const LAM: char = '\u{644}';
+
+/// Forms of `U+0621` to `U+064A` in Arabic Presentation Forms-B, which
+/// start at `U+FE80` in that order: isolated, final, initial then medial
+const FORMS: [u8; 42] = [
+    1, 2, 2, 2, 2, 4, 2, 4, 2, 4, 4, 4, 4, 4, 2, 2, 2, 2, 4, 4, 4, 4, 4, 4, 4, 4,
+    0, 0, 0, 0, 0, 0,
+    4, 4, 4, 4, 4, 4, 4, 2, 2, 4,
+];
+
+/// Joining of `c`, and its isolated form if it has presentation forms
+fn joining(c: char) -> (Joining, Option<u32>) {
+    match c as u32 {
+        n @ 0x621..=0x64a => {
+            let i = (n - 0x621) as usize;
+            let base = 0xfe80 + FORMS[..i].iter().map(|&k| k as u32).sum::<u32>();
+            match FORMS[i] {
+                1 => (Joining::None, Some(base)),
+                2 => (Joining::Right, Some(base)),
+                4 => (Joining::Dual, Some(base)),
+                _ if c == '\u{640}' => (Joining::Causing, None),
+                _ => (Joining::Dual, None),
+            }
+        },
+        0x610..=0x61a | 0x64b..=0x65f | 0x670 | 0x6d6..=0x6dc | 0x6df..=0x6e4 | 0x6e7 | 0x6e8 | 0x6ea..=0x6ed => {
+            (Joining::Transparent, None)
+        },
+        _ => (Joining::None, None),
+    }
+}
+
+/// Characters of `range` of `text` with their byte offsets, Arabic letters
+/// replaced by the presentation forms their neighbours call for, lam-alef
+/// by its ligature, as long as the font `has` them
+fn arabic_forms(text: &str, range: Range<usize>, has: impl Fn(char) -> bool) -> Vec<(usize, char)> {
+    let chars: Vec<(usize, char)> = text.char_indices().collect();
+    let joinings: Vec<Joining> = chars.iter().map(|&(_, c)| joining(c).0).collect();
+    let neighbour = |it: &mut dyn Iterator<Item = usize>| it.map(|j| joinings[j]).find(|&j| j != Joining::Transparent);
+
+    let mut out = Vec::with_capacity(range.len());
+    let mut i = chars.partition_point(|&(at, _)| at < range.start);
+    while i < chars.len() && chars[i].0 < range.end {
+        let (at, c) = chars[i];
+        let joins_prev = matches!(joinings[i], Joining::Right | Joining::Dual | Joining::Causing)
+            && matches!(neighbour(&mut (0..i).rev()), Some(Joining::Dual | Joining::Causing));
+        let joins_next = matches!(joinings[i], Joining::Dual | Joining::Causing)
+            && matches!(neighbour(&mut (i + 1..chars.len())), Some(Joining::Right | Joining::Dual | Joining::Causing));
+
+        // Lam-alef ligatures, isolated then final, for each alef
+        let alef = chars.get(i + 1).filter(|&&(at, _)| at < range.end).and_then(|&(_, a)| ['\u{622}', '\u{623}', '\u{625}', '\u{627}'].iter().position(|&x| x == a));
+        if let Some(k) = alef.filter(|_| c == LAM) {
+            let lig = char::from_u32(0xfef5 + 2 * k as u32 + joins_prev as u32).unwrap_or(c);
+            if has(lig) {
+                out.push((at, lig));
+                i += 2;
+                continue;
+            }
+        }
+
+        let form = match (joins_prev, joins_next) {
+            (false, false) => 0,
+            (true, false) => 1,
+            (false, true) => 2,
+            (true, true) => 3,
+        };
+        let shaped = match joining(c) {
+            (_, Some(base)) => char::from_u32(base + form).filter(|&f| has(f)).unwrap_or(c),
+            _ => c,
+        };
+        out.push((at, shaped));
+        i += 1;
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use backside_types::Style;
+
+    #[test]
+    fn shaping() {
+        let font = crate::fixture();
+        let face = font.face().unwrap();
+        let id = |c| face.glyph_index(c).unwrap().0;
+        let advance = |c| face.glyph_hor_advance(face.glyph_index(c).unwrap()).unwrap() as i32;
+        let mut state = State::new(&Style::default());
+        let run = |t: &str, rtl, mode, kerning, state: &State| {
+            shape(&face, t, 0..t.len(), rtl, &Shaper { mode, kerning }, state).into_iter()
+                .map(|g| (g.id.0, g.cluster, g.advance))
+                .collect::<Vec<_>>()
+        };
+        let ids = |glyphs: Vec<(u16, usize, i32)>| glyphs.into_iter().map(|g| (g.0, g.1)).collect::<Vec<_>>();
+
+        // `fi` ligature, `AV` only kerned with the `Kerning` header
+        for mode in [Shaping::Simple, Shaping::Complex] {
+            let plain = run("AV", false, mode, false, &state);
+            assert_eq!(plain, [(id('A'), 0, advance('A')), (id('V'), 1, advance('V'))], "{:?}", mode);
+            let kerned = run("AV", false, mode, true, &state);
+            assert!(kerned[0].2 + kerned[1].2 < advance('A') + advance('V'), "{:?}", mode);
+        }
+        assert_eq!(ids(run("fi", false, Shaping::Complex, false, &state)), [(id('\u{fb01}'), 0)]);
+        assert_eq!(ids(run("fi", false, Shaping::Simple, false, &state)), [(id('f'), 0), (id('i'), 1)]);
+        // Letter spacing breaks ligatures up
+        state.spacing = 1.0;
+        assert_eq!(ids(run("fi", false, Shaping::Complex, false, &state)), [(id('f'), 0), (id('i'), 1)]);
+        state.spacing = 0.0;
+
+        // Right to left comes out in the order of the text either way, which
+        // rustybuzz has wrong for fonts with a `kern` table and `kern` off
+        for (mode, kerning) in [(Shaping::Simple, false), (Shaping::Simple, true), (Shaping::Complex, false), (Shaping::Complex, true)] {
+            let beh = ids(run("\u{628}\u{628}\u{628}", true, mode, kerning, &state));
+            assert_eq!(beh, [(id('\u{fe91}'), 0), (id('\u{fe92}'), 2), (id('\u{fe90}'), 4)], "{:?} {}", mode, kerning);
+            let lam_alef = ids(run("\u{628}\u{644}\u{627}", true, mode, kerning, &state));
+            assert_eq!(lam_alef, [(id('\u{fe91}'), 0), (id('\u{fefc}'), 2)], "{:?} {}", mode, kerning);
+            // Brackets mirrored
+            let hebrew = ids(run("(\u{5e9})", true, mode, kerning, &state));
+            assert_eq!(hebrew, [(id(')'), 0), (id('\u{5e9}'), 1), (id('('), 3)], "{:?} {}", mode, kerning);
+        }
+        assert_eq!(kern_flips(&face, false), 1);
+        assert_eq!(kern_flips(&face, true), 0);
+    }
+
+    #[test]
+    fn arabic() {
+        let forms = |s: &str| arabic_forms(s, 0..s.len(), |_| true).into_iter().map(|(_, c)| c as u32).collect::<Vec<_>>();
+        // Beh alone, then beh-beh-beh: initial, medial, final
+        assert_eq!(forms("\u{628}"), [0xfe8f]);
+        assert_eq!(forms("\u{628}\u{628}\u{628}"), [0xfe91, 0xfe92, 0xfe90]);
+        // Alef doesn't join what follows it, a fatha doesn't break joining
+        assert_eq!(forms("\u{628}\u{627}\u{628}"), [0xfe91, 0xfe8e, 0xfe8f]);
+        assert_eq!(forms("\u{628}\u{64e}\u{628}"), [0xfe91, 0x64e, 0xfe90]);
+        // Yeh, tatweel, and beyond Arabic
+        assert_eq!(forms("\u{64a}\u{640}a"), [0xfef3, 0x640, 'a' as u32]);
+        // Lam-alef, isolated then final
+        assert_eq!(forms("\u{644}\u{627}"), [0xfefb]);
+        assert_eq!(forms("\u{628}\u{644}\u{627}"), [0xfe91, 0xfefc]);
+
+        // Without the forms in the font, letters stay as they are
+        let plain = arabic_forms("\u{628}\u{644}\u{627}", 0..6, |c| c == '\u{fe91}');
+        assert_eq!(plain, [(0, '\u{fe91}'), (2, '\u{644}'), (4, '\u{627}')]);
+        // Joining what's around the range
+        assert_eq!(arabic_forms("\u{628}\u{628}\u{628}", 2..4, |_| true), [(2, '\u{fe92}')]);
+    }
+}
@@ -0,0 +1,395 @@
+//! What the override tags set, at a given time of an event
+
+use alloc::string::String;
+
+use backside_types::{numpad, ClipShape, Colour, KaraokeKind, OverrideCode, Style, WrapStyle, XOrYOrZ};
+#[cfg(not(any(test, feature = "std")))]
+use core_maths::CoreFloat;
+
+/// Strongest `\be` and `\blur`, past which libass clamps them
+const MAX_BE: f32 = 127.0;
+const MAX_BLUR: f32 = 100.0;
+/// Widest `\bord` and farthest `\shad`, in script pixels, to keep the sizes
+/// of bitmaps in range
+const MAX_BORDER: f32 = 10000.0;
+
+/// Everything about the event the tags get to change
+pub struct Ctx<'a> {
+    /// Style of the event
+    pub style: &'a Style,
+    /// Every style of the script, for `\r`
+    pub styles: &'a [Style],
+    /// Milliseconds since the start of the event
+    pub t: i32,
+    /// Duration of the event, in milliseconds
+    pub dur: i32,
+}
+
+/// How a run of text gets drawn
+#[derive(Clone, PartialEq, Debug)]
+pub struct State {
+    pub font: String,
+    pub size: f32,
+    pub weight: u32,
+    pub italic: bool,
+    pub underline: bool,
+    pub strikeout: bool,
+    /// `\fscx` and `\fscy`, as fractions
+    pub scale: (f32, f32),
+    pub spacing: f32,
+    /// `\frx`, `\fry` and `\frz`, in degrees
+    pub rotation: [f32; 3],
+    /// `\fax` and `\fay`
+    pub shear: (f32, f32),
+    /// Primary, secondary, outline and back colours, alpha included
+    pub colours: [Colour; 4],
+    pub border: (f32, f32),
+    pub shadow: (f32, f32),
+    pub blur: f32,
+    /// `\be`, rounded when drawing
+    pub be: f32,
+    pub border_style: i8,
+    /// `\p` level, `0` for text
+    pub drawing: u32,
+    pub pbo: f32,
+    /// Kind, start and duration in milliseconds of the current syllable
+    pub karaoke: Option<(KaraokeKind, i32, i32)>,
+    /// Start of the next syllable
+    karaoke_next: i32,
+}
+
+impl State {
+    pub fn new(s: &Style) -> Self {
+        Self {
+            font: s.font_name.clone(),
+            size: s.font_size,
+            weight: if s.bold { 700 } else { 400 },
+            italic: s.italic,
+            underline: s.underline,
+            strikeout: s.strikeout,
+            scale: (s.scale_x / 100.0, s.scale_y / 100.0),
+            spacing: s.spacing,
+            rotation: [0.0, 0.0, s.angle],
+            shear: (0.0, 0.0),
+            colours: [s.primary_color, s.secondary_color, s.outline_color, s.back_color],
+            border: (s.outline, s.outline),
+            shadow: (s.shadow, s.shadow),
+            blur: 0.0,
+            be: 0.0,
+            border_style: s.border_style,
+            drawing: 0,
+            pbo: 0.0,
+            karaoke: None,
+            karaoke_next: 0,
+        }
+    }
+
+    /// Back to `s`, keeping the karaoke timing
+    fn reset(&mut self, s: &Style) {
+        let (karaoke, next, drawing) = (self.karaoke, self.karaoke_next, self.drawing);
+        *self = Self::new(s);
+        (self.karaoke, self.karaoke_next, self.drawing) = (karaoke, next, drawing);
+    }
+
+    /// Apply a tag, tags that set something for the whole event aside
+    pub fn apply(&mut self, code: &OverrideCode, ctx: &Ctx<'_>) {
+        use OverrideCode::*;
+
+        let s = ctx.style;
+        let both = |v: &mut (f32, f32), axis: &Option<XOrYOrZ>, to: f32| match axis {
+            Some(XOrYOrZ::X) => v.0 = to,
+            Some(XOrYOrZ::Y) => v.1 = to,
+            _ => *v = (to, to),
+        };
+        match code {
+            Bold(b) => self.weight = match b {
+                None    => if s.bold { 700 } else { 400 },
+                Some(0) => 400,
+                Some(1) => 700,
+                Some(w) => *w,
+            },
+            Italic(v)    => self.italic = v.unwrap_or(s.italic),
+            Underline(v) => self.underline = v.unwrap_or(s.underline),
+            Strikeout(v) => self.strikeout = v.unwrap_or(s.strikeout),
+            Border(axis, v) => both(&mut self.border, axis, finite(v.unwrap_or(s.outline)).clamp(0.0, MAX_BORDER)),
+            Shadow(axis, v) => both(&mut self.shadow, axis, finite(v.unwrap_or(s.shadow)).clamp(-MAX_BORDER, MAX_BORDER)),
+            BlurEdges(v) => self.be = finite(v.unwrap_or(0.0)).clamp(0.0, MAX_BE),
+            Blur(v)      => self.blur = finite(v.unwrap_or(0.0)).clamp(0.0, MAX_BLUR),
+            FontName(v)  => self.font = v.clone().unwrap_or_else(|| s.font_name.clone()),
+            FontSize(v)  => self.size = v.filter(|&v| v > 0.0).unwrap_or(s.font_size),
+            FontSizeRelative(v) => {
+                let size = self.size * (1.0 + v / 10.0);
+                self.size = if size > 0.0 { size } else { s.font_size };
+            },
+            Scale(axis, v) => match (axis, v) {
+                (Some(XOrYOrZ::X), Some(v)) => self.scale.0 = v.max(0.0) / 100.0,
+                (Some(XOrYOrZ::Y), Some(v)) => self.scale.1 = v.max(0.0) / 100.0,
+                _ => self.scale = (s.scale_x / 100.0, s.scale_y / 100.0),
+            },
+            Spacing(v) => self.spacing = v.unwrap_or(s.spacing),
+            Rotation(axis, v) => {
+                let i = *axis as usize;
+                self.rotation[i] = v.unwrap_or(if i == 2 { s.angle } else { 0.0 });
+            },
+            Shear(XOrYOrZ::Y, v) => self.shear.1 = v.unwrap_or(0.0),
+            Shear(_, v)          => self.shear.0 = v.unwrap_or(0.0),
+            Color(slot, c) => if let Some(i) = slot_index(*slot) {
+                let style = [s.primary_color, s.secondary_color, s.outline_color, s.back_color][i];
+                let c = c.unwrap_or(style);
+                self.colours[i] = c.with_alpha(self.colours[i].alpha());
+            },
+            Alpha(slot, a) => {
+                let style = [s.primary_color, s.secondary_color, s.outline_color, s.back_color];
+                for (i, (c, style)) in self.colours.iter_mut().zip(style).enumerate() {
+                    if slot.is_none_or(|s| slot_index(s) == Some(i)) {
+                        *c = c.with_alpha(a.unwrap_or(style.alpha()));
+                    }
+                }
+            },
+            Karaoke(KaraokeKind::Kt, t) => self.karaoke_next = t.saturating_mul(10),
+            Karaoke(kind, t) => {
+                let t = t.saturating_mul(10);
+                self.karaoke = Some((*kind, self.karaoke_next, t));
+                self.karaoke_next = self.karaoke_next.saturating_add(t);
+            },
+            Reset(name) => {
+                let style = name.as_deref()
+                    .and_then(|n| ctx.styles.iter().find(|s| s.name == n))
+                    .unwrap_or(s);
+                self.reset(style);
+            },
+            Drawing(p)        => self.drawing = *p,
+            BaselineOffset(v) => self.pbo = *v,
+            Transform(times, accel, codes) => {
+                let k = progress(*times, *accel, ctx);
+                let mut to = self.clone();
+                for c in codes.iter().filter(|c| animatable(c)) {
+                    to.apply(c, ctx);
+                }
+                self.animate(&to, k);
+            },
+            _ => {},
+        }
+    }
+
+    /// Move the animatable values `k` of the way to those of `to`
+    fn animate(&mut self, to: &State, k: f32) {
+        let f = |a: &mut f32, b: f32| *a += (b - *a) * k;
+        f(&mut self.size, to.size);
+        f(&mut self.scale.0, to.scale.0);
+        f(&mut self.scale.1, to.scale.1);
+        f(&mut self.spacing, to.spacing);
+        for i in 0..3 {
+            f(&mut self.rotation[i], to.rotation[i]);
+        }
+        f(&mut self.shear.0, to.shear.0);
+        f(&mut self.shear.1, to.shear.1);
+        for i in 0..4 {
+            self.colours[i] = lerp_colour(self.colours[i], to.colours[i], k);
+        }
+        f(&mut self.border.0, to.border.0);
+        f(&mut self.border.1, to.border.1);
+        f(&mut self.shadow.0, to.shadow.0);
+        f(&mut self.shadow.1, to.shadow.1);
+        f(&mut self.blur, to.blur);
+        f(&mut self.be, to.be);
+    }
+}
+
+/// Index in [`State::colours`] of colour slot `1` to `4`
+fn slot_index(slot: u8) -> Option<usize> {
+    (1..=4).contains(&slot).then(|| slot as usize - 1)
+}
+
+/// `k` of the way from `a` to `b`, `k` being clamped to `0..=1`
+fn lerp(a: u8, b: u8, k: f32) -> u8 {
+    (a as f32 + (b as f32 - a as f32) * k.clamp(0.0, 1.0) + 0.5) as u8
+}
+
+fn lerp_colour(a: Colour, b: Colour, k: f32) -> Colour {
+    let (a, b) = (a.0.to_le_bytes(), b.0.to_le_bytes());
+    Colour(u32::from_le_bytes(core::array::from_fn(|i| lerp(a[i], b[i], k))))
+}
+
+/// Tags `\t` can animate, the others being ignored in it
+fn animatable(c: &OverrideCode) -> bool {
+    use OverrideCode::*;
+
+    matches!(c,
+        FontSize(_) | FontSizeRelative(_) | Scale(..) | Spacing(_) | Rotation(..) | Shear(..) | Color(..) | Alpha(..)
+        | Border(..) | Shadow(..) | Blur(_) | BlurEdges(_) | Clip(ClipShape::Rect(..)) | InverseClip(ClipShape::Rect(..))
+    )
+}
+
+/// `v`, or `0` for NaN, which `clamp` would keep
+fn finite(v: f32) -> f32 {
+    if v.is_nan() { 0.0 } else { v }
+}
+
+/// How far along a `\t` is, `0` to `1`
+pub fn progress(times: Option<(i32, i32)>, accel: Option<f32>, ctx: &Ctx<'_>) -> f32 {
+    let (t1, t2) = times.unwrap_or((0, ctx.dur));
+    let k = if ctx.t < t1 {
+        0.0
+    } else if ctx.t >= t2 || t2 <= t1 {
+        1.0
+    } else {
+        (ctx.t as i64 - t1 as i64) as f32 / (t2 as i64 - t1 as i64) as f32
+    };
+    k.powf(accel.filter(|&a| a > 0.0).unwrap_or(1.0))
+}
+
+/// What the tags set for the whole event
+#[derive(Clone, PartialEq, Debug)]
+pub struct Settings {
+    /// Numpad alignment
+    pub align: u8,
+    pub pos: Option<(f32, f32)>,
+    pub org: Option<(f32, f32)>,
+    /// Transparency from `\fad` and `\fade`
+    pub fade: u8,
+    /// Shape, and whether it's an `\iclip`
+    pub clip: Option<(ClipShape, bool)>,
+    pub wrap: WrapStyle,
+    has_align: bool,
+    has_fade: bool,
+}
+
+impl Settings {
+    pub fn new(align: u8, wrap: WrapStyle) -> Self {
+        Self {
+            align,
+            pos: None,
+            org: None,
+            fade: 0,
+            clip: None,
+            wrap,
+            has_align: false,
+            has_fade: false,
+        }
+    }
+
+    /// Apply a tag, the first of `\pos`, `\move`, `\org`, `\fad` and
+    /// alignment counting while the last `\clip` and `\q` do
+    pub fn apply(&mut self, code: &OverrideCode, ctx: &Ctx<'_>) {
+        use OverrideCode::*;
+
+        let (t, dur) = (ctx.t, ctx.dur);
+        match code {
+            Alignment(a) | AlignmentNumpad(a) if !self.has_align => {
+                self.has_align = true;
+                self.align = match (code, a) {
+                    (_, None)              => self.align,
+                    (Alignment(_), Some(a)) => numpad(*a),
+                    (_, Some(a))           => (*a).clamp(1, 9),
+                };
+            },
+            Position(x, y) if self.pos.is_none() => self.pos = Some((*x, *y)),
+            Move(x1, y1, x2, y2, times) if self.pos.is_none() => {
+                let (t1, t2) = times.filter(|&(a, b)| (a, b) != (0, 0)).unwrap_or((0, dur));
+                let k = match () {
+                    _ if t <= t1 || t2 <= t1 => 0.0,
+                    _ if t >= t2 => 1.0,
+                    _ => (t - t1) as f32 / (t2 - t1) as f32,
+                };
+                self.pos = Some((x1 + (x2 - x1) * k, y1 + (y2 - y1) * k));
+            },
+            Origin(x, y) if self.org.is_none() => self.org = Some((*x, *y)),
+            Fade(t1, t2) if !self.has_fade => {
+                self.has_fade = true;
+                let out = dur - t2;
+                self.fade = match () {
+                    _ if t < *t1 => lerp(255, 0, t as f32 / *t1 as f32),
+                    _ if t > out => lerp(0, 255, (t - out) as f32 / *t2 as f32),
+                    _ => 0,
+                };
+            },
+            FadeComplex(a1, a2, a3, t1, t2, t3, t4) if !self.has_fade => {
+                self.has_fade = true;
+                self.fade = match () {
+                    _ if t < *t1 => *a1,
+                    _ if t < *t2 => lerp(*a1, *a2, (t - t1) as f32 / (t2 - t1) as f32),
+                    _ if t < *t3 => *a2,
+                    _ if t < *t4 => lerp(*a2, *a3, (t - t3) as f32 / (t4 - t3) as f32),
+                    _ => *a3,
+                };
+            },
+            Clip(c)        => self.clip = Some((c.clone(), false)),
+            InverseClip(c) => self.clip = Some((c.clone(), true)),
+            WrappingStyle(q) => self.wrap = match q {
+                Some(0) => WrapStyle::Smart,
+                Some(1) => WrapStyle::EndOfLine,
+                Some(2) => WrapStyle::None,
+                Some(3) => WrapStyle::SmartLower,
+                _ => self.wrap,
+            },
+            Transform(times, accel, codes) => {
+                let k = progress(*times, *accel, ctx);
+                let to = codes.iter().rev().find_map(|c| match c {
+                    Clip(ClipShape::Rect(a, b, c, d)) | InverseClip(ClipShape::Rect(a, b, c, d)) => Some([*a, *b, *c, *d]),
+                    _ => None,
+                });
+                if let (Some((ClipShape::Rect(x1, y1, x2, y2), _)), Some(to)) = (&mut self.clip, to) {
+                    for (v, to) in [x1, y1, x2, y2].into_iter().zip(to) {
+                        *v += (to - *v) * k;
+                    }
+                }
+            },
+            _ => {},
+        }
+    }
+}
+
+/// Transparency `a` with a fade of `fade` on top
+pub fn faded(a: u8, fade: u8) -> u8 {
+    (a as u32 + (255 - a as u32) * fade as u32 / 255) as u8
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn animation() {
+        let style = Style::default();
+        let mut ctx = Ctx { style: &style, styles: &[], t: 500, dur: 1000 };
+        let tokens = backside_parser::parse_overrides(br"\fs10\t(0,1000,\fs30\c&H0000FF&\b1)\fad(100,500)\move(0,0,100,50)").unwrap();
+
+        let mut state = State::new(&style);
+        let mut settings = Settings::new(2, WrapStyle::Smart);
+        for c in &tokens {
+            state.apply(c, &ctx);
+            settings.apply(c, &ctx);
+        }
+        assert_eq!((state.size, state.weight, state.colours[0]), (20.0, 400, Colour(0x008080FF)));
+        assert_eq!((settings.pos, settings.fade), (Some((50.0, 25.0)), 0));
+
+        ctx.t = 750;
+        let mut settings = Settings::new(2, WrapStyle::Smart);
+        for c in &tokens {
+            settings.apply(c, &ctx);
+        }
+        assert_eq!(settings.fade, 128);
+
+        // A tenth of the size per step, animated from the size before
+        let size = |tags: &[u8]| {
+            let mut state = State::new(&style);
+            for c in &backside_parser::parse_overrides(tags).unwrap() {
+                state.apply(c, &ctx);
+            }
+            state.size
+        };
+        assert_eq!((size(br"\fs10\fs+2"), size(br"\fs10\fs+2\t(0,1000,\fs-5)"), size(br"\fs-20")), (12.0, 7.5, 18.0));
+
+        assert_eq!((numpad(1), numpad(6), numpad(11)), (1, 8, 6));
+
+        let mut state = State::new(&style);
+        for c in &backside_parser::parse_overrides(br"\be4000000000\blur1e30").unwrap() {
+            state.apply(c, &ctx);
+        }
+        assert_eq!((state.be, state.blur), (MAX_BE, MAX_BLUR));
+        state.apply(&OverrideCode::Blur(Some(f32::NAN)), &ctx);
+        assert_eq!(state.blur, 0.0);
+        assert_eq!(faded(0x80, 128), 191);
+    }
+}
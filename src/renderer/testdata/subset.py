@@ -0,0 +1,382 @@
+#!/usr/bin/env python3
+"""Make the fonts the renderer's tests draw text with out of DejaVu Sans
+
+    subset.py /usr/share/fonts/truetype/dejavu/DejaVuSans.ttf fixture-sans.ttf
+    subset.py /usr/share/fonts/truetype/dejavu/DejaVuSans.ttf fixture-sans-latin.ttf latin
+
+Fixture Sans keeps printable ASCII, a no-break space, the Hebrew letters and
+a few Arabic ones with their presentation forms, renumbering the glyphs.
+Fixture Sans Latin only keeps the ASCII and the no-break space, for text to
+fall back from. Instead of
+the original GSUB and GPOS, it gets a GSUB of its own with only what the tests
+need: Arabic joining through `init`, `medi` and `fina`, lam-alef through
+`rlig`, and an `fi` ligature through `liga`. Kerning comes from the `kern`
+table, cut down to the glyphs that are kept. Hinting is dropped.
+
+DejaVu Sans is under the Bitstream Vera license, its own changes in the public
+domain. The copyright and license notices are kept in the `name` table, the
+names are changed as the license asks.
+"""
+
+import struct
+import sys
+
+# Arabic letters kept, with how many presentation forms they have: 2 for those
+# that only join the letter before them, 4 for those that join both sides
+ARABIC = {0x627: 2, 0x628: 4, 0x62A: 4, 0x644: 4, 0x645: 4, 0x646: 4, 0x647: 4, 0x648: 2, 0x64A: 4}
+LAM, ALEF = 0x644, 0x627
+# Lam-alef, isolated and final
+LAM_ALEF = (0xFEFB, 0xFEFC)
+FI = 0xFB01
+
+
+def presentation_forms():
+    """First presentation form of each Arabic letter kept, in U+FE80 on"""
+    # How many forms each of U+0621 to U+064A has in Presentation Forms-B
+    counts = [1, 2, 2, 2, 2, 4, 2, 4, 2, 4, 4, 4, 4, 4, 2, 2, 2, 2, 4, 4, 4, 4, 4, 4, 4, 4,
+              0, 0, 0, 0, 0, 0, 4, 4, 4, 4, 4, 4, 4, 2, 2, 4]
+    return {c: 0xFE80 + sum(counts[:c - 0x621]) for c in ARABIC}
+
+
+def tables(data):
+    n = struct.unpack('>H', data[4:6])[0]
+    out = {}
+    for i in range(n):
+        tag, _, off, length = struct.unpack('>4sIII', data[12 + 16 * i:28 + 16 * i])
+        out[tag.decode()] = data[off:off + length]
+    return out
+
+
+def cmap(t):
+    """Character to glyph of the format 4 Unicode BMP subtable"""
+    n = struct.unpack('>H', t[2:4])[0]
+    for i in range(n):
+        pid, eid, off = struct.unpack('>HHI', t[4 + 8 * i:12 + 8 * i])
+        if (pid, eid) == (3, 1) and struct.unpack('>H', t[off:off + 2])[0] == 4:
+            break
+    else:
+        raise SystemExit('no format 4 cmap')
+    sub = t[off:]
+    segs = struct.unpack('>H', sub[6:8])[0] // 2
+    arr = lambda k: struct.unpack('>%dH' % segs, sub[14 + k * 2 * segs + (2 if k else 0):][:2 * segs])
+    ends, starts, deltas, ranges = arr(0), arr(1), arr(2), arr(3)
+    ranges_at = 14 + 2 + 6 * segs
+    out = {}
+    for s in range(segs):
+        for c in range(starts[s], ends[s] + 1):
+            if c == 0xFFFF:
+                continue
+            if ranges[s] == 0:
+                g = (c + deltas[s]) & 0xFFFF
+            else:
+                at = ranges_at + 2 * s + ranges[s] + 2 * (c - starts[s])
+                g = struct.unpack('>H', sub[at:at + 2])[0]
+                if g:
+                    g = (g + deltas[s]) & 0xFFFF
+            if g:
+                out[c] = g
+    return out
+
+
+def glyphs(t, head, maxp):
+    n = struct.unpack('>H', maxp[4:6])[0]
+    long_loca = struct.unpack('>h', head[50:52])[0] == 1
+    loca = t['loca']
+    if long_loca:
+        offs = struct.unpack('>%dI' % (n + 1), loca[:4 * (n + 1)])
+    else:
+        offs = [o * 2 for o in struct.unpack('>%dH' % (n + 1), loca[:2 * (n + 1)])]
+    return [t['glyf'][offs[i]:offs[i + 1]] for i in range(n)]
+
+
+def components(g):
+    """Glyphs a composite glyph is made of, with where their numbers are"""
+    out = []
+    if len(g) < 10 or struct.unpack('>h', g[:2])[0] >= 0:
+        return out
+    at = 10
+    while True:
+        flags, gid = struct.unpack('>HH', g[at:at + 4])
+        out.append((at + 2, gid))
+        at += 4 + (4 if flags & 1 else 2)
+        at += 2 if flags & 8 else 4 if flags & 0x40 else 8 if flags & 0x80 else 0
+        if not flags & 0x20:
+            return out, at, flags
+
+
+def strip(g, remap):
+    """`g` without its instructions, components renumbered"""
+    if not g:
+        return g
+    contours = struct.unpack('>h', g[:2])[0]
+    if contours >= 0:
+        at = 10 + 2 * contours
+        n = struct.unpack('>H', g[at:at + 2])[0]
+        g = g[:at] + b'\0\0' + g[at + 2 + n:]
+    else:
+        comps, end, _ = components(g)
+        g = bytearray(g[:end])
+        for at, gid in comps:
+            struct.pack_into('>H', g, at, remap[gid])
+        # No more instructions after the last one
+        at = 10
+        while True:
+            flags = struct.unpack('>H', g[at:at + 2])[0]
+            if not flags & 0x20:
+                struct.pack_into('>H', g, at, flags & ~0x100)
+                break
+            at += 4 + (4 if flags & 1 else 2)
+            at += 2 if flags & 8 else 4 if flags & 0x40 else 8 if flags & 0x80 else 0
+        g = bytes(g)
+    # Glyphs start on 4 bytes
+    return g + b'\0' * (-len(g) % 4)
+
+
+def coverage(gids):
+    return struct.pack('>HH', 1, len(gids)) + b''.join(struct.pack('>H', g) for g in gids)
+
+
+def single(mapping):
+    """Single substitution, format 2"""
+    src = sorted(mapping)
+    cov = coverage(src)
+    head = struct.pack('>HHH', 2, 6 + 2 * len(src), len(src)) + b''.join(struct.pack('>H', mapping[g]) for g in src)
+    return head + cov
+
+
+def ligatures(ligs):
+    """Ligature substitution, format 1, out of `{first: [(rest, ligature)]}`"""
+    firsts = sorted(ligs)
+    sets = []
+    for f in firsts:
+        entries = [struct.pack('>HH', lig, len(rest) + 1) + b''.join(struct.pack('>H', r) for r in rest) for rest, lig in ligs[f]]
+        off = 2 + 2 * len(entries)
+        table = struct.pack('>H', len(entries))
+        for e in entries:
+            table += struct.pack('>H', off)
+            off += len(e)
+        sets.append(table + b''.join(entries))
+    off = 6 + 2 * len(firsts)
+    head = struct.pack('>HHH', 1, 0, len(firsts))
+    body = b''
+    for s in sets:
+        head += struct.pack('>H', off + len(body))
+        body += s
+    cov_at = off + len(body)
+    head = head[:2] + struct.pack('>H', cov_at) + head[4:]
+    return head + body + coverage(firsts)
+
+
+def gsub(lookups, features, scripts):
+    """`lookups` as (type, subtable), `features` as (tag, lookup indices),
+    `scripts` as (tag, feature indices)"""
+    def lookup(kind, sub):
+        return struct.pack('>HHHH', kind, 0, 1, 8) + sub
+
+    def offsets(items, start):
+        out, at = b'', start
+        for i in items:
+            out += struct.pack('>H', at)
+            at += len(i)
+        return out
+
+    ls = [lookup(k, s) for k, s in lookups]
+    lookup_list = struct.pack('>H', len(ls)) + offsets(ls, 2 + 2 * len(ls)) + b''.join(ls)
+
+    fs = [struct.pack('>HH', 0, len(idx)) + b''.join(struct.pack('>H', i) for i in idx) for _, idx in features]
+    at = 2 + 6 * len(fs)
+    recs = b''
+    for (tag, _), f in zip(features, fs):
+        recs += tag.encode() + struct.pack('>H', at)
+        at += len(f)
+    feature_list = struct.pack('>H', len(fs)) + recs + b''.join(fs)
+
+    ss = []
+    for _, idx in scripts:
+        langsys = struct.pack('>HHH', 0, 0xFFFF, len(idx)) + b''.join(struct.pack('>H', i) for i in idx)
+        ss.append(struct.pack('>HH', 4, 0) + langsys)
+    at = 2 + 6 * len(ss)
+    recs = b''
+    for (tag, _), s in zip(scripts, ss):
+        recs += tag.encode() + struct.pack('>H', at)
+        at += len(s)
+    script_list = struct.pack('>H', len(ss)) + recs + b''.join(ss)
+
+    at = 10
+    return (struct.pack('>HHHHH', 1, 0, at, at + len(script_list), at + len(script_list) + len(feature_list))
+            + script_list + feature_list + lookup_list)
+
+
+def kern(t, remap):
+    """Pairs of the format 0 subtables of `t` between glyphs that are kept"""
+    pairs = {}
+    n = struct.unpack('>H', t[2:4])[0]
+    at = 4
+    for _ in range(n):
+        _, length, coverage_ = struct.unpack('>HHH', t[at:at + 6])
+        if coverage_ >> 8 == 0 and coverage_ & 1:
+            count = struct.unpack('>H', t[at + 6:at + 8])[0]
+            for i in range(count):
+                l, r, v = struct.unpack('>HHh', t[at + 14 + 6 * i:at + 20 + 6 * i])
+                if l in remap and r in remap:
+                    pairs[(remap[l], remap[r])] = v
+        at += length
+    pairs = sorted(pairs.items())
+    n = len(pairs)
+    power = 1
+    while power * 2 <= n:
+        power *= 2
+    body = struct.pack('>HHHH', n, power * 6, power.bit_length() - 1, (n - power) * 6)
+    body += b''.join(struct.pack('>HHh', l, r, v) for (l, r), v in pairs)
+    sub = struct.pack('>HHH', 0, 6 + len(body), 1) + body
+    return struct.pack('>HH', 0, 1) + sub
+
+
+def name(orig, family):
+    """Names of the fixture, with the copyright and license of the original"""
+    keep = {}
+    n, strings = struct.unpack('>HH', orig[2:6])
+    for i in range(n):
+        pid, eid, lang, nid, length, off = struct.unpack('>6H', orig[6 + 12 * i:18 + 12 * i])
+        if (pid, eid, lang) == (3, 1, 0x409) and nid in (0, 13, 14):
+            keep[nid] = orig[strings + off:strings + off + length]
+    for nid, s in [(1, family), (2, 'Book'), (4, family), (6, family.replace(' ', ''))]:
+        keep[nid] = s.encode('utf-16-be')
+    recs, data = b'', b''
+    for nid in sorted(keep):
+        recs += struct.pack('>6H', 3, 1, 0x409, nid, len(keep[nid]), len(data))
+        data += keep[nid]
+    return struct.pack('>HHH', 0, len(keep), 6 + len(recs)) + recs + data
+
+
+def checksum(b):
+    b = b + b'\0' * (-len(b) % 4)
+    return sum(struct.unpack('>%dI' % (len(b) // 4), b)) & 0xFFFFFFFF
+
+
+def font(tabs):
+    tags = sorted(tabs)
+    n = len(tags)
+    power = 1
+    while power * 2 <= n:
+        power *= 2
+    out = struct.pack('>IHHHH', 0x00010000, n, power * 16, power.bit_length() - 1, (n - power) * 16)
+    at = 12 + 16 * n
+    dirs, body = b'', b''
+    for tag in tags:
+        t = tabs[tag]
+        dirs += tag.encode() + struct.pack('>III', checksum(t), at + len(body), len(t))
+        body += t + b'\0' * (-len(t) % 4)
+    data = bytearray(out + dirs + body)
+    head_at = 12 + 16 * n + sum(len(tabs[t]) + (-len(tabs[t]) % 4) for t in tags[:tags.index('head')])
+    struct.pack_into('>I', data, head_at + 8, (0xB1B0AFBA - checksum(bytes(data))) & 0xFFFFFFFF)
+    return bytes(data)
+
+
+def main(src, dst, latin=False):
+    t = tables(open(src, 'rb').read())
+    chars = cmap(t['cmap'])
+    all_glyphs = glyphs(t, t['head'], t['maxp'])
+
+    forms = presentation_forms()
+    wanted = list(range(0x20, 0x7F)) + [0xA0] + [FI]
+    if not latin:
+        wanted += list(range(0x5D0, 0x5EB)) + sorted(ARABIC)
+        wanted += [forms[c] + k for c in sorted(ARABIC) for k in range(ARABIC[c])]
+        wanted += list(LAM_ALEF)
+    wanted = [c for c in wanted if c in chars]
+
+    # Glyphs kept, with the components of the composite ones
+    keep = {0}
+    todo = [chars[c] for c in wanted]
+    while todo:
+        g = todo.pop()
+        if g in keep:
+            continue
+        keep.add(g)
+        comps = components(all_glyphs[g])
+        todo += [gid for _, gid in (comps[0] if comps else [])]
+    order = sorted(keep)
+    remap = {g: i for i, g in enumerate(order)}
+    glyph = lambda c: remap[chars[c]]
+
+    # Glyph outlines and metrics
+    glyf, loca = b'', []
+    for g in order:
+        loca.append(len(glyf))
+        glyf += strip(all_glyphs[g], remap)
+    loca.append(len(glyf))
+    metrics_n = struct.unpack('>H', t['hhea'][34:36])[0]
+    def metric(g):
+        adv = struct.unpack('>H', t['hmtx'][4 * min(g, metrics_n - 1):][:2])[0]
+        lsb = struct.unpack('>h', (t['hmtx'][4 * g + 2:4 * g + 4] if g < metrics_n else t['hmtx'][4 * metrics_n + 2 * (g - metrics_n):][:2]))[0]
+        return struct.pack('>Hh', adv, lsb)
+
+    # Character map, a segment per character
+    codes = sorted(wanted)
+    segs = len(codes) + 1
+    power = 1
+    while power * 2 <= segs:
+        power *= 2
+    ends = codes + [0xFFFF]
+    deltas = [(glyph(c) - c) & 0xFFFF for c in codes] + [1]
+    sub = struct.pack('>HHHH', segs * 2, power * 2, power.bit_length() - 1, segs * 2 - power * 2)
+    sub += b''.join(struct.pack('>H', e) for e in ends) + b'\0\0'
+    sub += b''.join(struct.pack('>H', s) for s in ends)
+    sub += b''.join(struct.pack('>H', d) for d in deltas)
+    sub += b'\0\0' * segs
+    sub = struct.pack('>HHH', 4, 6 + len(sub), 0) + sub
+    cmap_table = struct.pack('>HHHHI', 0, 1, 3, 1, 12) + sub
+
+    # Arabic joining, lam-alef and `fi`
+    liga = {glyph(ord('f')): [([glyph(ord('i'))], glyph(FI))]}
+    if latin:
+        gsub_table = gsub([(4, ligatures(liga))], [('liga', [0])], [('DFLT', [0]), ('latn', [0])])
+    else:
+        fina, init, medi = {}, {}, {}
+        for c in ARABIC:
+            fina[glyph(c)] = glyph(forms[c] + 1)
+            if ARABIC[c] == 4:
+                init[glyph(c)] = glyph(forms[c] + 2)
+                medi[glyph(c)] = glyph(forms[c] + 3)
+        alef_fina = glyph(forms[ALEF] + 1)
+        rlig = {
+            glyph(forms[LAM] + 2): [([alef_fina], glyph(LAM_ALEF[0]))],
+            glyph(forms[LAM] + 3): [([alef_fina], glyph(LAM_ALEF[1]))],
+        }
+        gsub_table = gsub(
+            [(1, single(fina)), (1, single(init)), (1, single(medi)), (4, ligatures(rlig)), (4, ligatures(liga))],
+            [('fina', [0]), ('init', [1]), ('liga', [4]), ('medi', [2]), ('rlig', [3])],
+            [('DFLT', [2]), ('arab', [0, 1, 3, 4]), ('latn', [2])],
+        )
+
+    n = len(order)
+    head = bytearray(t['head'])
+    struct.pack_into('>I', head, 8, 0)
+    struct.pack_into('>h', head, 50, 1)
+    hhea = bytearray(t['hhea'])
+    struct.pack_into('>H', hhea, 34, n)
+    maxp = bytearray(t['maxp'])
+    struct.pack_into('>H', maxp, 4, n)
+    post = bytearray(t['post'][:32])
+    struct.pack_into('>I', post, 0, 0x00030000)
+
+    out = font({
+        'OS/2': t['OS/2'],
+        'GSUB': gsub_table,
+        'cmap': cmap_table,
+        'glyf': glyf,
+        'head': bytes(head),
+        'hhea': bytes(hhea),
+        'hmtx': b''.join(metric(g) for g in order),
+        'kern': kern(t['kern'], remap),
+        'loca': b''.join(struct.pack('>I', o) for o in loca),
+        'maxp': bytes(maxp),
+        'name': name(t['name'], 'Fixture Sans Latin' if latin else 'Fixture Sans'),
+        'post': bytes(post),
+    })
+    open(dst, 'wb').write(out)
+
+
+if __name__ == '__main__':
+    main(sys.argv[1], sys.argv[2], sys.argv[3:4] == ['latin'])
@@ -0,0 +1,228 @@
+use core::fmt::{self, Display};
+use core::str::FromStr;
+
+use crate::{Error, ErrorKind, YCbCrMatrix};
+
+/// A colour, packed the way ASS writes it: `0xAABBGGRR`
+///
+/// The alpha byte is a transparency, `0x00` being opaque and `0xFF`
+/// invisible. Formats as `&HAABBGGRR`, the form used in `Style:` lines.
+#[derive(Default, Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub struct Colour(pub u32);
+
+/// Parse the digits at the start of `s`, ignoring whatever comes after
+///
+/// Overflowing values keep their low 32 bits, like libass.
+fn digits(s: &str, radix: u32) -> Option<u32> {
+    let mut n: u32 = 0;
+    let mut any = false;
+    for d in s.chars().map_while(|c| c.to_digit(radix)) {
+        n = n.wrapping_mul(radix).wrapping_add(d);
+        any = true;
+    }
+    any.then_some(n)
+}
+
+impl Colour {
+    pub const fn rgb(r: u8, g: u8, b: u8) -> Self {
+        Self::rgba(r, g, b, 0)
+    }
+
+    /// Colour with an ASS alpha, ie. transparency
+    pub const fn rgba(r: u8, g: u8, b: u8, a: u8) -> Self {
+        Self(u32::from_le_bytes([r, g, b, a]))
+    }
+
+    pub const fn r(self) -> u8 {
+        self.0.to_le_bytes()[0]
+    }
+
+    pub const fn g(self) -> u8 {
+        self.0.to_le_bytes()[1]
+    }
+
+    pub const fn b(self) -> u8 {
+        self.0.to_le_bytes()[2]
+    }
+
+    /// Transparency, `0` being opaque
+    pub const fn alpha(self) -> u8 {
+        self.0.to_le_bytes()[3]
+    }
+
+    /// Red, green, blue and opacity, as most graphics libraries want them
+    pub const fn to_rgba(self) -> [u8; 4] {
+        [self.r(), self.g(), self.b(), 255 - self.alpha()]
+    }
+
+    pub const fn with_alpha(self, a: u8) -> Self {
+        Self::rgba(self.r(), self.g(), self.b(), a)
+    }
+
+    /// Parse a colour field of a `Style:` line
+    ///
+    /// `&H`, `H` or `0x` (in any case, with any number of `&`) start
+    /// hexadecimal `AABBGGRR`, anything else is the decimal long integer SSA
+    /// uses, negative ones included. Trailing garbage such as the closing `&`
+    /// is ignored.
+    pub fn parse(s: &str) -> Option<Self> {
+        let s = s.trim();
+        let h = s.trim_start_matches('&');
+        if let Some(h) = h.strip_prefix(['H', 'h']).or_else(|| h.strip_prefix("0x")) {
+            return Self::parse_hex(h);
+        }
+        match s.strip_prefix('-') {
+            Some(d) => digits(d, 10).map(|n| Self(n.wrapping_neg())),
+            None    => digits(s.strip_prefix('+').unwrap_or(s), 10).map(Self),
+        }
+    }
+
+    /// Parse the hexadecimal value of a `\c` tag, eg. `&HBBGGRR&`
+    ///
+    /// The `&H` and the closing `&` are optional.
+    pub fn parse_hex(s: &str) -> Option<Self> {
+        let s = s.trim().trim_start_matches('&');
+        let s = s.strip_prefix(['H', 'h']).unwrap_or(s);
+        digits(s, 16).map(Self)
+    }
+
+    /// Display as `&HBBGGRR&`, the form used in `\c` tags, dropping the alpha
+    pub fn tag(self) -> impl Display {
+        struct Tag(u32);
+        impl Display for Tag {
+            fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                write!(f, "&H{:06X}&", self.0 & 0xFFFFFF)
+            }
+        }
+        Tag(self.0)
+    }
+
+    /// Y, Cb and Cr of the colour under matrix `m`
+    ///
+    /// [`YCbCrMatrix::None`] gives R, G and B back.
+    pub fn to_ycbcr(self, m: YCbCrMatrix) -> (u8, u8, u8) {
+        let Some((kr, kb, tv)) = coefficients(m) else {
+            return (self.r(), self.g(), self.b());
+        };
+        let [r, g, b] = [self.r(), self.g(), self.b()].map(|c| c as f32 / 255.0);
+
+        let y = kr * r + (1.0 - kr - kb) * g + kb * b;
+        let cb = (b - y) / (2.0 * (1.0 - kb));
+        let cr = (r - y) / (2.0 * (1.0 - kr));
+
+        match tv {
+            true  => (to_u8(16.0 + 219.0 * y), to_u8(128.0 + 224.0 * cb), to_u8(128.0 + 224.0 * cr)),
+            false => (to_u8(255.0 * y), to_u8(128.0 + 255.0 * cb), to_u8(128.0 + 255.0 * cr)),
+        }
+    }
+
+    /// Opaque colour of Y, Cb and Cr under matrix `m`
+    pub fn from_ycbcr(m: YCbCrMatrix, y: u8, cb: u8, cr: u8) -> Self {
+        let Some((kr, kb, tv)) = coefficients(m) else {
+            return Self::rgb(y, cb, cr);
+        };
+        let (y, cb, cr) = match tv {
+            true  => ((y as f32 - 16.0) / 219.0, (cb as f32 - 128.0) / 224.0, (cr as f32 - 128.0) / 224.0),
+            false => (y as f32 / 255.0, (cb as f32 - 128.0) / 255.0, (cr as f32 - 128.0) / 255.0),
+        };
+
+        let r = y + 2.0 * (1.0 - kr) * cr;
+        let b = y + 2.0 * (1.0 - kb) * cb;
+        let g = (y - kr * r - kb * b) / (1.0 - kr - kb);
+
+        Self::rgb(to_u8(255.0 * r), to_u8(255.0 * g), to_u8(255.0 * b))
+    }
+
+    /// Remap a colour picked on a video decoded with matrix `from` so it
+    /// looks the same on one decoded with `to`, keeping the alpha
+    ///
+    /// This is what renderers do with the `YCbCr Matrix` header: the colour
+    /// goes back to the YCbCr value it came from, which then gets decoded the
+    /// other way. Nothing changes if either is [`YCbCrMatrix::None`].
+    pub fn convert(self, from: YCbCrMatrix, to: YCbCrMatrix) -> Self {
+        if from == to || from == YCbCrMatrix::None || to == YCbCrMatrix::None {
+            return self;
+        }
+        let (y, cb, cr) = self.to_ycbcr(from);
+        Self::from_ycbcr(to, y, cb, cr).with_alpha(self.alpha())
+    }
+}
+
+/// Kr, Kb and whether the range is limited ("TV")
+fn coefficients(m: YCbCrMatrix) -> Option<(f32, f32, bool)> {
+    use YCbCrMatrix::*;
+    Some(match m {
+        None        => return Option::None,
+        Bt601Tv     => (0.299, 0.114, true),
+        Bt601Pc     => (0.299, 0.114, false),
+        Bt709Tv     => (0.2126, 0.0722, true),
+        Bt709Pc     => (0.2126, 0.0722, false),
+        Smpte240mTv => (0.212, 0.087, true),
+        Smpte240mPc => (0.212, 0.087, false),
+        FccTv       => (0.30, 0.11, true),
+        FccPc       => (0.30, 0.11, false),
+    })
+}
+
+/// Round and clamp, without `f32::round` in `no_std`
+fn to_u8(v: f32) -> u8 {
+    (v + 0.5).clamp(0.0, 255.0) as u8
+}
+
+impl Display for Colour {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "&H{:08X}", self.0)
+    }
+}
+
+impl FromStr for Colour {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Error> {
+        Self::parse(s).ok_or(ErrorKind::ColourInvalid.into())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::string::ToString;
+
+    use super::*;
+
+    #[test]
+    fn parse() {
+        let c = Colour::rgba(0x11, 0x22, 0x33, 0x80);
+        assert_eq!(Colour::parse("&H80332211"), Some(c));
+        assert_eq!(Colour::parse("&h80332211&"), Some(c));
+        assert_eq!(Colour::parse("H80332211"), Some(c));
+        assert_eq!(Colour::parse("0x80332211"), Some(c));
+        assert_eq!(Colour::parse("&HFF"), Some(Colour::rgb(0xFF, 0, 0)));
+        assert_eq!(Colour::parse("16777215"), Some(Colour(0xFFFFFF)));
+        assert_eq!(Colour::parse("-1"), Some(Colour(0xFFFFFFFF)));
+        assert_eq!(Colour::parse("&H1FFFFFFFF"), Some(Colour(0xFFFFFFFF)));
+        assert_eq!(Colour::parse("&Hgg"), None);
+        assert_eq!(Colour::parse_hex("&H332211&"), Some(Colour::rgb(0x11, 0x22, 0x33)));
+        assert_eq!(Colour::parse_hex("332211"), Some(Colour::rgb(0x11, 0x22, 0x33)));
+
+        assert_eq!(c.to_string(), "&H80332211");
+        assert_eq!(c.tag().to_string(), "&H332211&");
+        assert_eq!(c.to_rgba(), [0x11, 0x22, 0x33, 0x7F]);
+    }
+
+    #[test]
+    fn ycbcr() {
+        use YCbCrMatrix::*;
+        assert_eq!(Colour::rgb(255, 255, 255).to_ycbcr(Bt601Tv), (235, 128, 128));
+        assert_eq!(Colour::rgb(0, 0, 0).to_ycbcr(Bt709Pc), (0, 128, 128));
+        assert_eq!(Colour::rgb(255, 0, 0).to_ycbcr(Bt601Tv), (81, 90, 240));
+
+        let c = Colour::rgba(200, 30, 90, 0x40);
+        for m in [Bt601Tv, Bt601Pc, Bt709Tv, Bt709Pc, Smpte240mTv, FccPc] {
+            let back = c.convert(Bt709Tv, m).convert(m, Bt709Tv);
+            assert!(back.r().abs_diff(c.r()) <= 2 && back.g().abs_diff(c.g()) <= 2 && back.b().abs_diff(c.b()) <= 2);
+            assert_eq!(back.alpha(), 0x40);
+        }
+        assert_ne!(c.convert(Bt601Tv, Bt709Tv), c);
+        assert_eq!(c.convert(None, Bt709Tv), c);
+    }
+}
@@ -0,0 +1,127 @@
+use core::fmt::Display;
+use core::ops::Range;
+
+use alloc::string::{String, ToString};
+
+pub type Result<T> = core::result::Result<T, Error>;
+
+macro_rules! errs {
+    ( $( $ty:ident : $str:literal ),* ) => {
+        /// What went wrong
+        #[derive(Clone, Copy, PartialEq, Eq, Debug)]
+        pub enum ErrorKind {
+            $(
+            #[doc = $str]
+            $ty,
+            )*
+        }
+        impl Display for ErrorKind {
+            fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+                use ErrorKind::*;
+                match self {
+                    $( $ty => f.write_str($str), )*
+                }
+            }
+        }
+    };
+}
+
+errs! {
+    StructureInvalid: "basic file structure invalid",
+    HeaderUnclosed: "section header missing its `]`",
+    StrayBom: "byte order mark after the start of the script",
+    StyleUndefined: "style does not exist or was not defined",
+    StyleInvalid: "invalid style line",
+    EventInvalid: "invalid event line",
+    OCInvalid: "invalid override code",
+    OCInvalidParams: "invalid override code parameters",
+    OCMissingParams: "missing override code parameters",
+    UuInvalid: "invalid uuencoded data",
+    ColourInvalid: "invalid colour",
+    TimestampInvalid: "invalid timestamp",
+    KeyframesInvalid: "invalid keyframes file",
+    TimecodesInvalid: "invalid timecodes file"
+}
+
+/// An error, and where in the script it happened
+///
+/// Functions working on a single line or tag only fill in what they know
+/// (`span` and `snippet`), the location in the script gets added by whatever
+/// parses the whole script.
+#[derive(Clone, PartialEq, Debug)]
+pub struct Error {
+    pub kind: ErrorKind,
+
+    /// Line number, starting at 1
+    pub line: Option<usize>,
+
+    /// Byte range of the offending text, within the line
+    pub span: Option<Range<usize>>,
+
+    /// Name of the section the line is in, as written between the brackets
+    pub section: Option<String>,
+
+    /// The offending text
+    pub snippet: Option<String>,
+}
+
+impl Error {
+    pub fn new(kind: ErrorKind) -> Self {
+        Self {
+            kind,
+            line: None,
+            span: None,
+            section: None,
+            snippet: None,
+        }
+    }
+
+    /// Error about `snippet`, found at `span`
+    pub fn spanned(kind: ErrorKind, span: Range<usize>, snippet: &str) -> Self {
+        Self {
+            span: Some(span),
+            snippet: Some(snippet.to_string()),
+            ..Self::new(kind)
+        }
+    }
+
+    /// Set where in the script the error happened
+    ///
+    /// Without a span already, the whole `text` of the line is blamed.
+    pub fn locate(mut self, line: usize, section: Option<&str>, text: &str) -> Self {
+        self.line = Some(line);
+        self.section = section.map(String::from);
+        if self.span.is_none() {
+            self.span = Some(0..text.len());
+            self.snippet = Some(text.to_string());
+        }
+        self
+    }
+}
+
+impl From<ErrorKind> for Error {
+    fn from(kind: ErrorKind) -> Self {
+        Self::new(kind)
+    }
+}
+
+impl Display for Error {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        write!(f, "{}", self.kind)?;
+        if let Some(line) = self.line {
+            write!(f, " at line {}", line)?;
+            if let Some(span) = &self.span {
+                write!(f, ", column {}", span.start + 1)?;
+            }
+        }
+        if let Some(section) = &self.section {
+            write!(f, " in [{}]", section)?;
+        }
+        if let Some(snippet) = &self.snippet {
+            write!(f, ": `{}`", snippet)?;
+        }
+        Ok(())
+    }
+}
+
+impl core::error::Error for Error {}
@@ -0,0 +1,165 @@
+use alloc::string::String;
+
+use crate::Timestamp;
+use crate::resample::Resampler;
+
+/// Kind of an `[Events]` line
+#[derive(Default, Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub enum EventKind {
+    /// `Dialogue:`
+    ///
+    /// Text to be displayed.
+    #[default]
+    Dialogue,
+    /// `Comment:`
+    ///
+    /// Same as Dialogue, but isn't displayed.
+    Comment,
+    /// `Picture:` (SSA)
+    ///
+    /// `Text` is the path of a picture to display instead of subtitle text.
+    Picture,
+    /// `Sound:` (SSA)
+    ///
+    /// `Text` is the path of a sound to play.
+    Sound,
+    /// `Movie:` (SSA)
+    ///
+    /// `Text` is the path of an AVI file to play.
+    Movie,
+    /// `Command:` (SSA)
+    ///
+    /// `Text` is a program to run.
+    Command,
+}
+
+impl EventKind {
+    /// The line prefix, without the colon
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            EventKind::Dialogue => "Dialogue",
+            EventKind::Comment  => "Comment",
+            EventKind::Picture  => "Picture",
+            EventKind::Sound    => "Sound",
+            EventKind::Movie    => "Movie",
+            EventKind::Command  => "Command",
+        }
+    }
+}
+
+/// A single line of `[Events]`
+#[derive(Default, Clone, PartialEq, Hash, Debug)]
+pub struct Event {
+    /// `Dialogue` / `Comment` / ...
+    pub kind: EventKind,
+
+    /// #1:
+    ///  `Layer` (ASS)
+    ///
+    /// Subtitles having different layer number will be ignored during the collision detection.
+    /// Higher numbered layers will be drawn over the lower numbered.
+    pub layer: i32,
+
+    /// #1:
+    ///  `Marked` (SSA)
+    ///
+    /// `Marked=1` means the line is shown as "marked" in SSA.
+    pub marked: bool,
+
+    /// #2:
+    ///  `Start`
+    ///
+    /// Start Time of the Event, in 0:00:00:00 format ie. Hrs:Mins:Secs:hundredths.
+    pub start: Timestamp,
+
+    /// #3:
+    ///  `End`
+    ///
+    /// End Time of the Event, in 0:00:00:00 format ie. Hrs:Mins:Secs:hundredths.
+    pub end: Timestamp,
+
+    /// #4:
+    ///  `Style`
+    ///
+    /// Style name. If it is `Default`, then your own *Default style will be substituted.
+    pub style: String,
+
+    /// #5:
+    ///  `Name` / `Actor`
+    ///
+    /// Character name. This is the name of the character who speaks the dialogue.
+    /// It is for information only, to make the script easier to follow when editing/timing.
+    pub name: String,
+
+    /// #6:
+    ///  `MarginL`
+    ///
+    /// 4-figure Left Margin override. The values are in pixels. All zeroes means the default margins defined by the style are used.
+    pub margin_l: i32,
+
+    /// #7:
+    ///  `MarginR`
+    ///
+    /// 4-figure Right Margin override. The values are in pixels. All zeroes means the default margins defined by the style are used.
+    pub margin_r: i32,
+
+    /// #8:
+    ///  `MarginV`
+    ///
+    /// 4-figure Bottom Margin override. The values are in pixels. All zeroes means the default margins defined by the style are used.
+    pub margin_v: i32,
+
+    /// #9:
+    ///  `Effect`
+    ///
+    /// Transition Effect. This is either empty, or contains information for one of the three transition effects implemented in SSA v4.x
+    ///
+    /// - `Karaoke` (obsolete, use `\k` instead)
+    /// - `Scroll up;y1;y2;delay[;fadeawayheight]`
+    /// - `Scroll down;y1;y2;delay[;fadeawayheight]`
+    /// - `Banner;delay[;lefttoright;fadeawaywidth]`
+    pub effect: String,
+
+    /// #10:
+    ///  `Text`
+    ///
+    /// Subtitle Text. This is the actual text which will be displayed as a subtitle onscreen.
+    /// Kept verbatim, commas, override blocks and all.
+    pub text: String,
+}
+
+impl Event {
+    /// Move by `by`, clamping times that would end up negative to zero
+    pub fn shift(&mut self, by: Timestamp) {
+        self.start = (self.start + by).max(Timestamp::ZERO);
+        self.end = (self.end + by).max(Timestamp::ZERO);
+    }
+
+    /// Multiply the start and end times by `num / den`
+    ///
+    /// Only the times get scaled, not the tags in [`text`](Self::text). They
+    /// are left alone if `den` is zero.
+    pub fn scale(&mut self, num: i64, den: i64) {
+        if let (Some(start), Some(end)) = (self.start.scale(num, den), self.end.scale(num, den)) {
+            self.start = start.max(Timestamp::ZERO);
+            self.end = end.max(Timestamp::ZERO);
+        }
+    }
+
+    /// Scale the margins to another resolution, `align` being the numpad
+    /// alignment of the line
+    ///
+    /// Zero margins stand for those of the style and are kept. The tags in
+    /// [`text`](Self::text) are left alone.
+    pub fn resample(&mut self, r: &Resampler, align: u8) {
+        if self.margin_l != 0 {
+            self.margin_l = r.margin_l(self.margin_l);
+        }
+        if self.margin_r != 0 {
+            self.margin_r = r.margin_r(self.margin_r);
+        }
+        if self.margin_v != 0 {
+            self.margin_v = r.margin_v(self.margin_v, align);
+        }
+    }
+}
@@ -0,0 +1,32 @@
+//! Default `Format:` lines
+
+/// `Format:` of `[V4+ Styles]`, used when the section doesn't have one
+pub const ASS_STYLE_FORMAT: &[&[u8]] = &[
+    b"Name", b"Fontname", b"Fontsize",
+    b"PrimaryColour", b"SecondaryColour", b"OutlineColour", b"BackColour",
+    b"Bold", b"Italic", b"Underline", b"StrikeOut",
+    b"ScaleX", b"ScaleY", b"Spacing", b"Angle",
+    b"BorderStyle", b"Outline", b"Shadow", b"Alignment",
+    b"MarginL", b"MarginR", b"MarginV", b"Encoding",
+];
+
+/// `Format:` of `[V4 Styles]`, used when the section doesn't have one
+pub const SSA_STYLE_FORMAT: &[&[u8]] = &[
+    b"Name", b"Fontname", b"Fontsize",
+    b"PrimaryColour", b"SecondaryColour", b"TertiaryColour", b"BackColour",
+    b"Bold", b"Italic",
+    b"BorderStyle", b"Outline", b"Shadow", b"Alignment",
+    b"MarginL", b"MarginR", b"MarginV", b"AlphaLevel", b"Encoding",
+];
+
+/// `Format:` of `[Events]` in ASS scripts, used when the section doesn't have one
+pub const ASS_EVENT_FORMAT: &[&[u8]] = &[
+    b"Layer", b"Start", b"End", b"Style", b"Name",
+    b"MarginL", b"MarginR", b"MarginV", b"Effect", b"Text",
+];
+
+/// `Format:` of `[Events]` in SSA scripts, used when the section doesn't have one
+pub const SSA_EVENT_FORMAT: &[&[u8]] = &[
+    b"Marked", b"Start", b"End", b"Style", b"Name",
+    b"MarginL", b"MarginR", b"MarginV", b"Effect", b"Text",
+];
@@ -0,0 +1,367 @@
+use core::fmt::{self, Display};
+
+use alloc::{string::{String, ToString}, vec::Vec};
+
+/// `[Script Info]`
+///
+/// Keeps every `Key: value` pair and `;` comment in file order, unknown keys
+/// included, and reads and writes the ones renderers care about through typed
+/// accessors. Keys are matched case-insensitively, like libass does.
+#[derive(Default, Clone, PartialEq, Debug)]
+pub struct ScriptInfo {
+    /// Fields and comments in the order they appear in the file
+    pub entries: Vec<InfoEntry>,
+}
+
+/// A line of `[Script Info]`
+#[derive(Clone, PartialEq, Eq, Hash, Debug)]
+pub enum InfoEntry {
+    /// `Key: value`
+    Field(String, String),
+    /// `;` line, what comes after the `;`
+    Comment(String),
+}
+
+/// `ScriptType:` value
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum ScriptType {
+    /// `v4.00`, SSA
+    V4,
+    /// `v4.00+`, ASS
+    V4Plus,
+    /// `v4.00++`, ASS with `LayoutResX/Y` and per-side margins
+    V4PlusPlus,
+}
+
+/// `WrapStyle:` value, also set per line by `\q`
+#[derive(Default, Clone, Copy, PartialEq, Eq, Debug)]
+pub enum WrapStyle {
+    /// 0, lines are broken evenly, the top one being the longest
+    #[default]
+    Smart,
+    /// 1, only the end of line gets wrapped
+    EndOfLine,
+    /// 2, no wrapping, only `\N` breaks lines
+    None,
+    /// 3, like [`Smart`](Self::Smart) but the bottom line is the longest
+    SmartLower,
+}
+
+/// `YCbCr Matrix:` value, the colour matrix the script was made for
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum YCbCrMatrix {
+    /// `None`, colours are RGB and never get converted
+    None,
+    Bt601Tv,
+    Bt601Pc,
+    Bt709Tv,
+    Bt709Pc,
+    Smpte240mTv,
+    Smpte240mPc,
+    FccTv,
+    FccPc,
+}
+
+/// `Collisions:` value
+#[derive(Default, Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Collisions {
+    /// Lines that would overlap get moved up (or down, at the top)
+    #[default]
+    Normal,
+    /// Lines get moved the other way
+    Reverse,
+}
+
+macro_rules! values {
+    ( $ty:ident { $( $v:ident = $s:literal ),* $(,)? } ) => {
+        impl $ty {
+            /// Value as written in `[Script Info]`
+            pub fn as_str(&self) -> &'static str {
+                match self {
+                    $( $ty::$v => $s, )*
+                }
+            }
+
+            /// Parse a value as written in `[Script Info]`
+            pub fn parse(s: &str) -> Option<Self> {
+                $(
+                if s.trim().eq_ignore_ascii_case($s) {
+                    return Some($ty::$v);
+                }
+                )*
+                None
+            }
+        }
+        impl Display for $ty {
+            fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                f.write_str(self.as_str())
+            }
+        }
+    };
+}
+
+values!(ScriptType {
+    V4 = "v4.00",
+    V4Plus = "v4.00+",
+    V4PlusPlus = "v4.00++",
+});
+values!(WrapStyle {
+    Smart = "0",
+    EndOfLine = "1",
+    None = "2",
+    SmartLower = "3",
+});
+values!(YCbCrMatrix {
+    None = "None",
+    Bt601Tv = "TV.601",
+    Bt601Pc = "PC.601",
+    Bt709Tv = "TV.709",
+    Bt709Pc = "PC.709",
+    Smpte240mTv = "TV.240M",
+    Smpte240mPc = "PC.240M",
+    FccTv = "TV.FCC",
+    FccPc = "PC.FCC",
+});
+values!(Collisions {
+    Normal = "Normal",
+    Reverse = "Reverse",
+});
+
+/// `yes` / `no` or a number, like libass
+fn parse_bool(s: &str) -> bool {
+    let s = s.trim();
+    s.eq_ignore_ascii_case("yes") || s.parse::<i32>().is_ok_and(|v| v != 0)
+}
+
+impl ScriptInfo {
+    /// `Key: value` pairs, without the comments
+    pub fn fields(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.entries.iter().filter_map(|e| match e {
+            InfoEntry::Field(k, v) => Some((k.as_str(), v.as_str())),
+            InfoEntry::Comment(_)  => None,
+        })
+    }
+
+    /// Value of `key`
+    pub fn get(&self, key: &str) -> Option<&str> {
+        self.fields().find(|(k, _)| k.eq_ignore_ascii_case(key)).map(|(_, v)| v)
+    }
+
+    /// Set `key`, in place if it's already there, at the end otherwise
+    pub fn set(&mut self, key: &str, value: impl Display) {
+        let value = value.to_string();
+        let field = self.entries.iter_mut().find_map(|e| match e {
+            InfoEntry::Field(k, v) if k.eq_ignore_ascii_case(key) => Some(v),
+            _ => None,
+        });
+        match field {
+            Some(v) => *v = value,
+            None    => self.entries.push(InfoEntry::Field(String::from(key), value)),
+        }
+    }
+
+    /// Remove `key`
+    pub fn remove(&mut self, key: &str) {
+        self.entries.retain(|e| !matches!(e, InfoEntry::Field(k, _) if k.eq_ignore_ascii_case(key)));
+    }
+
+    /// Add a `;` comment at the end
+    pub fn add_comment(&mut self, text: &str) {
+        self.entries.push(InfoEntry::Comment(String::from(text)));
+    }
+
+    fn num<T: core::str::FromStr>(&self, key: &str) -> Option<T> {
+        self.get(key)?.trim().parse().ok()
+    }
+
+    pub fn title(&self) -> Option<&str> {
+        self.get("Title")
+    }
+
+    pub fn set_title(&mut self, title: &str) {
+        self.set("Title", title);
+    }
+
+    pub fn original_script(&self) -> Option<&str> {
+        self.get("Original Script")
+    }
+
+    pub fn set_original_script(&mut self, author: &str) {
+        self.set("Original Script", author);
+    }
+
+    pub fn script_type(&self) -> Option<ScriptType> {
+        ScriptType::parse(self.get("ScriptType")?)
+    }
+
+    pub fn set_script_type(&mut self, t: ScriptType) {
+        self.set("ScriptType", t);
+    }
+
+    /// `PlayResX`, as written
+    pub fn play_res_x(&self) -> Option<i32> {
+        self.num("PlayResX")
+    }
+
+    /// `PlayResY`, as written
+    pub fn play_res_y(&self) -> Option<i32> {
+        self.num("PlayResY")
+    }
+
+    /// Size of the coordinate space of the script
+    ///
+    /// Missing values are made up the way libass does: 384×288 without
+    /// either, otherwise the other one is worked out for a 4:3 frame, with
+    /// the special case of 1280×1024.
+    pub fn play_res(&self) -> (i32, i32) {
+        let valid = |v: Option<i32>| v.filter(|&v| v > 0);
+        match (valid(self.play_res_x()), valid(self.play_res_y())) {
+            (Some(x), Some(y)) => (x, y),
+            (None, None)       => (384, 288),
+            (None, Some(1024)) => (1280, 1024),
+            (None, Some(y))    => (y * 4 / 3, y),
+            (Some(1280), None) => (1280, 1024),
+            (Some(x), None)    => (x, x * 3 / 4),
+        }
+    }
+
+    pub fn set_play_res(&mut self, x: i32, y: i32) {
+        self.set("PlayResX", x);
+        self.set("PlayResY", y);
+    }
+
+    /// `LayoutResX` and `LayoutResY`, the size of the video the script was
+    /// laid out on, if both are there
+    pub fn layout_res(&self) -> Option<(i32, i32)> {
+        Some((self.num("LayoutResX")?, self.num("LayoutResY")?))
+    }
+
+    pub fn set_layout_res(&mut self, x: i32, y: i32) {
+        self.set("LayoutResX", x);
+        self.set("LayoutResY", y);
+    }
+
+    pub fn wrap_style(&self) -> WrapStyle {
+        self.get("WrapStyle").and_then(WrapStyle::parse).unwrap_or_default()
+    }
+
+    pub fn set_wrap_style(&mut self, w: WrapStyle) {
+        self.set("WrapStyle", w);
+    }
+
+    /// Whether borders and shadows scale with the video rather than being in
+    /// video pixels, `false` when missing like VSFilter
+    pub fn scaled_border_and_shadow(&self) -> bool {
+        self.get("ScaledBorderAndShadow").is_some_and(parse_bool)
+    }
+
+    pub fn set_scaled_border_and_shadow(&mut self, v: bool) {
+        self.set("ScaledBorderAndShadow", if v { "yes" } else { "no" });
+    }
+
+    /// `None` when missing or unknown
+    pub fn ycbcr_matrix(&self) -> Option<YCbCrMatrix> {
+        YCbCrMatrix::parse(self.get("YCbCr Matrix")?)
+    }
+
+    pub fn set_ycbcr_matrix(&mut self, m: YCbCrMatrix) {
+        self.set("YCbCr Matrix", m);
+    }
+
+    pub fn kerning(&self) -> bool {
+        self.get("Kerning").is_some_and(parse_bool)
+    }
+
+    pub fn set_kerning(&mut self, v: bool) {
+        self.set("Kerning", if v { "yes" } else { "no" });
+    }
+
+    /// Speed of the script in percent, 100 when missing
+    pub fn timer(&self) -> f32 {
+        self.num("Timer").filter(|&t: &f32| t > 0.0).unwrap_or(100.0)
+    }
+
+    /// Written with four decimals, like Aegisub
+    pub fn set_timer(&mut self, percent: f32) {
+        self.set("Timer", format_args!("{:.4}", percent));
+    }
+
+    pub fn collisions(&self) -> Collisions {
+        self.get("Collisions").and_then(Collisions::parse).unwrap_or_default()
+    }
+
+    pub fn set_collisions(&mut self, c: Collisions) {
+        self.set("Collisions", c);
+    }
+
+    pub fn play_depth(&self) -> Option<i32> {
+        self.num("PlayDepth")
+    }
+
+    pub fn set_play_depth(&mut self, depth: i32) {
+        self.set("PlayDepth", depth);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn info(entries: &[(&str, &str)]) -> ScriptInfo {
+        ScriptInfo {
+            entries: entries.iter().map(|&(k, v)| InfoEntry::Field(k.to_string(), v.to_string())).collect(),
+        }
+    }
+
+    #[test]
+    fn play_res() {
+        assert_eq!(info(&[]).play_res(), (384, 288));
+        assert_eq!(info(&[("PlayResY", "1024")]).play_res(), (1280, 1024));
+        assert_eq!(info(&[("PlayResY", "720")]).play_res(), (960, 720));
+        assert_eq!(info(&[("playresx", "1280")]).play_res(), (1280, 1024));
+        assert_eq!(info(&[("PlayResX", "640"), ("PlayResY", "0")]).play_res(), (640, 480));
+    }
+
+    #[test]
+    fn typed() {
+        let mut i = info(&[
+            ("ScriptType", "v4.00+"),
+            ("X-Custom", "1"),
+            ("ScaledBorderAndShadow", "yes"),
+            ("YCbCr Matrix", "TV.709"),
+            ("WrapStyle", "2"),
+        ]);
+        assert_eq!(i.script_type(), Some(ScriptType::V4Plus));
+        assert!(i.scaled_border_and_shadow());
+        assert_eq!(i.ycbcr_matrix(), Some(YCbCrMatrix::Bt709Tv));
+        assert_eq!(i.wrap_style(), WrapStyle::None);
+        assert_eq!(i.timer(), 100.0);
+        assert_eq!(i.collisions(), Collisions::Normal);
+
+        i.entries.insert(1, InfoEntry::Comment(" made by hand".to_string()));
+        i.set_wrap_style(WrapStyle::SmartLower);
+        i.set_play_res(1920, 1080);
+        i.set_title("Test");
+        i.set_timer(100.0);
+        i.set_kerning(true);
+        i.set_collisions(Collisions::Reverse);
+        assert!(i.kerning());
+        assert_eq!(i.collisions(), Collisions::Reverse);
+        let keys: Vec<_> = i.fields().collect();
+        assert_eq!(keys, [
+            ("ScriptType", "v4.00+"),
+            ("X-Custom", "1"),
+            ("ScaledBorderAndShadow", "yes"),
+            ("YCbCr Matrix", "TV.709"),
+            ("WrapStyle", "3"),
+            ("PlayResX", "1920"),
+            ("PlayResY", "1080"),
+            ("Title", "Test"),
+            ("Timer", "100.0000"),
+            ("Kerning", "yes"),
+            ("Collisions", "Reverse"),
+        ]);
+        // Comments stay where they were
+        assert_eq!(i.entries[1], InfoEntry::Comment(" made by hand".to_string()));
+    }
+}
@@ -0,0 +1,127 @@
+use core::hash::{Hash, Hasher};
+use core::ops::Range;
+
+use alloc::{string::String, vec::Vec};
+
+use crate::Section;
+
+/// How a parsed script was laid out, so the writer can reproduce it byte-for-byte
+///
+/// A [`Script`](crate::Script) built from scratch has an empty layout, and
+/// gets written in the canonical form.
+#[derive(Default, Clone, Debug)]
+pub struct Layout {
+    /// Starts with a UTF-8 byte order mark
+    pub bom: bool,
+
+    /// Lines end with `\r\n` rather than `\n`, going by the first one
+    pub crlf: bool,
+
+    /// Numbers of the lines, from 0, that end the other way, sorted
+    ///
+    /// The writer goes by the line numbers of what it writes, so the endings
+    /// stay in place as long as lines don't get added or removed before them.
+    pub mixed: Vec<usize>,
+
+    /// The last line has no line ending
+    pub missing_final_newline: bool,
+
+    /// Blank lines before the first section header
+    pub preamble: Vec<String>,
+
+    /// Sections, in file order
+    pub sections: Vec<SectionLayout>,
+
+    /// Source lines of [`ScriptInfo::entries`](crate::ScriptInfo::entries), by index
+    pub info: Vec<Source>,
+
+    /// Source lines of [`Script::styles`](crate::Script::styles), by index
+    pub styles: Vec<Source>,
+
+    /// Source lines of [`Script::events`](crate::Script::events), by index
+    pub events: Vec<Source>,
+}
+
+/// A section header and the lines of the section that aren't items
+#[derive(Clone, Debug)]
+pub struct SectionLayout {
+    /// What the section holds
+    pub section: Section,
+
+    /// Name between the brackets, as written
+    pub name: String,
+
+    /// What comes after the `]` on the header line, as written
+    pub rest: String,
+
+    /// Indices of the items that were in the section, in the list of its
+    /// kind, for sections that come up more than once to keep theirs
+    pub items: Range<usize>,
+
+    /// Columns the lines of a styles or events section were read with, those
+    /// of its `Format:` line or else the default ones
+    pub format: Vec<String>,
+
+    /// Lines that aren't items, with the index of the item they come before
+    pub filler: Vec<(usize, Filler)>,
+}
+
+/// A line of a section that isn't one of its items
+#[derive(Clone, Debug)]
+pub enum Filler {
+    /// The `Format:` line, as written
+    Format(String),
+
+    /// Blank lines, `;` comments and anything else, as written
+    Raw(String),
+}
+
+/// The line an item was parsed from
+///
+/// The writer only reuses it while the item still has the same fingerprint,
+/// ie. it wasn't modified since it was parsed.
+#[derive(Clone, Debug)]
+pub struct Source {
+    /// The line, without its line ending
+    pub line: String,
+
+    /// [`fingerprint`] of the item parsed from `line`
+    pub hash: u64,
+}
+
+impl Source {
+    pub fn new<T: Hash>(line: String, item: &T) -> Self {
+        Self {
+            line,
+            hash: fingerprint(item),
+        }
+    }
+
+    /// Whether `item` is still what was parsed from the line
+    pub fn matches<T: Hash>(&self, item: &T) -> bool {
+        self.hash == fingerprint(item)
+    }
+}
+
+/// FNV-1a, so fingerprints don't depend on `std`
+struct Fnv(u64);
+
+impl Hasher for Fnv {
+    fn finish(&self) -> u64 {
+        self.0
+    }
+
+    fn write(&mut self, bytes: &[u8]) {
+        for &b in bytes {
+            self.0 ^= b as u64;
+            self.0 = self.0.wrapping_mul(0x100000001b3);
+        }
+    }
+}
+
+/// Hash of an item, used to tell whether it was modified
+pub fn fingerprint<T: Hash>(item: &T) -> u64 {
+    let mut h = Fnv(0xcbf29ce484222325);
+    item.hash(&mut h);
+    h.finish()
+}
@@ -0,0 +1,481 @@
+use core::fmt::{self, Display};
+
+use alloc::{string::String, vec::Vec};
+
+use crate::Colour;
+use crate::resample::Resampler;
+use crate::timestamp::scale;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum XOrYOrZ {
+    X,
+    Y,
+    Z,
+}
+
+impl XOrYOrZ {
+    fn as_str(&self) -> &'static str {
+        match self {
+            XOrYOrZ::X => "x",
+            XOrYOrZ::Y => "y",
+            XOrYOrZ::Z => "z",
+        }
+    }
+}
+
+/// Which of the karaoke tags was used
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum KaraokeKind {
+    /// `\k`, highlight by words
+    K,
+    /// `\K`, fill up from left to right
+    KUpper,
+    /// `\kf`, same as `\K`
+    Kf,
+    /// `\ko`, outline highlighting from left to right
+    Ko,
+    /// `\kt`, sets the start time of the next syllable (libass)
+    Kt,
+}
+
+impl KaraokeKind {
+    fn as_str(&self) -> &'static str {
+        match self {
+            KaraokeKind::K      => "k",
+            KaraokeKind::KUpper => "K",
+            KaraokeKind::Kf     => "kf",
+            KaraokeKind::Ko     => "ko",
+            KaraokeKind::Kt     => "kt",
+        }
+    }
+}
+
+/// Shape given to `\clip` / `\iclip`
+#[derive(Clone, PartialEq, Debug)]
+pub enum ClipShape {
+    /// `(x1,y1,x2,y2)`
+    Rect(f32, f32, f32, f32),
+    /// `([scale,]drawing commands)`
+    Vector(Option<u32>, String),
+}
+
+impl Display for ClipShape {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ClipShape::Rect(x1, y1, x2, y2)  => write!(f, "({},{},{},{})", x1, y1, x2, y2),
+            ClipShape::Vector(Some(s), d) => write!(f, "({},{})", s, d),
+            ClipShape::Vector(None, d)    => write!(f, "({})", d),
+        }
+    }
+}
+
+/// ASS/SSA override codes
+///
+/// Tags that take a value reset to the style default when the value is
+/// missing (eg. `{\bord}`), which is represented by `None`.
+///
+/// - [**`\b`** *`"0" / "1" / weight`*](#variant.Bold)
+/// - [**`\i`** *`"0" / "1"`*](#variant.Italic)
+/// - [**`\u`** *`"0" / "1"`*](#variant.Underline)
+/// - [**`\s`** *`"0" / "1"`*](#variant.Strikeout)
+/// - [**`\bord`**, **`\xbord`**, **`\ybord`** *`width`*](#variant.Border)
+/// - [**`\shad`**, **`\xshad`**, **`\yshad`** *`depth`*](#variant.Shadow)
+/// - [**`\be`** *`strength`*](#variant.BlurEdges)
+/// - [**`\blur`** *`strength`*](#variant.Blur) **(ASS)**
+/// - [**`\fn`** *`font name`*](#variant.FontName)
+/// - [**`\fs`** *`font size`*](#variant.FontSize)
+/// - [**`\fs+`**, **`\fs-`** *`steps`*](#variant.FontSizeRelative)
+/// - [**`\fsc`** *`"x" / "y"`* *`percent`*](#variant.Scale)
+/// - [**`\fsp`** *`pixels`*](#variant.Spacing)
+/// - [**`\fr`** *`"x" / "y" / "z"`* *`degrees`*](#variant.Rotation)
+/// - [**`\fa`** *`"x" / "y"`* *`factor`*](#variant.Shear) **(ASS)**
+/// - [**`\fe`** *`charset`*](#variant.FontEncoding)
+/// - [**`\c&H`** *`bbggrr`* **`&`**](#variant.Color)
+/// - [**`\alpha&H`** *`aa`* **`&`**](#variant.Alpha)
+/// - [**`\a`** *`alignment`*](#variant.Alignment)
+/// - [**`\an`** *`alignment`*](#variant.AlignmentNumpad) **(ASS)**
+/// - [**`\k`** *`duration`*](#variant.Karaoke)
+/// - [**`\q`** *`num`*](#variant.WrappingStyle) **(ASS)**
+/// - [**`\r`** *`style`*](#variant.Reset)
+/// - [**`\pos`** *`(x,y)`*](#variant.Position) **(ASS)**
+/// - [**`\org`** *`(x,y)`*](#variant.Origin) **(ASS)**
+/// - [**`\move`** *`(x1,y1,x2,y2[,t1,t2])`*](#variant.Move) **(ASS)**
+/// - [**`\fad`** *`(t1,t2)`*](#variant.Fade) **(ASS)**
+/// - [**`\fade`** *`(a1,a2,a3,t1,t2,t3,t4)`*](#variant.FadeComplex) **(ASS)**
+/// - [**`\clip`** *`(...)`*](#variant.Clip) **(ASS)**
+/// - [**`\iclip`** *`(...)`*](#variant.InverseClip) **(ASS)**
+/// - [**`\p`** *`scale`*](#variant.Drawing) **(ASS)**
+/// - [**`\pbo`** *`offset`*](#variant.BaselineOffset) **(ASS)**
+/// - [**`\t`** *`([t1,t2,][accel,]tags)`*](#variant.Transform) **(ASS)**
+#[derive(Clone, PartialEq, Debug)]
+pub enum OverrideCode {
+    /// # **`\b`** *`"0" / "1" / weight`*
+    ///
+    /// `\b1` makes the text bold. `\b0` forces non-bold text.
+    /// > eg. There is a {\b1}bold {\b0}word here
+    ///
+    /// ## ASS
+    ///
+    /// When this parameter is greater than 1, it will be used as the weight of the font.
+    /// (400 = Normal, 700 = Bold, note: most fonts will quantize to 2 or 3 levels of thickness)
+    Bold(Option<u32>),
+    /// # **`\i`** *`"0" / "1"`*
+    ///
+    /// `\i1` makes the text italic. `\i0` forces non-italic text.
+    /// > eg. `There is an {\i1}italicised {\i0}word here`
+    Italic(Option<bool>),
+    /// # **`\u`** *`"0" / "1"`*
+    ///
+    /// underline
+    Underline(Option<bool>),
+    /// # **`\s`** *`"0" / "1"`*
+    ///
+    /// strikeout
+    Strikeout(Option<bool>),
+    /// # **`\bord`** *`width`*
+    ///
+    /// Width of the border.
+    ///
+    /// `\xbord` and `\ybord` set it along a single axis, `\bord` along both (`None`).
+    Border(Option<XOrYOrZ>, Option<f32>),
+    /// # **`\shad`** *`depth`*
+    ///
+    /// Depth of the shadow.
+    ///
+    /// `\xshad` and `\yshad` set it along a single axis, `\shad` along both (`None`).
+    Shadow(Option<XOrYOrZ>, Option<f32>),
+    /// # **`\be`** *`strength`*
+    ///
+    /// blur edges
+    ///
+    /// ## ASS
+    ///
+    /// *`strength`* is the number of times the blur is applied. Fractional
+    /// ones get rounded when rendering, after any `\t`, like libass does.
+    BlurEdges(Option<f32>),
+    /// # **`\blur`** *`strength`* **(ASS)**
+    ///
+    /// Gaussian blur of the edges.
+    Blur(Option<f32>),
+    /// # **`\fn`** *`font name`*
+    ///
+    /// specifies a font which you have installed in Windows. This is case sensitive.
+    /// > e.g. `Here is some {\fnCourier New}fixed space text`
+    ///
+    /// If you use a font name that doesn't exist, then Arial will be used instead.
+    FontName(Option<String>),
+    /// # **`\fs`** *`font size`*
+    ///
+    /// A number specifying a font point size.
+    /// > e.g. `{\fs16}This is small text. {\fs28}This is large text`
+    FontSize(Option<f32>),
+    /// # **`\fs+`**, **`\fs-`** *`steps`*
+    ///
+    /// Makes the font size a tenth bigger or smaller per step, eg. `\fs+2`
+    /// is 120% of the current size and `\fs-2` 80% of it.
+    FontSizeRelative(f32),
+    /// # **`\fsc`** *`"x" / "y"`* *`percent`*
+    ///
+    /// `\fsc` on its own (`None`) resets both axes.
+    Scale(Option<XOrYOrZ>, Option<f32>),
+    /// # **`\fsp`** *`pixels`*
+    Spacing(Option<f32>),
+    /// # **`\fr`** *`"x" / "y" / "z"`* *`degrees`*
+    ///
+    /// *`degrees`* sets the rotation angle around the x/y/z axis.
+    ///
+    /// `\fr` defaults to `\frz`.
+    Rotation(XOrYOrZ, Option<f32>),
+    /// # **`\fa`** *`"x" / "y"`* *`factor`* **(ASS)**
+    ///
+    /// Shears the text along the x/y axis.
+    Shear(XOrYOrZ, Option<f32>),
+    /// # **`\fe`** *`charset`*
+    ///
+    /// A number specifying the character set (font encoding)
+    FontEncoding(Option<i32>),
+    /// # **`\c&H`** *`bbggrr`* **`&`**
+    ///
+    /// *`bbggrr`* is a hexadecimal RGB value, but in reverse order. Leading zeroes are not required.
+    /// > e.g. `{\c&HFF&}This is pure, full intensity red`
+    /// >
+    /// > `{\c&HFF00&}This is pure, full intensity Green`
+    /// >
+    /// > `{\c&HFF0000&}This is pure, full intensity Blue`
+    /// >
+    /// > `{\c&HFFFFFF&}This is White`
+    /// >
+    /// > `{\c&HA0A0A&}This is dark grey`
+    ///
+    /// `\1c&Hbbggrr&`, `\2c&Hbbggrr&`, `\3c&Hbbggrr&`, `\4c&Hbbggrr&` to set specific colors.
+    ///
+    /// The first field is the colour slot (`1`-`4`); `\c` is `\1c`.
+    Color(u8, Option<Colour>),
+    /// # **`\alpha&H`** *`aa`* **`&`**
+    ///
+    /// `\1a&Haa&`, `\2a&Haa&`, `\3a&Haa&`, `\4a&Haa&` to set specific alpha channels.
+    ///
+    /// The first field is the colour slot (`1`-`4`), or `None` for `\alpha`, which sets all four.
+    Alpha(Option<u8>, Option<u8>),
+    /// # **`\a`** *`alignment`*
+    ///
+    /// *`alignment`* is a number specifying the onscreen alignment/positioning of a subtitle.
+    ///
+    /// - A value of 1 specifies a left-justified subtitle
+    /// - A value of 2 specifies a centered subtitle
+    /// - A value of 3 specifies a right-justified subtitle
+    ///
+    /// - Adding 4 to the value specifies a "Toptitle"
+    /// - Adding 8 to the value specifies a "Midtitle"
+    ///
+    /// 0 or nothing resets to the style default (which is usually 2)
+    /// > eg. `{\a1}This is a left-justified subtitle`
+    /// >
+    /// > `{\a2}This is a centered subtitle`
+    /// >
+    /// > `{\a3}This is a right-justified subtitle`
+    /// >
+    /// > `{\a5}This is a left-justified toptitle`
+    /// >
+    /// > `{\a11}This is a right-justified midtitle`
+    ///
+    /// ## ASS
+    ///
+    /// Only the first appearance counts.
+    Alignment(Option<u8>),
+    /// # **`\an`** *`alignment`* **(ASS)**
+    ///
+    /// numpad layout
+    ///
+    /// Only the first appearance counts.
+    AlignmentNumpad(Option<u8>),
+    /// # **`\k`** *`duration`*
+    ///
+    /// *`duration`* is the amount of time that each section of text is highlighted for in a dialogue event with the Karaoke effect. The durations are in hundredths of seconds.
+    /// > eg. `{\k94}This {\k48}is {\k24}a {\k150}karaoke {\k94}line`
+    ///
+    /// `\k<duration>` highlight by words
+    ///
+    /// `\kf` or `\K<duration>` fill up from left to right
+    ///
+    /// `\ko<duration>` outline highlighting from left to right
+    Karaoke(KaraokeKind, i32),
+    /// # **`\q`** *`num`* **(ASS)**
+    ///
+    /// *`num`*-- wrapping style
+    WrappingStyle(Option<u8>),
+    /// # **`\r`** *`style`*
+    ///
+    /// This cancels all previous style overrides in a line
+    ///
+    /// ## ASS
+    ///
+    /// *`style`* Restores to *`style`* instead of the dialogue line default.
+    /// Any style modifier followed by no recognizable parameter resets to the default.
+    Reset(Option<String>),
+    /// # **`\pos`** *`(x,y)`* **(ASS)**
+    ///
+    /// Positions the line at *`x`*, *`y`*, relative to the alignment.
+    Position(f32, f32),
+    /// # **`\org`** *`(x,y)`* **(ASS)**
+    ///
+    /// Moves the origin of rotation to *`x`*, *`y`*.
+    Origin(f32, f32),
+    /// # **`\move`** *`(x1,y1,x2,y2[,t1,t2])`* **(ASS)**
+    ///
+    /// Moves the line from *`x1`*, *`y1`* to *`x2`*, *`y2`*, between *`t1`* and *`t2`* (milliseconds),
+    /// or over the whole duration of the line.
+    Move(f32, f32, f32, f32, Option<(i32, i32)>),
+    /// # **`\fad`** *`(t1,t2)`* **(ASS)**
+    ///
+    /// Fades in over *`t1`* and out over *`t2`* milliseconds.
+    Fade(i32, i32),
+    /// # **`\fade`** *`(a1,a2,a3,t1,t2,t3,t4)`* **(ASS)**
+    ///
+    /// Alpha goes from *`a1`* to *`a2`* between *`t1`* and *`t2`*,
+    /// then from *`a2`* to *`a3`* between *`t3`* and *`t4`*.
+    FadeComplex(u8, u8, u8, i32, i32, i32, i32),
+    /// # **`\clip`** *`(x1,y1,x2,y2)`* / *`([scale,]drawing commands)`* **(ASS)**
+    ///
+    /// Only draws what's inside the rectangle or drawing.
+    Clip(ClipShape),
+    /// # **`\iclip`** *`(x1,y1,x2,y2)`* / *`([scale,]drawing commands)`* **(ASS)**
+    ///
+    /// Only draws what's outside the rectangle or drawing.
+    InverseClip(ClipShape),
+    /// # **`\p`** *`scale`* **(ASS)**
+    ///
+    /// Switches to drawing mode, the text is treated as drawing commands.
+    /// `0` switches back to text.
+    Drawing(u32),
+    /// # **`\pbo`** *`offset`* **(ASS)**
+    ///
+    /// Baseline offset of drawings.
+    BaselineOffset(f32),
+    /// # **`\t`** *`([t1,t2,][accel,]tags)`* **(ASS)**
+    ///
+    /// Animates *`tags`* between *`t1`* and *`t2`* (milliseconds, the whole line if missing),
+    /// with acceleration *`accel`*.
+    Transform(Option<(i32, i32)>, Option<f32>, Vec<OverrideCode>),
+}
+
+impl OverrideCode {
+    /// Multiply the times of `\k`, `\t`, `\move`, `\fad` and `\fade` by `num / den`
+    ///
+    /// The tags inside a `\t` are left alone, none of them has times. So are
+    /// all of them if `den` is zero.
+    pub fn scale_times(&mut self, num: i64, den: i64) {
+        use OverrideCode::*;
+
+        let s = |t: &mut i32| if let Some(v) = scale(*t as i64, num, den) {
+            *t = v.clamp(i32::MIN as i64, i32::MAX as i64) as i32;
+        };
+        match self {
+            Karaoke(_, t) => s(t),
+            Move(.., Some((t1, t2))) | Fade(t1, t2) => {
+                s(t1);
+                s(t2);
+            },
+            FadeComplex(.., t1, t2, t3, t4) => {
+                for t in [t1, t2, t3, t4] {
+                    s(t);
+                }
+            },
+            Transform(Some((t1, t2)), ..) => {
+                s(t1);
+                s(t2);
+            },
+            _ => {},
+        }
+    }
+
+    /// Scale positions and sizes to another resolution
+    ///
+    /// That is `\pos`, `\move`, `\org`, `\clip`, `\iclip`, `\bord`,
+    /// `\shad`, `\blur`, `\fs`, `\fsp`, `\fscx` and `\pbo`. Only the tag
+    /// itself changes: the tags inside a `\t` need resampling one by one,
+    /// which is what `Script::resample` does. It also goes by the first `\a`
+    /// or `\an` for which edge the `MarginV` of the line is from.
+    pub fn resample(&mut self, r: &Resampler) {
+        use OverrideCode::*;
+
+        fn clip(c: &mut ClipShape, r: &Resampler) {
+            match c {
+                ClipShape::Rect(x1, y1, x2, y2) => {
+                    (*x1, *y1, *x2, *y2) = (r.x(*x1), r.y(*y1), r.x(*x2), r.y(*y2));
+                },
+                ClipShape::Vector(s, d) => *d = r.clip(d, *s),
+            }
+        }
+
+        match self {
+            Position(x, y) | Origin(x, y) => (*x, *y) = (r.x(*x), r.y(*y)),
+            Move(x1, y1, x2, y2, _) => {
+                (*x1, *y1, *x2, *y2) = (r.x(*x1), r.y(*y1), r.x(*x2), r.y(*y2));
+            },
+            Clip(c) | InverseClip(c) => clip(c, r),
+            Border(Some(XOrYOrZ::X), Some(v)) | Shadow(Some(XOrYOrZ::X), Some(v)) => *v = r.w(*v),
+            Border(_, Some(v)) | Shadow(_, Some(v)) => *v = r.h(*v),
+            Blur(Some(v)) | FontSize(Some(v)) => *v = r.h(*v),
+            Spacing(Some(v)) => *v = r.w(*v),
+            Scale(Some(XOrYOrZ::X), Some(v)) => *v = r.scale_x(*v),
+            BaselineOffset(v) => *v = r.h(*v),
+            _ => {},
+        }
+    }
+}
+
+impl Display for OverrideCode {
+    /// Format as a tag, including the leading `\`
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        use OverrideCode::*;
+
+        /// The value, if any
+        fn val<T: Display>(f: &mut fmt::Formatter<'_>, v: &Option<T>) -> fmt::Result {
+            match v {
+                Some(v) => write!(f, "{}", v),
+                None    => Ok(()),
+            }
+        }
+
+        /// `\name` followed by the value, if any
+        fn tag<T: Display>(f: &mut fmt::Formatter<'_>, name: &str, v: &Option<T>) -> fmt::Result {
+            write!(f, "\\{}", name)?;
+            val(f, v)
+        }
+
+        fn axis(a: &Option<XOrYOrZ>) -> &'static str {
+            a.as_ref().map_or("", XOrYOrZ::as_str)
+        }
+
+        match self {
+            Bold(v)            => tag(f, "b", v),
+            Italic(v)          => tag(f, "i", &v.map(u8::from)),
+            Underline(v)       => tag(f, "u", &v.map(u8::from)),
+            Strikeout(v)       => tag(f, "s", &v.map(u8::from)),
+            Border(a, v)       => { write!(f, "\\{}bord", axis(a))?; val(f, v) },
+            Shadow(a, v)       => { write!(f, "\\{}shad", axis(a))?; val(f, v) },
+            BlurEdges(v)       => tag(f, "be", v),
+            Blur(v)            => tag(f, "blur", v),
+            FontName(v)        => tag(f, "fn", v),
+            FontSize(v)        => tag(f, "fs", v),
+            FontSizeRelative(v) => write!(f, "\\fs{:+}", v),
+            Scale(a, v)        => { write!(f, "\\fsc{}", axis(a))?; val(f, v) },
+            Spacing(v)         => tag(f, "fsp", v),
+            Rotation(a, v)     => { write!(f, "\\fr{}", a.as_str())?; val(f, v) },
+            Shear(a, v)        => { write!(f, "\\fa{}", a.as_str())?; val(f, v) },
+            FontEncoding(v)    => tag(f, "fe", v),
+            Color(n, v)        => {
+                write!(f, "\\{}c", n)?;
+                match v {
+                    Some(v) => write!(f, "{}", v.tag()),
+                    None    => Ok(()),
+                }
+            },
+            Alpha(n, v)        => {
+                match n {
+                    Some(n) => write!(f, "\\{}a", n)?,
+                    None    => write!(f, "\\alpha")?,
+                }
+                match v {
+                    Some(v) => write!(f, "&H{:02X}&", v),
+                    None    => Ok(()),
+                }
+            },
+            Alignment(v)       => tag(f, "a", v),
+            AlignmentNumpad(v) => tag(f, "an", v),
+            Karaoke(k, v)      => write!(f, "\\{}{}", k.as_str(), v),
+            WrappingStyle(v)   => tag(f, "q", v),
+            Reset(v)           => tag(f, "r", v),
+            Position(x, y)     => write!(f, "\\pos({},{})", x, y),
+            Origin(x, y)       => write!(f, "\\org({},{})", x, y),
+            Move(x1, y1, x2, y2, t) => {
+                write!(f, "\\move({},{},{},{}", x1, y1, x2, y2)?;
+                if let Some((t1, t2)) = t {
+                    write!(f, ",{},{}", t1, t2)?;
+                }
+                write!(f, ")")
+            },
+            Fade(t1, t2)       => write!(f, "\\fad({},{})", t1, t2),
+            FadeComplex(a1, a2, a3, t1, t2, t3, t4) =>
+                write!(f, "\\fade({},{},{},{},{},{},{})", a1, a2, a3, t1, t2, t3, t4),
+            Clip(c)            => write!(f, "\\clip{}", c),
+            InverseClip(c)     => write!(f, "\\iclip{}", c),
+            Drawing(v)         => write!(f, "\\p{}", v),
+            BaselineOffset(v)  => write!(f, "\\pbo{}", v),
+            Transform(t, a, tags) => {
+                write!(f, "\\t(")?;
+                if let Some((t1, t2)) = t {
+                    write!(f, "{},{},", t1, t2)?;
+                }
+                if let Some(a) = a {
+                    write!(f, "{},", a)?;
+                }
+                for tag in tags {
+                    write!(f, "{}", tag)?;
+                }
+                write!(f, ")")
+            },
+        }
+    }
+}
@@ -0,0 +1,231 @@
+//! Changing the resolution of a script
+//!
+//! A [`Resampler`] maps the coordinate space of a script (its `PlayResX`
+//! and `PlayResY`) onto another one, like Aegisub's Resample Resolution.
+//! Sizes scale with the height, so text keeps its shape unless the aspect
+//! ratio gets stretched.
+
+use alloc::string::String;
+use core::fmt::Write;
+
+use crate::timing::floor;
+
+/// What to do when the aspect ratio changes
+#[derive(Default, Clone, Copy, PartialEq, Debug)]
+pub enum AspectRatio {
+    /// Stretch the old frame over the new one, text included
+    #[default]
+    Stretch,
+    /// Fit the old frame inside the new one, adding borders around it
+    AddBorders,
+    /// Fill the new frame with the old one, cropping what sticks out
+    RemoveBorders,
+    /// Add these many pixels to the left, right, top and bottom of the old
+    /// frame (negative ones crop), then stretch whatever is left over
+    Margins(i32, i32, i32, i32),
+}
+
+/// Maps coordinates from one resolution to another
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Resampler {
+    pub(crate) rx: f64,
+    pub(crate) ry: f64,
+    /// How much wider text gets
+    pub(crate) stretch: f64,
+    /// Borders added around the old frame
+    pub(crate) left: f64,
+    pub(crate) right: f64,
+    pub(crate) top: f64,
+    pub(crate) bottom: f64,
+}
+
+impl Resampler {
+    /// Resampler from a `from` frame to a `to` one, both `(width, height)`
+    ///
+    /// Aspect ratios less than 1% apart are taken to be the same.
+    pub fn new(from: (i32, i32), to: (i32, i32), ar: AspectRatio) -> Self {
+        let (sx, sy) = (from.0 as f64, from.1 as f64);
+        let (dx, dy) = (to.0 as f64, to.1 as f64);
+        let (old, new) = (sx / sy, dx / dy);
+        let close = |a: f64| (a - new).abs() / new <= 0.01;
+
+        // Left, right, top, bottom
+        let mut m = [0.0; 4];
+        let mut stretch = 1.0;
+        match ar {
+            AspectRatio::Margins(l, r, t, b) => {
+                m = [l, r, t, b].map(|v| v as f64);
+                let ar = (sx + m[0] + m[1]) / (sy + m[2] + m[3]);
+                if !close(ar) {
+                    stretch = new / ar;
+                }
+            },
+            _ if close(old) => {},
+            AspectRatio::Stretch => stretch = new / old,
+            AspectRatio::AddBorders | AspectRatio::RemoveBorders => {
+                let pillarbox = (new > old) == (ar == AspectRatio::AddBorders);
+                match pillarbox {
+                    true  => m[0] = (sy * new - sx) / 2.0,
+                    false => m[2] = (sx / new - sy) / 2.0,
+                }
+                m[1] = m[0];
+                m[3] = m[2];
+            },
+        }
+
+        Self {
+            rx: dx / (sx + m[0] + m[1]),
+            ry: dy / (sy + m[2] + m[3]),
+            stretch,
+            left: m[0],
+            right: m[1],
+            top: m[2],
+            bottom: m[3],
+        }
+    }
+
+    /// Horizontal position
+    pub fn x(&self, x: f32) -> f32 {
+        round((x as f64 + self.left) * self.rx)
+    }
+
+    /// Vertical position
+    pub fn y(&self, y: f32) -> f32 {
+        round((y as f64 + self.top) * self.ry)
+    }
+
+    /// Horizontal size
+    pub fn w(&self, w: f32) -> f32 {
+        round(w as f64 * self.rx)
+    }
+
+    /// Vertical size, and sizes that don't have a direction
+    pub fn h(&self, h: f32) -> f32 {
+        round(h as f64 * self.ry)
+    }
+
+    /// Horizontal scale, eg. `\fscx`
+    pub fn scale_x(&self, s: f32) -> f32 {
+        round(s as f64 * self.stretch)
+    }
+
+    /// `MarginL` of a style or event
+    pub fn margin_l(&self, m: i32) -> i32 {
+        floor((m as f64 + self.left) * self.rx + 0.5) as i32
+    }
+
+    /// `MarginR` of a style or event
+    pub fn margin_r(&self, m: i32) -> i32 {
+        floor((m as f64 + self.right) * self.rx + 0.5) as i32
+    }
+
+    /// `MarginV` of a style or event with numpad alignment `align`
+    ///
+    /// It's from the top edge for top alignments and from the bottom one for
+    /// bottom alignments, so it gets the border of that edge. Middle ones
+    /// don't use it and only get it scaled.
+    pub fn margin_v(&self, m: i32, align: u8) -> i32 {
+        let border = match align {
+            7..=9 => self.top,
+            1..=3 => self.bottom,
+            _     => 0.0,
+        };
+        floor((m as f64 + border) * self.ry + 0.5) as i32
+    }
+
+    /// Drawing commands, eg. from `\p1` text, with their coordinates scaled
+    ///
+    /// Everything but the numbers is kept as is.
+    pub fn drawing(&self, d: &str) -> String {
+        map_drawing(d, |x| self.w(x), |y| self.h(y))
+    }
+
+    /// Drawing of a `\clip` at `scale`, moved along with the frame
+    pub fn clip(&self, d: &str, scale: Option<u32>) -> String {
+        // Coordinates are in 1/2^(scale-1) pixels
+        let k = (1u64 << scale.unwrap_or(1).clamp(1, 32).saturating_sub(1)) as f64;
+        map_drawing(
+            d,
+            |x| round((x as f64 + self.left * k) * self.rx),
+            |y| round((y as f64 + self.top * k) * self.ry),
+        )
+    }
+}
+
+/// `d` with the numbers replaced, going back and forth between `x` and `y`
+fn map_drawing(d: &str, x: impl Fn(f32) -> f32, y: impl Fn(f32) -> f32) -> String {
+    let mut out = String::with_capacity(d.len());
+    let mut rest = d;
+    // Coordinates come in pairs after each command
+    let mut is_y = false;
+    while let Some(c) = rest.chars().next() {
+        let len = number_len(rest);
+        match rest[..len].parse::<f32>() {
+            Ok(n) if len > 0 => {
+                let _ = write!(out, "{}", if is_y { y(n) } else { x(n) });
+                is_y = !is_y;
+                rest = &rest[len..];
+            },
+            _ => {
+                if c.is_ascii_alphabetic() {
+                    is_y = false;
+                }
+                out.push(c);
+                rest = &rest[c.len_utf8()..];
+            },
+        }
+    }
+    out
+}
+
+/// Length of the number at the start of `s`, `0` if there is none
+fn number_len(s: &str) -> usize {
+    let b = s.as_bytes();
+    let sign = matches!(b.first(), Some(b'-' | b'+')) as usize;
+    sign + b[sign..].iter().take_while(|c| c.is_ascii_digit() || **c == b'.').count()
+}
+
+/// Rounded to a thousandth, so `Display` doesn't show float noise
+fn round(v: f64) -> f32 {
+    (floor(v * 1000.0 + 0.5) as f64 / 1000.0) as f32
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn aspect_ratio() {
+        let r = Resampler::new((1280, 720), (1920, 1080), AspectRatio::Stretch);
+        assert_eq!((r.x(640.0), r.y(360.0), r.h(2.0), r.scale_x(100.0)), (960.0, 540.0, 3.0, 100.0));
+
+        let r = Resampler::new((640, 480), (1920, 1080), AspectRatio::Stretch);
+        assert_eq!((r.x(320.0), r.y(240.0), r.scale_x(100.0)), (960.0, 540.0, 133.333));
+
+        // 640x480 in 853.3x480, then scaled up
+        let r = Resampler::new((640, 480), (1920, 1080), AspectRatio::AddBorders);
+        assert_eq!((r.x(0.0), r.x(640.0), r.y(240.0), r.scale_x(100.0)), (240.0, 1680.0, 540.0, 100.0));
+
+        // 640x360 out of 640x480
+        let r = Resampler::new((640, 480), (1920, 1080), AspectRatio::RemoveBorders);
+        assert_eq!((r.x(320.0), r.y(60.0), r.y(420.0)), (960.0, 0.0, 1080.0));
+
+        let r = Resampler::new((640, 480), (1920, 1080), AspectRatio::Margins(0, 0, -60, -60));
+        assert_eq!((r.y(60.0), r.scale_x(100.0)), (0.0, 100.0));
+        assert_eq!((r.margin_l(10), r.margin_v(30, 8)), (30, -90));
+
+        // Bottom margins get the bottom border
+        let r = Resampler::new((640, 480), (640, 480), AspectRatio::Margins(0, 0, 0, 40));
+        assert_eq!((r.margin_v(30, 2), r.margin_v(30, 8), r.margin_v(30, 5)), (65, 28, 28));
+    }
+
+    #[test]
+    fn drawing() {
+        let r = Resampler::new((640, 360), (1920, 1080), AspectRatio::AddBorders);
+        assert_eq!(r.drawing("m 0 0 l 10 -5.5 b1 2 3 4 5 6"), "m 0 0 l 30 -16.5 b3 6 9 12 15 18");
+
+        let r = Resampler::new((640, 480), (1920, 1080), AspectRatio::AddBorders);
+        assert_eq!(r.clip("m 0 0 l 10 0", None), "m 240 0 l 262.5 0");
+        assert_eq!(r.clip("m 0 0 l 20 0", Some(2)), "m 480 0 l 525 0");
+    }
+}
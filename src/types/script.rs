@@ -0,0 +1,168 @@
+use alloc::{string::String, vec::Vec};
+
+use crate::{Event, EventKind, Layout, Result, ScriptInfo, Section, Style, Timestamp};
+use crate::timing::{self, Snap, Timecodes};
+use crate::uuenc::{uudecode, uuencode};
+
+/// Parsed ASS/SSA document
+///
+/// Every section of the file ends up in one of the fields below; sections
+/// the parser doesn't know about are kept verbatim in [`extra`](#structfield.extra).
+#[derive(Default, Clone, Debug)]
+pub struct Script {
+    /// `[Script Info]`
+    pub info: ScriptInfo,
+
+    /// `Format:` line of `[V4+ Styles]` / `[V4 Styles]`, the last one if
+    /// there are several
+    ///
+    /// Styles sections of the [`layout`](#structfield.layout) keep their own,
+    /// this is for those written without one.
+    pub style_format: Vec<String>,
+
+    /// `[V4+ Styles]` / `[V4 Styles]`
+    pub styles: Vec<Style>,
+
+    /// `Format:` line of `[Events]`, the last one if there are several
+    ///
+    /// Like [`style_format`](#structfield.style_format), for events sections
+    /// written without one.
+    pub event_format: Vec<String>,
+
+    /// `[Events]`
+    pub events: Vec<Event>,
+
+    /// `[Fonts]`
+    pub fonts: Vec<Attachment>,
+
+    /// `[Graphics]`
+    pub graphics: Vec<Attachment>,
+
+    /// Any other section
+    pub extra: Vec<ExtraSection>,
+
+    /// How the file was laid out
+    pub layout: Layout,
+}
+
+impl Script {
+    /// Number of items of the kind `section` holds, `0` for unknown sections
+    pub fn item_count(&self, section: Section) -> usize {
+        match section {
+            Section::ScriptInfo => self.info.entries.len(),
+            Section::V4PlusStyles | Section::V4Styles => self.styles.len(),
+            Section::Fonts => self.fonts.len(),
+            Section::Graphics => self.graphics.len(),
+            Section::Events => self.events.len(),
+            Section::None => 0,
+        }
+    }
+
+    /// Move every event by `by`, clamping times that would end up negative to zero
+    pub fn shift(&mut self, by: Timestamp) {
+        self.shift_where(by, |_| true);
+    }
+
+    /// Move the events `filter` picks by `by`
+    pub fn shift_where(&mut self, by: Timestamp, mut filter: impl FnMut(&Event) -> bool) {
+        for e in self.events.iter_mut().filter(|e| filter(e)) {
+            e.shift(by);
+        }
+    }
+
+    /// Link the dialogue lines and snap them to `keyframes`, see [`timing::snap`]
+    pub fn snap(&mut self, opts: &Snap, keyframes: &[i64], tc: &Timecodes) {
+        self.snap_where(opts, keyframes, tc, |e| e.kind == EventKind::Dialogue);
+    }
+
+    /// Link the events `filter` picks and snap them to `keyframes`
+    pub fn snap_where(
+        &mut self,
+        opts: &Snap,
+        keyframes: &[i64],
+        tc: &Timecodes,
+        mut filter: impl FnMut(&Event) -> bool,
+    ) {
+        timing::snap(self.events.iter_mut().filter(|e| filter(e)), opts, keyframes, tc);
+    }
+
+    /// Names of the fonts embedded in `[Fonts]`
+    pub fn font_names(&self) -> impl Iterator<Item = &str> {
+        self.fonts.iter().map(|f| f.name.as_str())
+    }
+
+    /// Data of the embedded font called `name`
+    pub fn extract_font(&self, name: &str) -> Option<Result<Vec<u8>>> {
+        extract(&self.fonts, name)
+    }
+
+    /// Embed a font, replacing any other one called `name`
+    ///
+    /// By convention, `name` is the file name with `_0` before the extension
+    /// (eg. `arial_0.ttf`), or `_B0`, `_I0`, `_BI0` for bold and italic fonts.
+    pub fn embed_font(&mut self, name: &str, data: &[u8]) {
+        embed(&mut self.fonts, name, data);
+    }
+
+    /// Names of the images embedded in `[Graphics]`
+    pub fn graphic_names(&self) -> impl Iterator<Item = &str> {
+        self.graphics.iter().map(|g| g.name.as_str())
+    }
+
+    /// Data of the embedded image called `name`
+    pub fn extract_graphic(&self, name: &str) -> Option<Result<Vec<u8>>> {
+        extract(&self.graphics, name)
+    }
+
+    /// Embed an image, replacing any other one called `name`
+    pub fn embed_graphic(&mut self, name: &str, data: &[u8]) {
+        embed(&mut self.graphics, name, data);
+    }
+}
+
+fn extract(list: &[Attachment], name: &str) -> Option<Result<Vec<u8>>> {
+    list.iter().find(|a| a.name == name).map(Attachment::data)
+}
+
+fn embed(list: &mut Vec<Attachment>, name: &str, data: &[u8]) {
+    let a = Attachment::new(name, data);
+    match list.iter_mut().find(|a| a.name == name) {
+        Some(f) => *f = a,
+        None    => list.push(a),
+    }
+}
+
+/// A file embedded in `[Fonts]` or `[Graphics]`
+#[derive(Default, Clone, PartialEq, Debug)]
+pub struct Attachment {
+    /// Name given on the `fontname:` / `filename:` line
+    pub name: String,
+
+    /// uuencoded data, one entry per line
+    pub lines: Vec<String>,
+}
+
+impl Attachment {
+    /// Attachment holding `data`
+    pub fn new(name: &str, data: &[u8]) -> Self {
+        Self {
+            name: String::from(name),
+            lines: uuencode(data),
+        }
+    }
+
+    /// Decoded data
+    pub fn data(&self) -> Result<Vec<u8>> {
+        uudecode(self.lines.iter().map(String::as_str))
+    }
+}
+
+/// A section the parser doesn't understand
+#[derive(Default, Clone, PartialEq, Debug)]
+pub struct ExtraSection {
+    /// Section name, without the brackets
+    pub name: String,
+
+    /// Lines of the section body, blank ones included
+    pub lines: Vec<String>,
+}
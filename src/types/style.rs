@@ -1,7 +1,12 @@
-use crate::*;
+use core::hash::{Hash, Hasher};
+
+use alloc::string::String;
+
+use crate::Colour;
+use crate::resample::Resampler;
 
 /// Style
-#[derive(Default, Clone)]
+#[derive(Clone, PartialEq, Debug)]
 pub struct Style {
     /// #1:
     ///  `Name`
@@ -18,36 +23,37 @@ pub struct Style {
 
     /// #3:
     ///  `Fontsize`
-    pub font_size: i8,
+    /// Can be a floating point number. (points)
+    pub font_size: f32,
 
     /// #4:
     ///  `PrimaryColour`
     ///
     /// A long integer BGR (blue-green-red) value. ie. the byte order in the hexadecimal equivelent of this number is BBGGRR
     /// This is the colour that a subtitle will normally appear in.
-    pub primary_color: i64,
+    pub primary_color: Colour,
 
     /// #5:
     ///  `SecondaryColour`
     ///
     /// A long integer BGR (blue-green-red) value. ie. the byte order in the hexadecimal equivelent of this number is BBGGRR
     /// This colour may be used instead of the Primary colour when a subtitle is automatically shifted to prevent an onscreen collsion, to distinguish the different subtitles.
-    pub secondary_color: i64,
+    pub secondary_color: Colour,
 
     /// #6:
-    ///  `OutlineColor`   (ASS),
+    ///  `OutlineColour`  (ASS),
     ///  `TertiaryColour` (SSA)
     ///
     /// A long integer BGR (blue-green-red) value. ie. the byte order in the hexadecimal equivelent of this number is BBGGRR.
     /// This colour may be used instead of the Primary colour when a subtitle is automatically shifted to prevent an onscreen collsion, to distinguish the different subtitles.
-    pub outline_color: i64,
+    pub outline_color: Colour,
 
     /// #7:
     ///  `BackColour`
     ///
     /// This is the colour of the subtitle outline or shadow, if these are used.
     /// A long integer BGR (blue-green-red) value. ie. the byte order in the hexadecimal equivelent of this number is BBGGRR.
-    pub back_color: i64,
+    pub back_color: Colour,
 
     /// #8:
     ///  `Bold`
@@ -81,19 +87,20 @@ pub struct Style {
     ///  `ScaleX` (ASS)
     ///
     /// Modifies the width of the font. (percent)
-    pub scale_x: i16,
+    pub scale_x: f32,
 
     /// #9.4:
     ///  `ScaleY` (ASS)
     ///
     /// Modifies the height of the font. (percent)
-    pub scale_y: i16,
+    pub scale_y: f32,
 
     /// #9.5:
     ///  `Spacing` (ASS)
     ///
-    /// Extra space between characters. (pixels)
-    pub spacing: i32,
+    /// Extra space between characters.
+    /// Can be a floating point number. (pixels)
+    pub spacing: f32,
 
     /// #9.6:
     ///  `Angle` (ASS)
@@ -167,62 +174,112 @@ pub struct Style {
     /// For a toptitle, it is the distance from the top of the screen.
     /// For a midtitle, the value is ignored - the text will be vertically centred.
     pub margin_v: i32,
+
+    /// #17:
+    ///  `AlphaLevel` (SSA)
+    ///
+    /// This defines the transparency of the text. SSA does not use this yet.
+    pub alpha_level: i32,
+
+    /// #18:
+    ///  `Encoding`
+    ///
+    /// This specifies the font character set or encoding and on multi-lingual Windows installations it provides access to characters used in more than one language.
+    /// It is usually 0 (zero) for English (Western, ANSI) Windows.
+    pub encoding: i32,
 }
 
-pub fn parse_style(r: &str) -> Option<Style> {
-    // Parse fields
-    let fields: Vec<&str> = {
-        let o;
-        let strp = l.strip_prefix("Style: ");
-        if strp != None {
-            o = strp.unwrap();
-        } else {
-            return None;
-        }
-        o.split(",").collect()
-    };
-
-    let iter = format.iter().clone().zip(fields.clone());
-
-    let mut style = Style::default();
-    for f in iter {
-        let k: &str = f.0;
-        let v: String = f.1.to_string();
-
-        macro_rules! parse {
-            () => {
-                match v.parse::<i8>().unwrap() {
-                    -1 => true,
-                    0  => false,
-                    _  => unimplemented!(),
-                }
-            };
-            ($type:ident) => {
-                v.parse::<$type>().unwrap()
-            };
+impl Default for Style {
+    /// The style libass falls back to when a line refers to a style that doesn't exist
+    fn default() -> Self {
+        Self {
+            name: String::from("Default"),
+            font_name: String::from("Arial"),
+            font_size: 18.0,
+            primary_color: Colour(0x00FFFFFF),
+            secondary_color: Colour(0x0000FFFF),
+            outline_color: Colour(0x00000000),
+            back_color: Colour(0x80000000),
+            bold: false,
+            italic: false,
+            underline: false,
+            strikeout: false,
+            scale_x: 100.0,
+            scale_y: 100.0,
+            spacing: 0.0,
+            angle: 0.0,
+            border_style: 1,
+            outline: 2.0,
+            shadow: 3.0,
+            alignment: 2,
+            margin_l: 20,
+            margin_r: 20,
+            margin_v: 20,
+            alpha_level: 0,
+            encoding: 1,
         }
+    }
+}
 
-        match k {
-            "Name"        => style.name = v,
-            "Fontname"    => style.font_name = v,
-            "Fontsize"    => style.font_size = parse!(i8),
-            "Bold"        => style.bold = parse!(),
-            "Italic"      => style.italic = parse!(),
-            "Underline"   => style.underline = parse!(),
-            "Strikeout"   => style.strikeout = parse!(),
-            "ScaleX"      => style.scale_x = parse!(i16),
-            "ScaleY"      => style.scale_y = parse!(i16),
-            "Spacing"     => style.spacing = parse!(i32),
-            "Angle"       => style.angle = parse!(f32),
-            "BorderStyle" => style.border_style = parse!(i8),
-            "Outline"     => style.outline = parse!(f32),
-            "Shadow"      => style.shadow = parse!(f32),
-            "Alignment"   => style.alignment = parse!(i8),
-            "MarginL"     => style.margin_l = parse!(i32),
-            "MarginR"     => style.margin_r = parse!(i32),
-            _             => {}
+impl Style {
+    /// [`alignment`](Self::alignment) on the numpad, `ssa` for the values of
+    /// `[V4 Styles]`
+    pub fn numpad_alignment(&self, ssa: bool) -> u8 {
+        match ssa {
+            true  => numpad(self.alignment as u8),
+            false => self.alignment.clamp(1, 9) as u8,
         }
     }
 
-    Some(style)
+    /// Scale the sizes and margins to another resolution, `ssa` as for
+    /// [`numpad_alignment`](Self::numpad_alignment)
+    pub fn resample(&mut self, r: &Resampler, ssa: bool) {
+        self.font_size = r.h(self.font_size);
+        self.scale_x = r.scale_x(self.scale_x);
+        self.spacing = r.w(self.spacing);
+        self.outline = r.h(self.outline);
+        self.shadow = r.h(self.shadow);
+        self.margin_l = r.margin_l(self.margin_l);
+        self.margin_r = r.margin_r(self.margin_r);
+        self.margin_v = r.margin_v(self.margin_v, self.numpad_alignment(ssa));
+    }
+}
+
+/// Numpad alignment of a legacy SSA one, as in `[V4 Styles]` and `\a`
+pub fn numpad(a: u8) -> u8 {
+    let h = (a & 3).max(1);
+    match () {
+        _ if a & 4 != 0 => h + 6,
+        _ if a & 8 != 0 => h + 3,
+        _ => h,
+    }
+}
+
+impl Hash for Style {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.name.hash(state);
+        self.font_name.hash(state);
+        self.font_size.to_bits().hash(state);
+        self.primary_color.hash(state);
+        self.secondary_color.hash(state);
+        self.outline_color.hash(state);
+        self.back_color.hash(state);
+        self.bold.hash(state);
+        self.italic.hash(state);
+        self.underline.hash(state);
+        self.strikeout.hash(state);
+        self.scale_x.to_bits().hash(state);
+        self.scale_y.to_bits().hash(state);
+        self.spacing.to_bits().hash(state);
+        self.angle.to_bits().hash(state);
+        self.border_style.hash(state);
+        self.outline.to_bits().hash(state);
+        self.shadow.to_bits().hash(state);
+        self.alignment.hash(state);
+        self.margin_l.hash(state);
+        self.margin_r.hash(state);
+        self.margin_v.hash(state);
+        self.alpha_level.hash(state);
+        self.encoding.hash(state);
+    }
 }
@@ -0,0 +1,231 @@
+use core::fmt::{self, Display};
+use core::ops::{Add, AddAssign, Sub, SubAssign};
+use core::str::FromStr;
+
+use crate::{Error, ErrorKind};
+
+/// A point in time, or a duration, in centiseconds
+///
+/// Formats as `H:MM:SS.cc` the way `Start` and `End` are written. Negative
+/// values can come out of arithmetic, and get written as `0:00:00.00`.
+#[derive(Default, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Debug)]
+pub struct Timestamp(pub i64);
+
+/// A constant frame rate, as a fraction, eg. 24000/1001
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Fps {
+    num: u32,
+    den: u32,
+}
+
+impl Fps {
+    /// `num / den` frames per second, `None` if either is zero
+    pub const fn new(num: u32, den: u32) -> Option<Self> {
+        match num > 0 && den > 0 {
+            true  => Some(Self { num, den }),
+            false => None,
+        }
+    }
+
+    pub const fn num(self) -> u32 {
+        self.num
+    }
+
+    pub const fn den(self) -> u32 {
+        self.den
+    }
+}
+
+/// Division rounding towards negative infinity
+fn div_floor(a: i128, b: i128) -> i128 {
+    let d = a / b;
+    if (a % b != 0) && ((a < 0) != (b < 0)) { d - 1 } else { d }
+}
+
+/// `v` as an `i64`, saturating
+fn saturate(v: i128) -> i64 {
+    v.clamp(i64::MIN as i128, i64::MAX as i128) as i64
+}
+
+/// `v * num / den`, rounded to the closest integer and saturating, `None` if
+/// `den` is zero
+pub(crate) fn scale(v: i64, num: i64, den: i64) -> Option<i64> {
+    let (v, num, den) = (v as i128, num as i128, den as i128);
+    (den != 0).then(|| saturate(div_floor(2 * v * num + den, 2 * den)))
+}
+
+impl Timestamp {
+    pub const ZERO: Self = Self(0);
+
+    /// Saturating at the ends of the range of `i64`
+    pub const fn from_hms(h: i64, m: i64, s: i64, cs: i64) -> Self {
+        Self(h.saturating_mul(60).saturating_add(m)
+            .saturating_mul(60).saturating_add(s)
+            .saturating_mul(100).saturating_add(cs))
+    }
+
+    /// Closest timestamp to `ms` milliseconds, halves rounding up
+    pub fn from_ms(ms: i64) -> Self {
+        Self(saturate(div_floor(ms as i128 + 5, 10)))
+    }
+
+    pub const fn as_ms(self) -> i64 {
+        self.0.saturating_mul(10)
+    }
+
+    /// Parse `H:MM:SS.cc`
+    ///
+    /// Like libass, every field is a plain integer: fields may have any
+    /// number of digits, go over their usual range (`0:75:00.00` is
+    /// `1:15:00.00`), and the fraction counts centiseconds however long it
+    /// is. The fraction may also be missing.
+    pub fn parse(s: &str) -> Option<Self> {
+        let s = s.trim();
+        let (hms, cs) = match s.split_once('.') {
+            Some((hms, cs)) => (hms, cs.parse().ok()?),
+            None            => (s, 0),
+        };
+        let mut f = hms.splitn(3, ':').map(|f| f.trim().parse::<i64>().ok());
+        let (h, m, sec) = (f.next()??, f.next()??, f.next()??);
+        Some(Self::from_hms(h, m, sec, cs))
+    }
+
+    /// Multiply by `num / den`, rounding to the closest centisecond, `None`
+    /// if `den` is zero
+    pub fn scale(self, num: i64, den: i64) -> Option<Self> {
+        scale(self.0, num, den).map(Self)
+    }
+
+    /// Frame being shown at this time, at a constant frame rate
+    pub fn to_frame(self, fps: Fps) -> i64 {
+        saturate(div_floor(self.0 as i128 * fps.num as i128, 100 * fps.den as i128))
+    }
+
+    /// First timestamp at which `frame` is shown, at a constant frame rate
+    pub fn from_frame(frame: i64, fps: Fps) -> Self {
+        Self(saturate(-div_floor(-(frame as i128) * 100 * fps.den as i128, fps.num as i128)))
+    }
+
+    pub const fn hours(self) -> i64 {
+        self.0 / 360000
+    }
+
+    pub const fn minutes(self) -> i64 {
+        self.0 / 6000 % 60
+    }
+
+    pub const fn seconds(self) -> i64 {
+        self.0 / 100 % 60
+    }
+
+    pub const fn centis(self) -> i64 {
+        self.0 % 100
+    }
+}
+
+impl Display for Timestamp {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let t = Self(self.0.max(0));
+        write!(f, "{}:{:02}:{:02}.{:02}", t.hours(), t.minutes(), t.seconds(), t.centis())
+    }
+}
+
+impl FromStr for Timestamp {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Error> {
+        Self::parse(s).ok_or(ErrorKind::TimestampInvalid.into())
+    }
+}
+
+impl Add for Timestamp {
+    type Output = Self;
+
+    fn add(self, rhs: Self) -> Self {
+        Self(self.0 + rhs.0)
+    }
+}
+
+impl Sub for Timestamp {
+    type Output = Self;
+
+    fn sub(self, rhs: Self) -> Self {
+        Self(self.0 - rhs.0)
+    }
+}
+
+impl AddAssign for Timestamp {
+    fn add_assign(&mut self, rhs: Self) {
+        self.0 += rhs.0;
+    }
+}
+
+impl SubAssign for Timestamp {
+    fn sub_assign(&mut self, rhs: Self) {
+        self.0 -= rhs.0;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::string::ToString;
+
+    use super::*;
+
+    #[test]
+    fn parse() {
+        assert_eq!(Timestamp::parse("1:02:03.45"), Some(Timestamp::from_hms(1, 2, 3, 45)));
+        assert_eq!(Timestamp::parse("0:0:5.5"), Some(Timestamp(505)));
+        assert_eq!(Timestamp::parse("0:75:00.00"), Some(Timestamp::from_hms(1, 15, 0, 0)));
+        assert_eq!(Timestamp::parse(" 10:00:00 "), Some(Timestamp::from_hms(10, 0, 0, 0)));
+        assert_eq!(Timestamp::parse("0:00"), None);
+        assert_eq!(Timestamp::parse("a:00:00.00"), None);
+
+        assert_eq!(Timestamp::from_hms(1, 2, 3, 4).to_string(), "1:02:03.04");
+        assert_eq!(Timestamp(-5).to_string(), "0:00:00.00");
+    }
+
+    #[test]
+    fn conversions() {
+        assert_eq!(Timestamp::from_ms(1234), Timestamp(123));
+        assert_eq!(Timestamp::from_ms(1235), Timestamp(124));
+        assert_eq!(Timestamp::from_ms(-14), Timestamp(-1));
+        assert_eq!(Timestamp(123).as_ms(), 1230);
+
+        let ntsc = Fps::new(24000, 1001).unwrap();
+        assert_eq!(Timestamp::from_frame(24, ntsc), Timestamp(101));
+        assert_eq!(Timestamp(101).to_frame(ntsc), 24);
+        assert_eq!(Timestamp(100).to_frame(ntsc), 23);
+        for f in 0..1000 {
+            assert_eq!(Timestamp::from_frame(f, ntsc).to_frame(ntsc), f);
+        }
+
+        let a = Timestamp::from_hms(0, 0, 1, 50);
+        let b = Timestamp::from_hms(0, 0, 2, 0);
+        assert_eq!(a + b, Timestamp(350));
+        assert_eq!(a - b, Timestamp(-50));
+        assert!(a < b);
+
+        assert_eq!(Timestamp(1001).scale(24000, 25025), Some(Timestamp(960)));
+        assert_eq!(Timestamp(-3).scale(1, 2), Some(Timestamp(-1)));
+    }
+
+    #[test]
+    fn extremes() {
+        assert_eq!(Timestamp::from_hms(i64::MAX, 0, 0, 0), Timestamp(i64::MAX));
+        assert_eq!(Timestamp::from_hms(0, i64::MIN, 0, 0), Timestamp(i64::MIN));
+        assert_eq!(Timestamp::parse("99999999999999999:00:00.00"), Some(Timestamp(i64::MAX)));
+        assert_eq!(Timestamp::from_ms(i64::MAX), Timestamp(i64::MAX / 10 + 1));
+        assert_eq!(Timestamp(i64::MAX).as_ms(), i64::MAX);
+
+        assert_eq!(Timestamp(5).scale(1, 0), None);
+        assert_eq!(Timestamp(i64::MAX).scale(3, 1), Some(Timestamp(i64::MAX)));
+        assert_eq!(Timestamp(i64::MIN).scale(3, 1), Some(Timestamp(i64::MIN)));
+
+        assert_eq!(Fps::new(0, 1), None);
+        assert_eq!(Fps::new(25, 0), None);
+        let fps = Fps::new(u32::MAX, 1).unwrap();
+        assert_eq!(Timestamp(i64::MAX).to_frame(fps), i64::MAX);
+        assert_eq!(Timestamp::from_frame(i64::MAX, Fps::new(1, u32::MAX).unwrap()), Timestamp(i64::MAX));
+    }
+}
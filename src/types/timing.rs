@@ -0,0 +1,405 @@
+//! Timing events to video frames
+//!
+//! Renderers show an event on the frames whose time falls in `start..end`,
+//! so only the frames event boundaries land on matter. [`Timecodes`] tells
+//! when each frame of a video is shown, [`parse_keyframes`] where its scene
+//! changes are, and [`snap`] moves event boundaries onto them and closes
+//! small gaps between lines, like Aegisub's timing post-processor.
+
+use alloc::vec::Vec;
+
+use crate::{Error, ErrorKind, Event, Fps, Result, Timestamp};
+
+/// Rounded down, without `f64::floor` in `no_std`
+pub(crate) fn floor(x: f64) -> i64 {
+    let i = x as i64;
+    if (i as f64) > x { i - 1 } else { i }
+}
+
+/// Non-blank lines, with their number
+fn lines(s: &str) -> impl Iterator<Item = (usize, &str)> {
+    s.lines()
+        .enumerate()
+        .map(|(i, l)| (i + 1, l.trim()))
+        .filter(|(_, l)| !l.is_empty())
+}
+
+fn invalid(kind: ErrorKind, (n, l): (usize, &str)) -> Error {
+    Error::new(kind).locate(n, None, l)
+}
+
+/// When each frame of a video is shown
+#[derive(Clone, PartialEq, Debug)]
+pub struct Timecodes {
+    /// Time of each frame of a v2 file in milliseconds
+    times: Vec<f64>,
+
+    /// Frame rate of a v1 file from frame `0` on, as the first frame of each
+    /// range with its time in milliseconds and its rate
+    ranges: Vec<(i64, f64, f64)>,
+
+    /// Frame rate outside of `times` and `ranges`, as a fraction
+    num: f64,
+    den: f64,
+}
+
+impl Timecodes {
+    /// Constant frame rate
+    pub fn cfr(fps: Fps) -> Self {
+        Self {
+            times: Vec::new(),
+            ranges: Vec::new(),
+            num: fps.num() as f64,
+            den: fps.den() as f64,
+        }
+    }
+
+    /// Parse a v1 or v2 timecodes file, as written by mkvextract
+    ///
+    /// Frames past the end of a v2 file are given the average frame rate of
+    /// the file, those past the ranges of a v1 file its `Assume` rate.
+    pub fn parse(s: &str) -> Result<Self> {
+        let mut lines = lines(s);
+        let header = lines.next().ok_or(ErrorKind::TimecodesInvalid)?;
+        let lines = lines.filter(|(_, l)| !l.starts_with('#'));
+
+        match header.1.strip_prefix('#').map(str::trim) {
+            Some("timecode format v1" | "timestamp format v1") => Self::v1(lines),
+            Some("timecode format v2" | "timestamp format v2") => Self::v2(lines),
+            _ => Err(invalid(ErrorKind::TimecodesInvalid, header)),
+        }
+    }
+
+    fn v1<'a>(mut lines: impl Iterator<Item = (usize, &'a str)>) -> Result<Self> {
+        let fps = |s: &str| s.trim().parse().ok().filter(|&f: &f64| f > 0.0);
+
+        let l = lines.next().ok_or(ErrorKind::TimecodesInvalid)?;
+        let assume = l.1.get(..6)
+            .filter(|k| k.eq_ignore_ascii_case("assume"))
+            .and_then(|_| fps(&l.1[6..]))
+            .ok_or_else(|| invalid(ErrorKind::TimecodesInvalid, l))?;
+
+        // `start,end,fps`, in order and without overlaps, with the gaps
+        // between them at the `Assume` rate
+        let mut ranges = Vec::new();
+        // First frame after the ranges so far, and its time
+        let (mut next, mut t) = (0i64, 0.0);
+        for l in lines {
+            let mut f = l.1.splitn(3, ',');
+            let range = (|| {
+                let start = f.next()?.trim().parse().ok()?;
+                let end: i64 = f.next()?.trim().parse().ok()?;
+                // The frame after it has to have a number too
+                end.checked_add(1)?;
+                (start >= next && end >= start).then_some((start, end, fps(f.next()?)?))
+            })();
+            let (start, end, fps) = range.ok_or_else(|| invalid(ErrorKind::TimecodesInvalid, l))?;
+            if start > next {
+                ranges.push((next, t, assume));
+                t += (start - next) as f64 * 1000.0 / assume;
+            }
+            ranges.push((start, t, fps));
+            t += (end - start + 1) as f64 * 1000.0 / fps;
+            next = end + 1;
+        }
+        ranges.push((next, t, assume));
+
+        Ok(Self { times: Vec::new(), ranges, num: assume, den: 1.0 })
+    }
+
+    fn v2<'a>(lines: impl Iterator<Item = (usize, &'a str)>) -> Result<Self> {
+        let mut times: Vec<f64> = Vec::new();
+        for l in lines {
+            let t = l.1.parse().ok()
+                .filter(|&t| times.last().is_none_or(|&last| t >= last))
+                .ok_or_else(|| invalid(ErrorKind::TimecodesInvalid, l))?;
+            times.push(t);
+        }
+
+        match (times.first(), times.last()) {
+            (Some(&first), Some(&last)) if last > first => Ok(Self {
+                num: (times.len() - 1) as f64 * 1000.0,
+                den: last - first,
+                times,
+                ranges: Vec::new(),
+            }),
+            _ => Err(ErrorKind::TimecodesInvalid.into()),
+        }
+    }
+
+    /// Time at which `frame` is shown, in milliseconds
+    pub fn time(&self, frame: i64) -> f64 {
+        if frame >= 0 && !self.ranges.is_empty() {
+            let i = self.ranges.partition_point(|r| r.0 <= frame) - 1;
+            let (first, t, fps) = self.ranges[i];
+            return t + (frame - first) as f64 * 1000.0 / fps;
+        }
+        let len = self.times.len() as i64;
+        let (base, n) = match (self.times.first(), self.times.last()) {
+            _ if (0..len).contains(&frame) => return self.times[frame as usize],
+            (Some(&first), _) if frame < 0 => (first, frame),
+            (_, Some(&last))               => (last, frame - len + 1),
+            _                              => (0.0, frame),
+        };
+        base + (n as f64 * 1000.0 * self.den) / self.num
+    }
+
+    /// Frame being shown at `t`
+    pub fn frame(&self, t: Timestamp) -> i64 {
+        let t = t.as_ms() as f64;
+        if t >= 0.0 && !self.ranges.is_empty() {
+            let i = self.ranges.partition_point(|r| r.1 <= t) - 1;
+            let (first, start, fps) = self.ranges[i];
+            let n = first.saturating_add(floor((t - start) * fps / 1000.0));
+            // Rounding doesn't get it into the next range
+            return self.ranges.get(i + 1).map_or(n, |r| n.min(r.0 - 1));
+        }
+        let len = self.times.len() as i64;
+        let (base, n) = match (self.times.first(), self.times.last()) {
+            (Some(&first), Some(&last)) if first <= t && t <= last =>
+                return self.times.partition_point(|&x| x <= t) as i64 - 1,
+            (Some(&first), _) if t < first => (first, 0),
+            (_, Some(&last))               => (last, len - 1),
+            _                              => (0.0, 0),
+        };
+        n + floor((t - base) * self.num / (1000.0 * self.den))
+    }
+
+    /// First frame shown from `t` on
+    fn first_from(&self, t: Timestamp) -> i64 {
+        let f = self.frame(t);
+        if self.time(f) < t.as_ms() as f64 { f + 1 } else { f }
+    }
+
+    /// Time to start an event at for `frame` to be its first one, or to end
+    /// it at for `frame` to be the first one it isn't shown on
+    ///
+    /// This is halfway between the frame and the one before, so that the
+    /// rounding to centiseconds doesn't move the event to another frame.
+    pub fn boundary(&self, frame: i64) -> Timestamp {
+        let mid = (self.time(frame - 1) + self.time(frame)) / 2.0;
+        Timestamp::from_ms(floor(mid + 0.5)).max(Timestamp::ZERO)
+    }
+}
+
+/// Parse a keyframes file into a sorted list of frame numbers
+///
+/// Reads Aegisub keyframe files, XviD and SCXviD first pass logs, and plain
+/// lists of frame numbers, one per line.
+pub fn parse_keyframes(s: &str) -> Result<Vec<i64>> {
+    let mut lines = lines(s).peekable();
+    let mut frames = Vec::new();
+
+    match lines.peek() {
+        // Frame types, one per line, `i` being keyframes
+        Some((_, h)) if h.starts_with("# XviD 2pass stat file") => {
+            let mut frame = 0;
+            for (_, l) in lines {
+                match l.as_bytes()[0].to_ascii_lowercase() {
+                    b'i'        => frames.push(frame),
+                    b'p' | b'b' => {},
+                    _           => continue,
+                }
+                frame += 1;
+            }
+        },
+        header => {
+            if header.is_some_and(|(_, h)| h.starts_with("# keyframe format v1")) {
+                lines.next();
+                lines.next_if(|(_, l)| l.starts_with("fps "));
+            }
+            for l in lines.filter(|(_, l)| !l.starts_with('#')) {
+                let frame = l.1.parse().ok().filter(|&f: &i64| f >= 0);
+                frames.push(frame.ok_or_else(|| invalid(ErrorKind::KeyframesInvalid, l))?);
+            }
+        },
+    }
+
+    frames.sort_unstable();
+    frames.dedup();
+    Ok(frames)
+}
+
+/// What [`snap`] does
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Snap {
+    /// Join lines whose gap or overlap is at most this long
+    pub link: Option<Timestamp>,
+
+    /// Where joined lines meet, from `0.0` (the end of the first one) to
+    /// `1.0` (the start of the second one)
+    pub link_bias: f32,
+
+    /// Frames a line can start before a keyframe and still get moved to it
+    pub starts_before: i64,
+
+    /// Frames a line can start after a keyframe and still get moved to it
+    pub starts_after: i64,
+
+    /// Frames a line can end before a keyframe and still get moved to it
+    pub ends_before: i64,
+
+    /// Frames a line can end after a keyframe and still get moved to it
+    pub ends_after: i64,
+}
+
+impl Default for Snap {
+    fn default() -> Self {
+        Self {
+            link: Some(Timestamp(30)),
+            link_bias: 0.5,
+            starts_before: 5,
+            starts_after: 4,
+            ends_before: 5,
+            ends_after: 5,
+        }
+    }
+}
+
+/// Link `events` to each other, then snap them to `keyframes`
+///
+/// Linking goes through the events in start order and joins each one with
+/// the next one if they are close enough. Snapping then moves the first
+/// and last frame of every event to the closest scene change within the
+/// thresholds, as long as that leaves it at least a frame long.
+pub fn snap<'a>(
+    events: impl IntoIterator<Item = &'a mut Event>,
+    opts: &Snap,
+    keyframes: &[i64],
+    tc: &Timecodes,
+) {
+    let mut events: Vec<_> = events.into_iter().collect();
+    events.sort_by_key(|e| e.start);
+
+    if let Some(link) = opts.link {
+        for i in 1..events.len() {
+            let (a, b) = events.split_at_mut(i);
+            let (prev, cur) = (&mut a[i - 1], &mut b[0]);
+
+            let dist = cur.start - prev.end;
+            // Neither starts nor ends before the other
+            let in_order = prev.start < cur.start && prev.end < cur.end;
+            if dist != Timestamp::ZERO && dist.0.abs() <= link.0 && in_order {
+                let at = prev.end + Timestamp(floor(dist.0 as f64 * opts.link_bias as f64 + 0.5));
+                prev.end = at;
+                cur.start = at;
+            }
+        }
+    }
+
+    let closest = |f: i64| {
+        let i = keyframes.partition_point(|&k| k < f);
+        let after = keyframes.get(i).copied();
+        let before = i.checked_sub(1).map(|i| keyframes[i]);
+        match (before, after) {
+            (Some(b), Some(a)) => Some(if f - b <= a - f { b } else { a }),
+            (b, a) => b.or(a),
+        }
+    };
+    let snapped = |f: i64, before: i64, after: i64| match closest(f) {
+        Some(k) if (k > f && k - f <= before) || (k < f && f - k <= after) => k,
+        _ => f,
+    };
+
+    for e in events {
+        // First frame shown, and first one not shown
+        let (start, end) = (tc.first_from(e.start), tc.first_from(e.end));
+        let new_start = snapped(start, opts.starts_before, opts.starts_after);
+        let new_end = snapped(end, opts.ends_before, opts.ends_after);
+        if new_start >= new_end {
+            continue;
+        }
+        if new_start != start {
+            e.start = tc.boundary(new_start);
+        }
+        if new_end != end {
+            e.end = tc.boundary(new_end);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::format;
+
+    use super::*;
+
+    fn event(start: i64, end: i64) -> Event {
+        Event {
+            start: Timestamp(start),
+            end: Timestamp(end),
+            ..Event::default()
+        }
+    }
+
+    #[test]
+    fn timecodes() {
+        let ntsc = Timecodes::cfr(Fps::new(24000, 1001).unwrap());
+        assert_eq!(ntsc.time(24), 1001.0);
+        assert_eq!(ntsc.frame(Timestamp(100)), 23);
+        assert_eq!(ntsc.frame(Timestamp(101)), 24);
+        assert_eq!(ntsc.boundary(24), Timestamp(98));
+        for f in 0..1000 {
+            assert_eq!(ntsc.first_from(ntsc.boundary(f)), f);
+        }
+
+        let v1 = Timecodes::parse("# timecode format v1\nAssume 25\n# slow part\n2,3,10\n").unwrap();
+        let times: Vec<_> = (-1..6).map(|f| v1.time(f)).collect();
+        assert_eq!(times, [-40.0, 0.0, 40.0, 80.0, 180.0, 280.0, 320.0]);
+        let frames = [0, 3, 4, 8, 17, 18, 28, 31, 32].map(|t| v1.frame(Timestamp(t)));
+        assert_eq!(frames, [0, 0, 1, 2, 2, 3, 4, 4, 5]);
+
+        // Ranges are kept as they are, not as a time for each frame
+        let long = Timecodes::parse("# timecode format v1\nAssume 25\n0,99999999999,24\n").unwrap();
+        assert_eq!(long.time(100000000025) - long.time(100000000000), 1000.0);
+        assert_eq!(long.frame(Timestamp(100 * 3600)), 24 * 3600);
+        let max = format!("# timecode format v1\nAssume 25\n0,{},24\n", i64::MAX);
+        assert!(Timecodes::parse(&max).is_err());
+
+        let v2 = Timecodes::parse("# timestamp format v2\n0\n40\n100.5\n120\n").unwrap();
+        assert_eq!(v2.time(2), 100.5);
+        assert_eq!(v2.time(4), 160.0);
+        assert_eq!(v2.time(-1), -40.0);
+        assert_eq!(v2.frame(Timestamp(10)), 1);
+        assert_eq!(v2.frame(Timestamp(11)), 2);
+        assert_eq!(v2.frame(Timestamp(16)), 4);
+
+        let e = Timecodes::parse("# timecode format v2\n0\n40\n20\n").unwrap_err();
+        assert_eq!((e.kind, e.line), (ErrorKind::TimecodesInvalid, Some(4)));
+        assert!(Timecodes::parse("# timecode format v1\n2,3,10\n").is_err());
+        assert!(Timecodes::parse("# timecode format v1\nAssume 25\n3,2,10\n").is_err());
+        assert!(Timecodes::parse("0\n40\n").is_err());
+    }
+
+    #[test]
+    fn keyframes() {
+        let aegisub = "# keyframe format v1\nfps 0\n0\n120\n48\n";
+        assert_eq!(parse_keyframes(aegisub).unwrap(), [0, 48, 120]);
+
+        let xvid = "# XviD 2pass stat file\n# frames\ni 1 2 3\np 1 2 3\nb 1 2 3\n\nI 1 2 3\np 1 2 3\n";
+        assert_eq!(parse_keyframes(xvid).unwrap(), [0, 3]);
+
+        assert_eq!(parse_keyframes("10\n\n5\n").unwrap(), [5, 10]);
+        let e = parse_keyframes("10\nx\n").unwrap_err();
+        assert_eq!((e.kind, e.line), (ErrorKind::KeyframesInvalid, Some(2)));
+    }
+
+    #[test]
+    fn snapping() {
+        let tc = Timecodes::cfr(Fps::new(25, 1).unwrap());
+        let opts = Snap::default();
+
+        // Starts 3 frames before a keyframe, ends 2 frames after one
+        let mut events = [event(88, 208), event(400, 500), event(508, 600)];
+        snap(&mut events, &opts, &[25, 50], &tc);
+        assert_eq!((events[0].start, events[0].end), (Timestamp(98), Timestamp(198)));
+
+        // Linked halfway, nothing to snap to
+        assert_eq!((events[1].end, events[2].start), (Timestamp(504), Timestamp(504)));
+
+        // Would end up empty
+        let mut events = [event(100, 108)];
+        snap(&mut events, &Snap { link: None, ..opts }, &[27], &tc);
+        assert_eq!((events[0].start, events[0].end), (Timestamp(100), Timestamp(108)));
+    }
+}
@@ -0,0 +1,54 @@
+use core::fmt::{self, Display};
+
+use alloc::vec::Vec;
+
+use crate::OverrideCode;
+
+/// A piece of the `Text` field of an event
+#[derive(Clone, PartialEq, Debug)]
+pub enum Token<'a> {
+    /// `{\...}`
+    ///
+    /// A block of override codes.
+    OverrideBlock(Vec<OverrideCode>),
+    /// `{...}`
+    ///
+    /// A block without any override codes, which renderers ignore. Text
+    /// before the first tag of a block is one too, coming before the
+    /// [`OverrideBlock`](Self::OverrideBlock) of its tags.
+    Comment(&'a str),
+    /// Text to be displayed, or drawing commands after `\p1`
+    Text(&'a str),
+    /// `\N`
+    ///
+    /// Always breaks the line.
+    HardBreak,
+    /// `\n`
+    ///
+    /// Breaks the line only when the wrapping style is `2`, otherwise a space.
+    SoftBreak,
+    /// `\h`
+    ///
+    /// Non-breaking space.
+    HardSpace,
+}
+
+impl Display for Token<'_> {
+    /// Format as it would appear in the `Text` field
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Token::OverrideBlock(codes) => {
+                write!(f, "{{")?;
+                for c in codes {
+                    write!(f, "{}", c)?;
+                }
+                write!(f, "}}")
+            },
+            Token::Comment(s) => write!(f, "{{{}}}", s),
+            Token::Text(s)    => write!(f, "{}", s),
+            Token::HardBreak  => write!(f, "\\N"),
+            Token::SoftBreak  => write!(f, "\\n"),
+            Token::HardSpace  => write!(f, "\\h"),
+        }
+    }
+}
@@ -0,0 +1,97 @@
+//! The uuencoding variant SSA uses for `[Fonts]` and `[Graphics]`
+//!
+//! Every 3 bytes become 4 characters, each holding 6 bits plus 33. A final
+//! group of 1 or 2 bytes only gets the 2 or 3 characters it needs, and the
+//! characters are split into lines of [`LINE_LEN`].
+
+use alloc::{string::String, vec::Vec};
+
+use crate::{ErrorKind, Result};
+
+/// Length of every line of encoded data but the last
+pub const LINE_LEN: usize = 80;
+
+/// Encode `data`, one entry per line
+pub fn uuencode(data: &[u8]) -> Vec<String> {
+    let mut lines = Vec::new();
+    let mut line = String::with_capacity(LINE_LEN);
+
+    for group in data.chunks(3) {
+        let n = group.iter()
+            .chain(&[0, 0])
+            .take(3)
+            .fold(0u32, |n, &b| n << 8 | b as u32);
+
+        for i in 0..=group.len() {
+            line.push((((n >> (18 - 6 * i)) & 0x3f) as u8 + 33) as char);
+            if line.len() == LINE_LEN {
+                lines.push(core::mem::replace(&mut line, String::with_capacity(LINE_LEN)));
+            }
+        }
+    }
+    if !line.is_empty() {
+        lines.push(line);
+    }
+
+    lines
+}
+
+/// Decode lines of data, eg. [`Attachment::lines`](crate::Attachment::lines)
+///
+/// The lines don't need to be [`LINE_LEN`] long, groups can span several of them.
+pub fn uudecode<'a>(lines: impl IntoIterator<Item = &'a str>) -> Result<Vec<u8>> {
+    let mut data = Vec::new();
+    // Bits of the current group, and how many characters it has so far
+    let mut n = 0u32;
+    let mut len = 0;
+
+    for c in lines.into_iter().flat_map(|l| l.trim_ascii().bytes()) {
+        if !(33..=96).contains(&c) {
+            return Err(ErrorKind::UuInvalid.into());
+        }
+        n = n << 6 | (c - 33) as u32;
+        len += 1;
+        if len == 4 {
+            data.extend_from_slice(&n.to_be_bytes()[1..]);
+            n = 0;
+            len = 0;
+        }
+    }
+
+    match len {
+        0 => {},
+        // A lone character doesn't even make a byte
+        1 => return Err(ErrorKind::UuInvalid.into()),
+        _ => {
+            let n = n << (6 * (4 - len));
+            data.extend_from_slice(&n.to_be_bytes()[1..len]);
+        },
+    }
+
+    Ok(data)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn known() {
+        assert_eq!(uuencode(b"abc"), ["97*D"]);
+        assert_eq!(uuencode(b"abcd"), ["97*D:!"]);
+        assert_eq!(uudecode(["97*D:", "!"]).ok().as_deref(), Some(&b"abcd"[..]));
+        assert!(uudecode(["97*D:"]).is_err());
+        assert!(uudecode(["97 D"]).is_err());
+    }
+
+    #[test]
+    fn round_trip() {
+        let data: Vec<u8> = (0..=255).chain(0..=255u8).collect();
+        for len in [0, 1, 2, 3, 59, 60, 61, data.len()] {
+            let lines = uuencode(&data[..len]);
+            assert!(lines.iter().all(|l| l.len() <= LINE_LEN));
+            assert!(lines.iter().rev().skip(1).all(|l| l.len() == LINE_LEN));
+            assert_eq!(uudecode(lines.iter().map(String::as_str)).ok().as_deref(), Some(&data[..len]));
+        }
+    }
+}
@@ -1,14 +1,628 @@
-pub fn add(left: usize, right: usize) -> usize {
-    left + right
+//! # `backside_writer`
+//! Writer implementation for `backside`
+
+#![no_std]
+
+extern crate alloc;
+
+use alloc::{string::String, vec::Vec};
+use core::fmt::{self, Write};
+use core::ops::Range;
+
+use backside_types::*;
+
+/// Writes lines, putting the line ending between them
+struct Lines<'w, W: Write> {
+    w: &'w mut W,
+    crlf: bool,
+    /// Lines that end the other way, see [`Layout::mixed`]
+    mixed: &'w [usize],
+    /// Lines written so far
+    n: usize,
+    empty: bool,
+}
+
+impl<W: Write> Lines<'_, W> {
+    fn line(&mut self, args: fmt::Arguments) -> fmt::Result {
+        if !self.empty {
+            self.end()?;
+        }
+        self.empty = false;
+        self.n += 1;
+        self.w.write_fmt(args)
+    }
+
+    /// End the last line written
+    fn end(&mut self) -> fmt::Result {
+        let crlf = self.crlf != self.mixed.binary_search(&(self.n - 1)).is_ok();
+        self.w.write_str(if crlf { "\r\n" } else { "\n" })
+    }
+}
+
+/// Whether `script` is SSA rather than ASS, going by its `ScriptType`
+///
+/// Without one, a parsed script keeps the format of its styles section, and
+/// anything else is ASS.
+pub fn is_ssa(script: &Script) -> bool {
+    match script.info.script_type() {
+        Some(t) => t == ScriptType::V4,
+        None    => script.layout.sections.iter().any(|s| s.section == Section::V4Styles),
+    }
+}
+
+/// Write a whole script
+///
+/// Lines that were parsed and not modified since are written exactly as
+/// they were read, so parsing and writing an untouched file gives the same
+/// bytes back. Everything else is written in the canonical form, as SSA or
+/// ASS depending on [`is_ssa`]; the layout only decides where things go.
+///
+/// Sections that come up more than once each get the items that were in
+/// them, items added since going to the last one of their kind.
+pub fn write_script<W: Write>(w: &mut W, script: &Script) -> fmt::Result {
+    let layout = &script.layout;
+    let ssa = is_ssa(script);
+    let (styles, styles_name) = match ssa {
+        true  => (Section::V4Styles, "V4 Styles"),
+        false => (Section::V4PlusStyles, "V4+ Styles"),
+    };
+    // Either styles section stands for the one of the script's format
+    let kind = |s: Section| match s {
+        Section::V4PlusStyles | Section::V4Styles => styles,
+        s => s,
+    };
+
+    if layout.bom {
+        w.write_char('\u{feff}')?;
+    }
+
+    let mut out = Lines {
+        w,
+        crlf: layout.crlf,
+        mixed: &layout.mixed,
+        n: 0,
+        empty: true,
+    };
+
+    for l in &layout.preamble {
+        out.line(format_args!("{}", l))?;
+    }
+
+    // Kinds of sections written so far, and how many of their items
+    let mut done: Vec<(Section, usize)> = Vec::new();
+    let mut extra = script.extra.iter();
+
+    for (i, sec) in layout.sections.iter().enumerate() {
+        let k = kind(sec.section);
+        match k == sec.section {
+            true  => out.line(format_args!("[{}]{}", sec.name, sec.rest))?,
+            false => out.line(format_args!("[{}]", styles_name))?,
+        }
+
+        let len = script.item_count(k);
+        let last = layout.sections[i + 1..].iter().all(|s| kind(s.section) != k);
+        let start = match done.iter_mut().find(|(s, _)| *s == k) {
+            Some((_, n)) => n,
+            None => {
+                done.push((k, 0));
+                &mut done.last_mut().unwrap().1
+            },
+        };
+        let end = if last { len } else { sec.items.end.clamp(*start, len) };
+        let items = *start..end;
+        *start = end;
+
+        match k {
+            Section::ScriptInfo => write_info(&mut out, script, &sec.filler, items)?,
+            Section::V4PlusStyles | Section::V4Styles =>
+                write_styles(&mut out, script, ssa, &sec.format, &sec.filler, items)?,
+            Section::Fonts =>
+                write_attachments(&mut out, &script.fonts, "fontname", &sec.filler, items)?,
+            Section::Graphics =>
+                write_attachments(&mut out, &script.graphics, "filename", &sec.filler, items)?,
+            Section::Events =>
+                write_events(&mut out, script, ssa, &sec.format, &sec.filler, items)?,
+            Section::None => if let Some(e) = extra.next() {
+                for l in &e.lines {
+                    out.line(format_args!("{}", l))?;
+                }
+            },
+        }
+    }
+
+    // Sections the layout doesn't know about, in the canonical order. The
+    // main ones always get written for scripts built from scratch, but a
+    // parsed script only gets the ones it has items for.
+    let fresh = layout.sections.is_empty();
+    for (section, name, needed) in [
+        (Section::ScriptInfo, "Script Info", fresh || !script.info.entries.is_empty()),
+        (styles, styles_name, fresh || !script.styles.is_empty()),
+        (Section::Fonts, "Fonts", !script.fonts.is_empty()),
+        (Section::Graphics, "Graphics", !script.graphics.is_empty()),
+        (Section::Events, "Events", fresh || !script.events.is_empty()),
+    ] {
+        if !needed || done.iter().any(|(s, _)| *s == section) {
+            continue;
+        }
+        if !out.empty {
+            out.line(format_args!(""))?;
+        }
+        out.line(format_args!("[{}]", name))?;
+
+        // An empty `Format:` never matches, so the canonical one gets written
+        let filler = [(0, Filler::Format(String::new()))];
+        let items = 0..script.item_count(section);
+        match section {
+            Section::ScriptInfo => write_info(&mut out, script, &[], items)?,
+            Section::Fonts      => write_attachments(&mut out, &script.fonts, "fontname", &[], items)?,
+            Section::Graphics   => write_attachments(&mut out, &script.graphics, "filename", &[], items)?,
+            Section::Events     => write_events(&mut out, script, ssa, &script.event_format, &filler, items)?,
+            _                   => write_styles(&mut out, script, ssa, &script.style_format, &filler, items)?,
+        }
+    }
+    for e in extra {
+        if !out.empty {
+            out.line(format_args!(""))?;
+        }
+        out.line(format_args!("[{}]", e.name))?;
+        for l in &e.lines {
+            out.line(format_args!("{}", l))?;
+        }
+    }
+
+    if !layout.missing_final_newline && !out.empty {
+        out.end()?;
+    }
+
+    Ok(())
+}
+
+/// Write a whole script to a `String`
+pub fn to_string(script: &Script) -> String {
+    let mut s = String::new();
+    // Writing to a `String` can't fail
+    let _ = write_script(&mut s, script);
+    s
+}
+
+/// Write `items`, with the filler lines in between
+fn write_items<W: Write>(
+    out: &mut Lines<W>,
+    filler: &[(usize, Filler)],
+    items: Range<usize>,
+    format: &[&str],
+    mut item: impl FnMut(&mut Lines<W>, usize) -> fmt::Result,
+) -> fmt::Result {
+    let mut filler = filler.iter().peekable();
+    for i in items.start..=items.end {
+        while let Some((_, f)) = filler.next_if(|(a, _)| *a <= i) {
+            match f {
+                Filler::Raw(l) => out.line(format_args!("{}", l))?,
+                Filler::Format(l) => {
+                    let same = l.split_once(':').is_some_and(|(_, v)| {
+                        v.split(',').map(str::trim).eq(format.iter().copied())
+                    });
+                    if same {
+                        out.line(format_args!("{}", l))?;
+                    } else {
+                        out.line(format_args!("Format: "))?;
+                        for (j, f) in format.iter().enumerate() {
+                            if j > 0 {
+                                out.w.write_str(", ")?;
+                            }
+                            out.w.write_str(f)?;
+                        }
+                    }
+                },
+            }
+        }
+        if i < items.end {
+            item(out, i)?;
+        }
+    }
+    Ok(())
+}
+
+/// The `Format:` columns of a section, or the default ones, and whether the
+/// lines read with them still fit
+///
+/// The defaults also stand in for columns of the other format, `other`, so
+/// a script whose `ScriptType` changed doesn't get written as a mix of both.
+fn columns<'a>(format: &'a [String], default: &'a [&[u8]], other: &[&[u8]]) -> (Vec<&'a str>, bool) {
+    let has = |list: &[&[u8]], k: &str| list.iter().any(|f| f.eq_ignore_ascii_case(k.as_bytes()));
+    let foreign = format.iter().any(|k| has(other, k) && !has(default, k));
+    match format.is_empty() || foreign {
+        false => (format.iter().map(String::as_str).collect(), true),
+        // The defaults are all ASCII
+        true  => (default.iter().map(|f| core::str::from_utf8(f).unwrap_or_default()).collect(), false),
+    }
+}
+
+fn write_info<W: Write>(
+    out: &mut Lines<W>,
+    script: &Script,
+    filler: &[(usize, Filler)],
+    items: Range<usize>,
+) -> fmt::Result {
+    write_items(out, filler, items, &[], |out, i| {
+        let item = &script.info.entries[i];
+        match script.layout.info.get(i).filter(|s| s.matches(item)) {
+            Some(s) => out.line(format_args!("{}", s.line)),
+            None    => match item {
+                InfoEntry::Field(k, v) => out.line(format_args!("{}: {}", k, v)),
+                InfoEntry::Comment(c)  => out.line(format_args!(";{}", c)),
+            },
+        }
+    })
+}
+
+fn write_styles<W: Write>(
+    out: &mut Lines<W>,
+    script: &Script,
+    ssa: bool,
+    own: &[String],
+    filler: &[(usize, Filler)],
+    items: Range<usize>,
+) -> fmt::Result {
+    let (default, other) = match ssa {
+        true  => (SSA_STYLE_FORMAT, ASS_STYLE_FORMAT),
+        false => (ASS_STYLE_FORMAT, SSA_STYLE_FORMAT),
+    };
+    // Lines as read only fit the columns they were read with
+    let (format, keep) = columns(own, default, other);
+
+    write_items(out, filler, items, &format, |out, i| {
+        let item = &script.styles[i];
+        match script.layout.styles.get(i).filter(|s| keep && s.matches(item)) {
+            Some(s) => out.line(format_args!("{}", s.line)),
+            None    => {
+                out.line(format_args!("Style: "))?;
+                write_style(out.w, item, &format, ssa)
+            },
+        }
+    })
+}
+
+/// `[Fonts]` / `[Graphics]`, `key` being what comes before the names
+fn write_attachments<W: Write>(
+    out: &mut Lines<W>,
+    list: &[Attachment],
+    key: &str,
+    filler: &[(usize, Filler)],
+    items: Range<usize>,
+) -> fmt::Result {
+    write_items(out, filler, items, &[], |out, i| {
+        let a = &list[i];
+        out.line(format_args!("{}: {}", key, a.name))?;
+        for l in &a.lines {
+            out.line(format_args!("{}", l))?;
+        }
+        Ok(())
+    })
+}
+
+fn write_events<W: Write>(
+    out: &mut Lines<W>,
+    script: &Script,
+    ssa: bool,
+    own: &[String],
+    filler: &[(usize, Filler)],
+    items: Range<usize>,
+) -> fmt::Result {
+    let (default, other) = match ssa {
+        true  => (SSA_EVENT_FORMAT, ASS_EVENT_FORMAT),
+        false => (ASS_EVENT_FORMAT, SSA_EVENT_FORMAT),
+    };
+    let (format, keep) = columns(own, default, other);
+
+    write_items(out, filler, items, &format, |out, i| {
+        let item = &script.events[i];
+        match script.layout.events.get(i).filter(|s| keep && s.matches(item)) {
+            Some(s) => out.line(format_args!("{}", s.line)),
+            None    => {
+                out.line(format_args!("{}: ", item.kind.as_str()))?;
+                write_event(out.w, item, &format, ssa)
+            },
+        }
+    })
+}
+
+/// Write the fields of a `Style:` line, in the order of `format`
+///
+/// Colours are written as `&HAABBGGRR`, or as decimal integers for SSA.
+pub fn write_style<W: Write>(w: &mut W, style: &Style, format: &[&str], ssa: bool) -> fmt::Result {
+    let color = |w: &mut W, c: Colour| match ssa {
+        true  => write!(w, "{}", c.0),
+        false => write!(w, "{}", c),
+    };
+    let flag = |b: bool| if b { -1 } else { 0 };
+
+    for (i, k) in format.iter().enumerate() {
+        if i > 0 {
+            w.write_char(',')?;
+        }
+        let is = |name: &str| k.eq_ignore_ascii_case(name);
+
+        if is("Name") { write!(w, "{}", style.name)?; }
+        else if is("Fontname") { write!(w, "{}", style.font_name)?; }
+        else if is("Fontsize") { write!(w, "{}", style.font_size)?; }
+        else if is("PrimaryColour") { color(w, style.primary_color)?; }
+        else if is("SecondaryColour") { color(w, style.secondary_color)?; }
+        else if is("OutlineColour") || is("TertiaryColour") { color(w, style.outline_color)?; }
+        else if is("BackColour") { color(w, style.back_color)?; }
+        else if is("Bold") { write!(w, "{}", flag(style.bold))?; }
+        else if is("Italic") { write!(w, "{}", flag(style.italic))?; }
+        else if is("Underline") { write!(w, "{}", flag(style.underline))?; }
+        else if is("StrikeOut") { write!(w, "{}", flag(style.strikeout))?; }
+        else if is("ScaleX") { write!(w, "{}", style.scale_x)?; }
+        else if is("ScaleY") { write!(w, "{}", style.scale_y)?; }
+        else if is("Spacing") { write!(w, "{}", style.spacing)?; }
+        else if is("Angle") { write!(w, "{}", style.angle)?; }
+        else if is("BorderStyle") { write!(w, "{}", style.border_style)?; }
+        else if is("Outline") { write!(w, "{}", style.outline)?; }
+        else if is("Shadow") { write!(w, "{}", style.shadow)?; }
+        else if is("Alignment") { write!(w, "{}", style.alignment)?; }
+        else if is("MarginL") { write!(w, "{}", style.margin_l)?; }
+        else if is("MarginR") { write!(w, "{}", style.margin_r)?; }
+        else if is("MarginV") { write!(w, "{}", style.margin_v)?; }
+        else if is("AlphaLevel") { write!(w, "{}", style.alpha_level)?; }
+        else if is("Encoding") { write!(w, "{}", style.encoding)?; }
+    }
+
+    Ok(())
+}
+
+/// Write the fields of an event line, in the order of `format`
+///
+/// Margins are written with 4 digits for SSA.
+pub fn write_event<W: Write>(w: &mut W, event: &Event, format: &[&str], ssa: bool) -> fmt::Result {
+    let margin = |w: &mut W, m: i32| match ssa {
+        true  => write!(w, "{:04}", m),
+        false => write!(w, "{}", m),
+    };
+
+    for (i, k) in format.iter().enumerate() {
+        if i > 0 {
+            w.write_char(',')?;
+        }
+        let is = |name: &str| k.eq_ignore_ascii_case(name);
+
+        if is("Layer") { write!(w, "{}", event.layer)?; }
+        else if is("Marked") { write!(w, "Marked={}", event.marked as u8)?; }
+        else if is("Start") { write!(w, "{}", event.start)?; }
+        else if is("End") { write!(w, "{}", event.end)?; }
+        else if is("Style") { write!(w, "{}", event.style)?; }
+        else if is("Name") || is("Actor") { write!(w, "{}", event.name)?; }
+        else if is("MarginL") { margin(w, event.margin_l)?; }
+        else if is("MarginR") { margin(w, event.margin_r)?; }
+        else if is("MarginV") { margin(w, event.margin_v)?; }
+        else if is("Effect") { write!(w, "{}", event.effect)?; }
+        else if is("Text") { write!(w, "{}", event.text)?; }
+    }
+
+    Ok(())
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    const SCRIPT: &str = "[Script Info]\r
+; Script generated by Aegisub\r
+Title: Test\r
+ScriptType: v4.00+\r
+PlayResX:1920\r
+\r
+[V4+ Styles]\r
+Format: Name, Fontname, Fontsize, PrimaryColour, SecondaryColour, OutlineColour, BackColour, Bold, Italic, Underline, StrikeOut, ScaleX, ScaleY, Spacing, Angle, BorderStyle, Outline, Shadow, Alignment, MarginL, MarginR, MarginV, Encoding\r
+Style: Default,Arial,48.00,&H00ffffff,&H000000FF,&H00000000,&H00000000,0,0,0,0,100,100,0,0,1,2,2,2,0010,10,10,1\r
+\r
+[Aegisub Project Garbage]\r
+Video File: test.mkv\r
+\r
+[Events]\r
+Format: Layer, Start, End, Style, Name, MarginL, MarginR, MarginV, Effect, Text\r
+Dialogue: 0,0:00:01.00,0:00:03.50,Default,,0,0,0,,Hello, {\\i1}world{\\i0}!\r
+; a comment\r
+Comment: 0,0:00:04.00,0:00:05.00,Default,,0000,0000,0000,,Note";
+
+    fn parse(s: &str) -> Script {
+        match backside_parser::parse_sections(s) {
+            Ok(script) => script,
+            Err(e) => panic!("{}", e),
+        }
+    }
+
+    #[test]
+    fn round_trip() {
+        assert_eq!(to_string(&parse(SCRIPT)), SCRIPT);
+
+        let lf = SCRIPT.replace('\r', "");
+        assert_eq!(to_string(&parse(&lf)), lf);
+
+        let bom = ["\u{feff}", &lf, "\n"].concat();
+        assert_eq!(to_string(&parse(&bom)), bom);
+    }
+
+    #[test]
+    fn mixed_lines() {
+        let src = "[Script Info] ; made by hand \r\nTitle: Test\n\r\n[Events]\t\nFormat: Layer, Start, End, Style, Name, MarginL, MarginR, MarginV, Effect, Text\r\nDialogue: 0,0:00:01.00,0:00:02.00,Default,,0,0,0,,a\n";
+        let mut script = parse(src);
+        assert_eq!(script.layout.mixed, [1, 3, 5]);
+        assert_eq!(script.layout.sections[0].rest, " ; made by hand ");
+        assert_eq!(to_string(&script), src);
+
+        // New lines end the way the first one does
+        let mut event = script.events[0].clone();
+        event.text = String::from("b");
+        script.events.push(event);
+        assert!(to_string(&script).ends_with(",,a\nDialogue: 0,0:00:01.00,0:00:02.00,Default,,0,0,0,,b\r\n"));
+    }
+
+    #[test]
+    fn modified_lines() {
+        let mut script = parse(&SCRIPT.replace('\r', ""));
+        script.styles[0].font_size = 52.0;
+        script.events[1].text.push_str(" here");
+
+        let out = to_string(&script);
+        assert!(out.contains("\nStyle: Default,Arial,52,&H00FFFFFF,&H000000FF,&H00000000,&H00000000,0,0,0,0,100,100,0,0,1,2,2,2,10,10,10,1\n"));
+        assert!(out.ends_with("\n; a comment\nComment: 0,0:00:04.00,0:00:05.00,Default,,0,0,0,,Note here"));
+        assert!(out.contains("\nDialogue: 0,0:00:01.00,0:00:03.50,Default,,0,0,0,,Hello, {\\i1}world{\\i0}!\n"));
+    }
+
+    #[test]
+    fn attachments() {
+        let src = "[Script Info]
+Title: Test
+
+[Fonts]
+fontname: a_0.ttf
+97*D:!
+
+[Graphics]
+filename: logo.png
+;GHI
+
+[Events]
+Format: Layer, Start, End, Style, Name, MarginL, MarginR, MarginV, Effect, Text
+";
+        let mut script = parse(src);
+        assert_eq!(to_string(&script), src);
+        assert_eq!(script.extract_font("a_0.ttf").and_then(|d| d.ok()).as_deref(), Some(&b"abcd"[..]));
+        assert_eq!(script.graphic_names().collect::<Vec<_>>(), ["logo.png"]);
+
+        script.embed_graphic("logo.png", b"abc");
+        script.embed_graphic("b.png", b"abc");
+        assert!(to_string(&script).contains("\n[Graphics]\nfilename: logo.png\n97*D\n\nfilename: b.png\n97*D\n"));
+    }
+
+    #[test]
+    fn binary_attachments() {
+        // Enough bytes that some lines of data start with a `[`
+        let data: Vec<u8> = (0..20000u32).map(|i| (i.wrapping_mul(2654435761) >> 13) as u8).collect();
+        let mut script = parse("[Script Info]\nTitle: Test\n\n[Events]\n");
+        script.embed_font("a_0.ttf", &data);
+        script.embed_graphic("b.png", &data);
+
+        let out = to_string(&script);
+        assert!(out.lines().filter(|l| l.starts_with('[')).count() > 4);
+        let script = parse(&out);
+        assert_eq!(to_string(&script), out);
+        assert_eq!(script.extract_font("a_0.ttf").and_then(|d| d.ok()), Some(data.clone()));
+        assert_eq!(script.extract_graphic("b.png").and_then(|d| d.ok()), Some(data));
+        assert_eq!(script.layout.sections.len(), 4);
+    }
+
+    #[test]
+    fn canonical() {
+        let mut script = Script::default();
+        script.info.set_script_type(ScriptType::V4Plus);
+        script.styles.push(Style::default());
+        script.events.push(Event {
+            start: Timestamp::ZERO,
+            end: Timestamp::from_hms(0, 0, 1, 0),
+            style: String::from("Default"),
+            text: String::from("Hi"),
+            ..Default::default()
+        });
+
+        assert_eq!(to_string(&script), "[Script Info]
+ScriptType: v4.00+
+
+[V4+ Styles]
+Format: Name, Fontname, Fontsize, PrimaryColour, SecondaryColour, OutlineColour, BackColour, Bold, Italic, Underline, StrikeOut, ScaleX, ScaleY, Spacing, Angle, BorderStyle, Outline, Shadow, Alignment, MarginL, MarginR, MarginV, Encoding
+Style: Default,Arial,18,&H00FFFFFF,&H0000FFFF,&H00000000,&H80000000,0,0,0,0,100,100,0,0,1,2,3,2,20,20,20,1
+
+[Events]
+Format: Layer, Start, End, Style, Name, MarginL, MarginR, MarginV, Effect, Text
+Dialogue: 0,0:00:00.00,0:00:01.00,Default,,0,0,0,,Hi
+");
+    }
+
+    #[test]
+    fn repeated_sections() {
+        let src = "[Script Info]
+Title: Test
+
+[Events]
+Format: Layer, Start, End, Style, Name, MarginL, MarginR, MarginV, Effect, Text
+Dialogue: 0,0:00:01.00,0:00:02.00,Default,,0,0,0,,a
+
+[Script Info]
+PlayResX: 640
+
+[Events]
+Format: Layer, Start, End, Style, Name, MarginL, MarginR, MarginV, Effect, Text
+Dialogue: 0,0:00:03.00,0:00:04.00,Default,,0,0,0,,b
+Dialogue: 0,0:00:05.00,0:00:06.00,Default,,0,0,0,,c
+";
+        let mut script = parse(src);
+        assert_eq!(script.events.len(), 3);
+        assert_eq!(to_string(&script), src);
+
+        // New items go to the last section of their kind
+        let mut event = script.events[0].clone();
+        event.text = String::from("d");
+        script.events.push(event);
+        assert!(to_string(&script).ends_with(",,c\nDialogue: 0,0:00:01.00,0:00:02.00,Default,,0,0,0,,d\n"));
+    }
+
+    #[test]
+    fn section_formats() {
+        let src = "[V4+ Styles]
+Format: Name, Fontname, Fontsize
+Style: A,Arial,20
+
+[V4+ Styles]
+Format: Name, Fontname, Fontsize, PrimaryColour, SecondaryColour, OutlineColour, BackColour, Bold, Italic, Underline, StrikeOut, ScaleX, ScaleY, Spacing, Angle, BorderStyle, Outline, Shadow, Alignment, MarginL, MarginR, MarginV, Encoding
+Style: B,Arial,48,&H00FFFFFF,&H000000FF,&H00000000,&H00000000,0,0,0,0,100,100,0,0,1,2,2,2,10,10,10,1
+
+[Events]
+Format: Start, End, Style, Text
+Dialogue: 0:00:01.00,0:00:02.00,Default,a
+
+[Events]
+Dialogue: 0,0:00:03.00,0:00:04.00,Default,,0,0,0,,b
+";
+        let mut script = parse(src);
+        assert_eq!(to_string(&script), src);
+
+        // Each section gets written with its own columns
+        script.styles[0].font_size = 22.0;
+        script.events[0].text = String::from("c");
+        script.events[1].text = String::from("d");
+        let out = to_string(&script);
+        assert!(out.starts_with("[V4+ Styles]\nFormat: Name, Fontname, Fontsize\nStyle: A,Arial,22\n"));
+        assert!(out.contains("\nFormat: Start, End, Style, Text\nDialogue: 0:00:01.00,0:00:02.00,Default,c\n"));
+        assert!(out.ends_with("\n[Events]\nDialogue: 0,0:00:03.00,0:00:04.00,Default,,0,0,0,,d\n"));
+        assert_eq!(parse(&out).styles[0].font_size, 22.0);
+    }
+
     #[test]
-    fn it_works() {
-        let result = add(2, 2);
-        assert_eq!(result, 4);
+    fn script_type() {
+        let src = "[Script Info]
+ScriptType: v4.00
+
+[V4 Styles]
+Format: Name, Fontname, Fontsize, PrimaryColour, SecondaryColour, TertiaryColour, BackColour, Bold, Italic, BorderStyle, Outline, Shadow, Alignment, MarginL, MarginR, MarginV, AlphaLevel, Encoding
+Style: Default,Arial,20,16777215,65535,0,0,0,0,1,2,2,2,10,10,10,0,1
+
+[Events]
+Format: Marked, Start, End, Style, Name, MarginL, MarginR, MarginV, Effect, Text
+Dialogue: Marked=0,0:00:01.00,0:00:02.00,Default,,0000,0000,0000,,a
+";
+        // Formatting an SSA script keeps it SSA
+        let mut script = parse(src);
+        script.layout = Default::default();
+        assert_eq!(to_string(&script), src);
+
+        // and so does setting the type of an ASS one, styles header and all
+        let mut script = parse(&SCRIPT.replace('\r', ""));
+        script.info.set_script_type(ScriptType::V4);
+        let out = to_string(&script);
+        assert!(out.contains("\n[V4 Styles]\nFormat: Name, Fontname, Fontsize, PrimaryColour, SecondaryColour, TertiaryColour, BackColour, Bold, Italic, BorderStyle, Outline, Shadow, Alignment, MarginL, MarginR, MarginV, AlphaLevel, Encoding\nStyle: Default,Arial,48,16777215,255,0,0,0,0,1,2,2,2,10,10,10,0,1\n"));
+        assert!(out.contains("\nFormat: Marked, Start, End, Style, Name, MarginL, MarginR, MarginV, Effect, Text\nDialogue: Marked=0,0:00:01.00,0:00:03.50,Default,,0000,0000,0000,,Hello, {\\i1}world{\\i0}!\n"));
+        assert!(!out.contains("V4+"));
     }
 }
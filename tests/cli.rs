@@ -0,0 +1,264 @@
+#![cfg(all(feature = "oneshot", feature = "pull", feature = "write"))]
+
+use std::io::Write;
+use std::process::{Command, Stdio};
+
+const ASS: &str = "[Script Info]
+ScriptType: v4.00+
+PlayResX: 1280
+PlayResY: 720
+
+[V4+ Styles]
+Format: Name, Fontname, Fontsize, PrimaryColour, SecondaryColour, OutlineColour, BackColour, Bold, Italic, Underline, StrikeOut, ScaleX, ScaleY, Spacing, Angle, BorderStyle, Outline, Shadow, Alignment, MarginL, MarginR, MarginV, Encoding
+Style: Default,Arial,48,&H00FFFFFF,&H000000FF,&H00000000,&H00000000,0,0,0,0,100,100,0,0,1,2,2,2,10,10,10,1
+
+[Events]
+Format: Layer, Start, End, Style, Name, MarginL, MarginR, MarginV, Effect, Text
+Dialogue: 0,0:00:01.00,0:00:02.00,Default,,0,0,0,,{\\pos(640,360)}Hi
+";
+
+const SSA: &str = "[Script Info]
+ScriptType: v4.00
+
+[V4 Styles]
+Format: Name, Fontname, Fontsize, PrimaryColour, SecondaryColour, TertiaryColour, BackColour, Bold, Italic, BorderStyle, Outline, Shadow, Alignment, MarginL, MarginR, MarginV, AlphaLevel, Encoding
+Style: Default,Arial,20,16777215,65535,0,0,0,0,1,2,2,2,10,10,10,0,1
+
+[Events]
+Format: Marked, Start, End, Style, Name, MarginL, MarginR, MarginV, Effect, Text
+Dialogue: Marked=0,0:00:01.00,0:00:02.00,Default,,0000,0000,0000,,Hi
+";
+
+/// Exit code, stdout and stderr of the CLI run with `args` and `input` on stdin
+fn run(args: &[&str], input: &str) -> (i32, String, String) {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_backside-cli"))
+        .args(args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    // Commands that fail early don't read it all
+    let _ = child.stdin.take().unwrap().write_all(input.as_bytes());
+    let out = child.wait_with_output().unwrap();
+    (
+        out.status.code().unwrap_or(-1),
+        String::from_utf8(out.stdout).unwrap(),
+        String::from_utf8(out.stderr).unwrap(),
+    )
+}
+
+#[test]
+fn check() {
+    assert_eq!(run(&["check"], ASS), (0, String::new(), String::new()));
+
+    let bad = ASS
+        .replace("PlayResY: 720", "PlayResY 720")
+        .replace("{\\pos(640,360)}", "{\\pos(640,360)\\xyz1}");
+    let (code, out, _) = run(&["check", "-"], &bad);
+    assert_eq!(code, 1);
+    assert_eq!(out.lines().collect::<Vec<_>>(), [
+        "<stdin>: basic file structure invalid at line 4, column 1 in [Script Info]: `PlayResY 720`",
+        "<stdin>: invalid override code at line 12, column 65 in [Events]: `\\xyz1`",
+    ]);
+}
+
+#[test]
+fn fmt() {
+    let messy = ASS.replace("ScaleX, ScaleY", "ScaleX,ScaleY").replace(",0,0,0,,{", ",0000,0000,0000,,{");
+    assert_eq!(run(&["fmt"], &messy), (0, String::from(ASS), String::new()));
+
+    // SSA stays SSA
+    assert_eq!(run(&["fmt"], SSA), (0, String::from(SSA), String::new()));
+
+    // Comments of [Script Info] are kept
+    let commented = ASS.replace("[Script Info]\n", "[Script Info]\n; Script generated by hand\n");
+    assert_eq!(run(&["fmt"], &commented), (0, commented.clone(), String::new()));
+
+    let (code, _, err) = run(&["fmt"], &ASS.replace("0:00:02.00", "0:00:0x.00"));
+    assert_eq!(code, 1);
+    assert!(err.starts_with("<stdin>: invalid event line at line 12"));
+}
+
+#[test]
+fn shift() {
+    let (code, out, _) = run(&["shift", "-500ms"], ASS);
+    assert_eq!(code, 0);
+    assert!(out.ends_with("\nDialogue: 0,0:00:00.50,0:00:01.50,Default,,0,0,0,,{\\pos(640,360)}Hi\n"));
+
+    let (code, out, _) = run(&["shift", "0:01:00.00"], SSA);
+    assert_eq!(code, 0);
+    assert!(out.ends_with("\nDialogue: Marked=0,0:01:01.00,0:01:02.00,Default,,0000,0000,0000,,Hi\n"));
+
+    assert_eq!(run(&["shift"], ASS).0, 2);
+    assert_eq!(run(&["shift", "soon"], ASS).0, 2);
+}
+
+#[test]
+fn convert() {
+    let (code, out, _) = run(&["convert", "--res", "1920x1080", "--fps", "25:50"], ASS);
+    assert_eq!(code, 0);
+    assert!(out.contains("\nPlayResX: 1920\nPlayResY: 1080\n"));
+    assert!(out.contains("\nStyle: Default,Arial,72,"));
+    assert!(out.ends_with("\nDialogue: 0,0:00:00.50,0:00:01.00,Default,,0,0,0,,{\\pos(960,540)}Hi\n"));
+
+    assert_eq!(run(&["convert", "--ar", "squash"], ASS).0, 2);
+}
+
+/// Empty directory for the files of test `name`
+fn temp_dir(name: &str) -> std::path::PathBuf {
+    let dir = std::env::temp_dir().join(format!("backside-cli-{}-{}", name, std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+#[test]
+fn info() {
+    assert_eq!(run(&["info"], ASS), (0, String::from("\
+Title:        -
+Script type:  v4.00+
+Resolution:   1280x720
+YCbCr matrix: -
+Styles:       1
+Events:       1 (1 dialogue)
+Time span:    0:00:01.00 - 0:00:02.00
+Fonts:        0
+Graphics:     0
+"), String::new()));
+
+    let mut script = backside::Script::parse(ASS).unwrap();
+    script.info.set_title("Test");
+    script.embed_font("a_0.ttf", b"font");
+    script.embed_font("b_0.ttf", b"font");
+    let (code, out, _) = run(&["info"], &script.to_string());
+    assert_eq!(code, 0);
+    assert!(out.starts_with("Title:        Test\n"));
+    assert!(out.ends_with("Fonts:        2 (a_0.ttf, b_0.ttf)\nGraphics:     0\n"));
+}
+
+#[test]
+fn snap() {
+    let dir = temp_dir("snap");
+    let path = |name: &str, data: &str| {
+        let p = dir.join(name);
+        std::fs::write(&p, data).unwrap();
+        p.to_str().unwrap().to_string()
+    };
+    let kf = path("keyframes.txt", "# keyframe format v1\nfps 0\n27\n50\n78\n");
+    let v1 = path("v1.txt", "# timecode format v1\nAssume 25\n");
+    let v2 = path("v2.txt", &(0..200).fold(String::from("# timecode format v2\n"), |s, f| s + &format!("{}\n", f * 40)));
+    let two = ASS.replace("{\\pos(640,360)}Hi\n", "Hi\nDialogue: 0,0:00:02.20,0:00:03.00,Default,,0,0,0,,There\n");
+    let times = |opts: &[&str]| {
+        let (code, out, err) = run(&[&["snap", "--keyframes", &kf], opts].concat(), &two);
+        assert_eq!((code, err.as_str()), (0, ""));
+        out.lines().filter_map(|l| l.strip_prefix("Dialogue: 0,")).map(|l| l[..21].to_string()).collect::<Vec<_>>()
+    };
+
+    // Joined halfway through the gap, then moved to keyframes 27, 50 and 78
+    let snapped = ["0:00:01.06,0:00:01.98", "0:00:01.98,0:00:03.10"];
+    assert_eq!(times(&["--fps", "25"]), snapped);
+    assert_eq!(times(&["--timecodes", &v1]), snapped);
+    assert_eq!(times(&["--timecodes", &v2]), snapped);
+
+    // The start of the second line is 6 frames after the keyframe
+    assert_eq!(times(&["--fps", "25", "--link", "off"]), ["0:00:01.06,0:00:02.00", "0:00:02.20,0:00:03.10"]);
+    assert_eq!(times(&["--fps", "25", "--link-bias", "0"]), ["0:00:01.06,0:00:02.00", "0:00:02.00,0:00:03.10"]);
+    assert_eq!(times(&["--fps", "25", "--starts-before", "1", "--ends-after", "0"]), [
+        "0:00:01.00,0:00:02.10",
+        "0:00:01.98,0:00:03.10",
+    ]);
+
+    let code = |args: &[&str]| run(args, &two).0;
+    assert_eq!(code(&["snap", "--fps", "25"]), 2);
+    assert_eq!(code(&["snap", "--keyframes", &kf]), 2);
+    assert_eq!(code(&["snap", "--keyframes", &kf, "--fps", "0"]), 2);
+    assert_eq!(code(&["snap", "--keyframes", &kf, "--fps", "25", "--link", "-1s"]), 2);
+    assert_eq!(code(&["snap", "--keyframes", &kf, "--fps", "25", "--link-bias", "2"]), 2);
+    assert_eq!(code(&["snap", "--keyframes", &kf, "--fps", "25", "--ends-before", "-1"]), 2);
+    assert_eq!(code(&["snap", "--keyframes", &v1, "--fps", "25"]), 1);
+    assert_eq!(code(&["snap", "--keyframes", &kf, "--timecodes", &dir.join("none").to_string_lossy()]), 3);
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn extract_fonts() {
+    let dir = temp_dir("extract-fonts");
+    let out = dir.join("out");
+    std::fs::create_dir(&out).unwrap();
+    let mut script = backside::Script::parse(ASS).unwrap();
+    script.embed_font("a_0.ttf", b"first font");
+    // Only the file name is used
+    script.embed_font("../b_0.ttf", b"second font");
+
+    let (code, _, err) = run(&["extract-fonts", "-o", out.to_str().unwrap()], &script.to_string());
+    assert_eq!(code, 0);
+    assert_eq!(err.lines().count(), 2);
+    assert_eq!(std::fs::read(out.join("a_0.ttf")).unwrap(), b"first font");
+    assert_eq!(std::fs::read(out.join("b_0.ttf")).unwrap(), b"second font");
+    assert!(!dir.join("b_0.ttf").exists());
+
+    // Nowhere to write to
+    assert_eq!(run(&["extract-fonts", "-o", dir.join("none").to_str().unwrap()], &script.to_string()).0, 3);
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[cfg(feature = "render")]
+#[test]
+fn render() {
+    let dir = temp_dir("render");
+    let pam = dir.join("frame.pam");
+    let fixture = concat!(env!("CARGO_MANIFEST_DIR"), "/src/renderer/testdata/fixture-sans.ttf");
+    let frame = |time: &str, opts: &[&str]| {
+        let args = [&["render", "--time", time, "--size", "64x36", "-o", pam.to_str().unwrap()], opts].concat();
+        let (code, _, err) = run(&args, ASS);
+        (code, err, std::fs::read(&pam).unwrap_or_default())
+    };
+
+    let (code, err, data) = frame("1.5s", &["--font", fixture]);
+    assert_eq!((code, err.as_str()), (0, "backside-cli: font `Arial` not found, used `Fixture Sans`\n"));
+    let header = "P7\nWIDTH 64\nHEIGHT 36\nDEPTH 4\nMAXVAL 255\nTUPLTYPE RGB_ALPHA\nENDHDR\n";
+    assert!(data.starts_with(header.as_bytes()));
+    let pixels = &data[header.len()..];
+    assert_eq!(pixels.len(), 64 * 36 * 4);
+    assert!(pixels.chunks(4).any(|p| p[3] > 0));
+
+    // Nothing on screen
+    let (code, _, data) = frame("0:00:05.00", &["--font", fixture, "--shaping", "simple"]);
+    assert_eq!(code, 0);
+    assert!(data[header.len()..].iter().all(|&b| b == 0));
+
+    assert_eq!(frame("1.5s", &["--shaping", "fancy"]).0, 2);
+    assert_eq!(frame("1.5s", &["--font", file!()]).0, 1);
+    assert_eq!(run(&["render"], ASS).0, 2);
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[cfg(feature = "system-fonts")]
+#[test]
+fn fonts_dir() {
+    let dir = temp_dir("fonts-dir");
+    let pam = dir.join("frame.pam");
+    let testdata = concat!(env!("CARGO_MANIFEST_DIR"), "/src/renderer/testdata");
+    let (code, _, err) = run(&["render", "--time", "1.5s", "--size", "64x36", "--fonts-dir", testdata, "-o", pam.to_str().unwrap()], ASS);
+    assert_eq!(code, 0);
+    assert!(err.starts_with("backside-cli: font `Arial` not found, used `Fixture Sans"));
+    assert_eq!(run(&["render", "--time", "1.5s", "--fonts-dir", &dir.join("none").to_string_lossy()], ASS).0, 3);
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[cfg(all(feature = "render", not(feature = "system-fonts")))]
+#[test]
+fn fonts_dir() {
+    let (code, _, err) = run(&["render", "--time", "1.5s", "--fonts-dir", "."], ASS);
+    assert_eq!(code, 2);
+    assert!(err.contains("it needs the `system-fonts` feature"));
+}
+
+#[cfg(not(feature = "render"))]
+#[test]
+fn render() {
+    let (code, _, err) = run(&["render", "--time", "1.5s"], ASS);
+    assert_eq!(code, 2);
+    assert!(err.contains("it needs the `render` feature"));
+}